@@ -0,0 +1,427 @@
+use crate::collector::{common, get_services};
+use crate::config::{AlertCondition, AlertConfig, AlertMetric};
+use crate::fingerprint;
+use crate::models::{Alert, AlertState, LoadAverage};
+use chrono::Utc;
+use log::{debug, info, warn};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use sysinfo::{Disks, System};
+
+/// Values read by one sampler tick
+#[derive(Debug, Clone, Default)]
+struct Sample {
+    memory_available_percent: Option<f64>,
+    cpu_usage_percent: f64,
+    load_average: LoadAverage,
+    /// Mount point and free space in percent
+    disks: Vec<(String, f64)>,
+    /// Only sampled when a `service_down` rule exists
+    services: Option<Vec<String>>,
+}
+
+/// Cheap periodic reads, unlike a full collection
+struct Sampler {
+    system: System,
+}
+
+impl Sampler {
+    fn new() -> Self {
+        let mut system = System::new();
+        // CPU usage is a difference between two refreshes
+        system.refresh_cpu_usage();
+        Sampler { system }
+    }
+
+    fn sample(&mut self, with_services: bool) -> Sample {
+        self.system.refresh_memory();
+        self.system.refresh_cpu_usage();
+
+        let total = self.system.total_memory();
+        let load = System::load_average();
+        Sample {
+            memory_available_percent: (total > 0)
+                .then(|| common::get_available_memory(&self.system) as f64 * 100.0 / total as f64),
+            cpu_usage_percent: self.system.global_cpu_info().cpu_usage() as f64,
+            load_average: LoadAverage { one: load.one, five: load.five, fifteen: load.fifteen },
+            disks: Disks::new_with_refreshed_list()
+                .iter()
+                .filter(|d| d.total_space() > 0)
+                .map(|d| {
+                    let free = d.available_space() as f64 * 100.0 / d.total_space() as f64;
+                    (d.mount_point().to_string_lossy().to_string(), free)
+                })
+                .collect(),
+            services: with_services.then(get_services),
+        }
+    }
+}
+
+/// Per rule and subject (a rule without `mount` watches every disk)
+#[derive(Debug, Default)]
+struct RuleState {
+    firing: bool,
+    /// Whether the current firing was notified (so its resolution is too)
+    notified: bool,
+    last_notified: Option<Instant>,
+    suppressed: u32,
+    /// Services only alert once they have been seen running
+    seen_running: bool,
+}
+
+/// Checks the `[alerts]` rules every `sample_interval_seconds`
+///
+/// A rule fires when its trigger is crossed and resolves only once the value
+/// is back past `clear` (hysteresis). Each rule notifies at most once per
+/// `cooldown_seconds`; dropped notifications are counted in `suppressed` and
+/// a rule still firing after the cooldown is notified again.
+pub struct Alerter {
+    config: AlertConfig,
+    sampler: Sampler,
+    states: HashMap<(usize, String), RuleState>,
+    last_sample: Option<Instant>,
+    agent_id: String,
+    device_fingerprint: String,
+    hostname: String,
+}
+
+impl Alerter {
+    pub fn new(config: &AlertConfig, agent_id: &str) -> Result<Self, String> {
+        validate(config)?;
+
+        Ok(Alerter {
+            config: config.clone(),
+            sampler: Sampler::new(),
+            states: HashMap::new(),
+            last_sample: None,
+            agent_id: agent_id.to_string(),
+            device_fingerprint: fingerprint::generate_fingerprint().unwrap_or_default(),
+            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+        })
+    }
+
+    /// Sample if due and return the notifications to send now
+    pub fn poll(&mut self) -> Vec<Alert> {
+        let now = Instant::now();
+        let interval = Duration::from_secs(self.config.sample_interval_seconds.max(1));
+        if self.last_sample.is_some_and(|last| now.duration_since(last) < interval) {
+            return Vec::new();
+        }
+        self.last_sample = Some(now);
+
+        let with_services = self.config.rules.iter()
+            .any(|rule| matches!(rule.condition, AlertCondition::ServiceDown { .. }));
+        let sample = self.sampler.sample(with_services);
+        self.evaluate(&sample, now)
+    }
+
+    fn evaluate(&mut self, sample: &Sample, now: Instant) -> Vec<Alert> {
+        let cooldown = Duration::from_secs(self.config.cooldown_seconds);
+        let mut alerts = Vec::new();
+
+        for (index, rule) in self.config.rules.iter().enumerate() {
+            for observation in observe(&rule.condition, sample) {
+                let state = self.states.entry((index, observation.subject.clone())).or_default();
+
+                let is_service = observation.value.is_none();
+                if is_service && observation.status == Status::Cleared {
+                    state.seen_running = true;
+                }
+                let transition = match observation.status {
+                    Status::Triggered if !state.firing && (!is_service || state.seen_running) => {
+                        Some(AlertState::Firing)
+                    }
+                    Status::Cleared if state.firing => Some(AlertState::Resolved),
+                    _ => None,
+                };
+                let firing_again = state.firing && !state.notified
+                    && state.last_notified.is_none_or(|last| now.duration_since(last) >= cooldown);
+
+                let notify = match transition {
+                    Some(AlertState::Firing) => {
+                        state.firing = true;
+                        state.notified = false;
+                        if state.last_notified.is_none_or(|last| now.duration_since(last) >= cooldown) {
+                            true
+                        } else {
+                            debug!("Alert '{}' rate limited", rule.name);
+                            state.suppressed += 1;
+                            false
+                        }
+                    }
+                    // Only resolve what was announced
+                    Some(AlertState::Resolved) => {
+                        state.firing = false;
+                        std::mem::replace(&mut state.notified, false)
+                    }
+                    None => firing_again,
+                };
+                if !notify {
+                    continue;
+                }
+
+                let alert_state = if state.firing { AlertState::Firing } else { AlertState::Resolved };
+                if alert_state == AlertState::Firing {
+                    state.notified = true;
+                    state.last_notified = Some(now);
+                }
+                alerts.push(Alert {
+                    rule: rule.name.clone(),
+                    state: alert_state,
+                    severity: rule.severity.clone(),
+                    subject: observation.subject.clone(),
+                    value: observation.value,
+                    threshold: observation.threshold,
+                    message: observation.message(alert_state),
+                    detected_at: Utc::now(),
+                    suppressed: std::mem::take(&mut state.suppressed),
+                });
+            }
+        }
+        alerts
+    }
+
+    /// Alert payload sent to the backend and the webhook
+    pub fn payload(&self, alert: &Alert) -> Value {
+        json!({
+            "payload_type": "alert",
+            "agent_id": self.agent_id,
+            "device_fingerprint": self.device_fingerprint,
+            "hostname": self.hostname,
+            "alert": alert,
+        })
+    }
+
+    /// POST an alert payload to the configured webhook
+    pub fn post_webhook(&self, url: &str, payload: &Value) -> Result<(), String> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(self.config.webhook_timeout_seconds))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        let response = client.post(url)
+            .json(payload)
+            .send()
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+
+        if response.status().is_success() {
+            info!("✓ Alert sent to webhook");
+            Ok(())
+        } else {
+            Err(format!("Webhook returned {}", response.status()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Triggered,
+    Cleared,
+    /// Between trigger and clear: keep the current state
+    Unchanged,
+}
+
+struct Observation {
+    subject: String,
+    value: Option<f64>,
+    threshold: Option<f64>,
+    status: Status,
+}
+
+impl Observation {
+    fn message(&self, state: AlertState) -> String {
+        match (self.value, self.threshold, state) {
+            (Some(value), Some(threshold), AlertState::Firing) => {
+                format!("{} is {:.1} (threshold {})", self.subject, value, threshold)
+            }
+            (Some(value), _, AlertState::Resolved) => format!("{} is back to {:.1}", self.subject, value),
+            (None, _, AlertState::Firing) => format!("{} is not running", self.subject),
+            (None, _, AlertState::Resolved) => format!("{} is running again", self.subject),
+            _ => self.subject.clone(),
+        }
+    }
+}
+
+fn metric_name(metric: AlertMetric) -> &'static str {
+    match metric {
+        AlertMetric::MemoryAvailablePercent => "memory_available_percent",
+        AlertMetric::DiskFreePercent => "disk_free_percent",
+        AlertMetric::CpuUsagePercent => "cpu_usage_percent",
+        AlertMetric::Load1m => "load_1m",
+        AlertMetric::Load5m => "load_5m",
+        AlertMetric::Load15m => "load_15m",
+    }
+}
+
+/// Current status of a rule for every subject it watches
+fn observe(condition: &AlertCondition, sample: &Sample) -> Vec<Observation> {
+    match condition {
+        AlertCondition::Threshold { metric, mount, above, below, clear } => {
+            let values: Vec<(String, f64)> = match metric {
+                AlertMetric::DiskFreePercent => sample.disks.iter()
+                    .filter(|(mount_point, _)| mount.as_ref().is_none_or(|m| m == mount_point))
+                    .map(|(mount_point, free)| (format!("disk_free_percent:{}", mount_point), *free))
+                    .collect(),
+                AlertMetric::MemoryAvailablePercent => sample.memory_available_percent
+                    .map(|value| vec![(metric_name(*metric).to_string(), value)])
+                    .unwrap_or_default(),
+                AlertMetric::CpuUsagePercent => vec![(metric_name(*metric).to_string(), sample.cpu_usage_percent)],
+                AlertMetric::Load1m => vec![(metric_name(*metric).to_string(), sample.load_average.one)],
+                AlertMetric::Load5m => vec![(metric_name(*metric).to_string(), sample.load_average.five)],
+                AlertMetric::Load15m => vec![(metric_name(*metric).to_string(), sample.load_average.fifteen)],
+            };
+
+            values.into_iter()
+                .map(|(subject, value)| {
+                    let (threshold, status) = match (above, below) {
+                        (Some(limit), _) => (*limit, if value > *limit {
+                            Status::Triggered
+                        } else if value <= clear.unwrap_or(*limit) {
+                            Status::Cleared
+                        } else {
+                            Status::Unchanged
+                        }),
+                        (None, Some(limit)) => (*limit, if value < *limit {
+                            Status::Triggered
+                        } else if value >= clear.unwrap_or(*limit) {
+                            Status::Cleared
+                        } else {
+                            Status::Unchanged
+                        }),
+                        (None, None) => unreachable!("validated when the alerter is created"),
+                    };
+                    Observation { subject, value: Some(value), threshold: Some(threshold), status }
+                })
+                .collect()
+        }
+        AlertCondition::ServiceDown { service } => {
+            let Some(services) = &sample.services else {
+                return Vec::new();
+            };
+            let running = services.iter().any(|s| s == service);
+            vec![Observation {
+                subject: service.clone(),
+                value: None,
+                threshold: None,
+                status: if running { Status::Cleared } else { Status::Triggered },
+            }]
+        }
+    }
+}
+
+fn validate(config: &AlertConfig) -> Result<(), String> {
+    for rule in &config.rules {
+        if let AlertCondition::Threshold { metric, mount, above, below, clear } = &rule.condition {
+            match (above, below, clear) {
+                (Some(_), Some(_), _) | (None, None, _) => {
+                    return Err(format!("Alert '{}' needs exactly one of 'above' or 'below'", rule.name));
+                }
+                (Some(limit), None, Some(clear)) if clear > limit => {
+                    return Err(format!("Alert '{}': 'clear' must not be above 'above'", rule.name));
+                }
+                (None, Some(limit), Some(clear)) if clear < limit => {
+                    return Err(format!("Alert '{}': 'clear' must not be below 'below'", rule.name));
+                }
+                _ => {}
+            }
+            if mount.is_some() && *metric != AlertMetric::DiskFreePercent {
+                warn!("Alert '{}': 'mount' only applies to disk_free_percent", rule.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertRule;
+
+    fn alerter(rules: Vec<AlertRule>) -> Alerter {
+        let config = AlertConfig { enabled: true, cooldown_seconds: 60, rules, ..AlertConfig::default() };
+        Alerter::new(&config, "agent-test").unwrap()
+    }
+
+    fn rule(name: &str, condition: AlertCondition) -> AlertRule {
+        AlertRule { name: name.to_string(), severity: "critical".to_string(), condition }
+    }
+
+    fn memory(percent: f64) -> Sample {
+        Sample { memory_available_percent: Some(percent), ..Sample::default() }
+    }
+
+    #[test]
+    fn test_hysteresis_and_rate_limiting() {
+        let mut alerter = alerter(vec![rule("low-memory", AlertCondition::Threshold {
+            metric: AlertMetric::MemoryAvailablePercent,
+            mount: None,
+            above: None,
+            below: Some(10.0),
+            clear: Some(15.0),
+        })]);
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+
+        let fired = alerter.evaluate(&memory(5.0), at(0));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].state, AlertState::Firing);
+        assert_eq!(fired[0].threshold, Some(10.0));
+
+        assert!(alerter.evaluate(&memory(12.0), at(15)).is_empty(), "Between trigger and clear nothing changes");
+        let resolved = alerter.evaluate(&memory(20.0), at(30));
+        assert_eq!(resolved[0].state, AlertState::Resolved);
+
+        // Flapping within the cooldown is suppressed, then reported once it elapses
+        assert!(alerter.evaluate(&memory(5.0), at(45)).is_empty());
+        assert!(alerter.evaluate(&memory(5.0), at(50)).is_empty());
+        let again = alerter.evaluate(&memory(5.0), at(61));
+        assert_eq!(again.len(), 1);
+        assert_eq!(again[0].state, AlertState::Firing);
+        assert_eq!(again[0].suppressed, 1);
+
+        let payload = alerter.payload(&again[0]);
+        assert_eq!(payload["payload_type"], "alert");
+        assert_eq!(payload["alert"]["state"], "firing");
+        assert_eq!(payload["alert"]["rule"], "low-memory");
+    }
+
+    #[test]
+    fn test_service_down_and_disk_rules() {
+        let mut alerter = alerter(vec![
+            rule("sshd-down", AlertCondition::ServiceDown { service: "sshd".to_string() }),
+            rule("disk", AlertCondition::Threshold {
+                metric: AlertMetric::DiskFreePercent,
+                mount: None,
+                above: None,
+                below: Some(5.0),
+                clear: None,
+            }),
+        ]);
+        let now = Instant::now();
+        let sample = |services: &[&str], disks: &[(&str, f64)]| Sample {
+            services: Some(services.iter().map(|s| s.to_string()).collect()),
+            disks: disks.iter().map(|(m, f)| (m.to_string(), *f)).collect(),
+            ..Sample::default()
+        };
+
+        let alerts = alerter.evaluate(&sample(&[], &[("/", 50.0), ("/data", 2.0)]), now);
+        assert_eq!(alerts.len(), 1, "A service never seen running does not fire: {:?}", alerts);
+        assert_eq!(alerts[0].subject, "disk_free_percent:/data");
+
+        let alerts = alerter.evaluate(&sample(&["sshd"], &[("/", 50.0), ("/data", 2.0)]), now);
+        assert!(alerts.is_empty(), "Nothing changed: {:?}", alerts);
+
+        let alerts = alerter.evaluate(&sample(&[], &[("/", 50.0), ("/data", 2.0)]), now);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].message, "sshd is not running");
+
+        assert!(Alerter::new(&AlertConfig {
+            rules: vec![rule("bad", AlertCondition::Threshold {
+                metric: AlertMetric::Load1m, mount: None, above: Some(4.0), below: None, clear: Some(5.0),
+            })],
+            ..AlertConfig::default()
+        }, "agent-test").is_err(), "'clear' on the wrong side of the trigger is rejected");
+    }
+}
@@ -0,0 +1,259 @@
+use crate::models::{Change, ChangeEvent, Package, SystemInfo};
+use log::{info, debug, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Compares each collection with the previous one and attaches change events
+///
+/// The previous snapshot is persisted so a restart does not lose the baseline.
+pub struct ChangeTracker {
+    state_path: PathBuf,
+    previous: Option<SystemInfo>,
+}
+
+impl ChangeTracker {
+    pub fn load(state_path: &str) -> Self {
+        let previous = match read_state(Path::new(state_path)) {
+            Ok(previous) => previous,
+            Err(e) => {
+                warn!("Ignoring previous snapshot {}: {}", state_path, e);
+                None
+            }
+        };
+
+        ChangeTracker {
+            state_path: PathBuf::from(state_path),
+            previous,
+        }
+    }
+
+    /// Fill `info.events` with the changes since the previous collection
+    pub fn track(&mut self, info: &mut SystemInfo) {
+        info.events = match &self.previous {
+            Some(previous) => diff(previous, info),
+            None => Vec::new(),
+        };
+
+        if !info.events.is_empty() {
+            info!("Detected {} change(s) since the previous collection", info.events.len());
+        }
+
+        let mut baseline = info.clone();
+        baseline.events.clear();
+        if let Err(e) = self.save_state(&baseline) {
+            warn!("Failed to persist snapshot for change tracking: {}", e);
+        }
+        self.previous = Some(baseline);
+    }
+
+    fn save_state(&self, snapshot: &SystemInfo) -> Result<(), String> {
+        if let Some(parent) = self.state_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create state directory: {}", e))?;
+        }
+
+        let json = serde_json::to_vec(snapshot)
+            .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
+        let tmp_path = self.state_path.with_extension("tmp");
+        fs::write(&tmp_path, json)
+            .map_err(|e| format!("Failed to write snapshot: {}", e))?;
+        fs::rename(&tmp_path, &self.state_path)
+            .map_err(|e| format!("Failed to commit snapshot: {}", e))
+    }
+}
+
+fn read_state(path: &Path) -> Result<Option<SystemInfo>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse file: {}", e))
+}
+
+/// Typed changes from `previous` to `current`, stamped with `current.collected_at`
+///
+/// Sections listed in `not_collected` on either side are skipped rather than
+/// reported as everything being removed or added; an emptied list is a change.
+pub fn diff(previous: &SystemInfo, current: &SystemInfo) -> Vec<ChangeEvent> {
+    let mut changes = Vec::new();
+    let collected = |section: &str| {
+        !previous.not_collected.iter().chain(&current.not_collected).any(|s| s == section)
+    };
+
+    if collected("packages") {
+        diff_packages(&previous.packages, &current.packages, &mut changes);
+    }
+
+    if collected("services") {
+        let old: BTreeSet<&String> = previous.services.iter().collect();
+        let new: BTreeSet<&String> = current.services.iter().collect();
+        for name in new.difference(&old) {
+            changes.push(Change::ServiceStarted { name: name.to_string() });
+        }
+        for name in old.difference(&new) {
+            changes.push(Change::ServiceStopped { name: name.to_string() });
+        }
+    }
+
+    let interfaces: BTreeSet<&String> = previous.ip_addresses.keys().chain(current.ip_addresses.keys()).collect();
+    for interface in interfaces {
+        let old: BTreeSet<&String> = previous.ip_addresses.get(interface).into_iter().flatten().collect();
+        let new: BTreeSet<&String> = current.ip_addresses.get(interface).into_iter().flatten().collect();
+        if old != new {
+            changes.push(Change::IpChanged {
+                interface: interface.clone(),
+                added: new.difference(&old).map(|s| s.to_string()).collect(),
+                removed: old.difference(&new).map(|s| s.to_string()).collect(),
+            });
+        }
+    }
+
+    // Total memory only; available memory changes on every collection
+    if previous.memory_total != current.memory_total && previous.memory_total > 0 {
+        changes.push(Change::MemoryChanged {
+            from_bytes: previous.memory_total,
+            to_bytes: current.memory_total,
+        });
+    }
+
+    debug!("Snapshot diff produced {} change(s)", changes.len());
+    changes
+        .into_iter()
+        .map(|change| ChangeEvent { detected_at: current.collected_at, change })
+        .collect()
+}
+
+fn diff_packages(previous: &[Package], current: &[Package], changes: &mut Vec<Change>) {
+    // Multi-arch systems can list the same name once per architecture
+    let key = |p: &Package| (p.name.clone(), p.arch.clone());
+    let old: BTreeMap<_, &Package> = previous.iter().map(|p| (key(p), p)).collect();
+    let new: BTreeMap<_, &Package> = current.iter().map(|p| (key(p), p)).collect();
+
+    for (k, package) in &new {
+        match old.get(k) {
+            None => changes.push(Change::PackageInstalled {
+                name: package.name.clone(),
+                version: package.version.clone(),
+            }),
+            Some(before) if before.version != package.version => changes.push(Change::PackageUpgraded {
+                name: package.name.clone(),
+                from_version: before.version.clone(),
+                to_version: package.version.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (k, package) in &old {
+        if !new.contains_key(k) {
+            changes.push(Change::PackageRemoved {
+                name: package.name.clone(),
+                version: package.version.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+
+    fn snapshot(packages: Vec<Package>, services: &[&str], address: &str, memory_total: u64) -> SystemInfo {
+        SystemInfo {
+            collected_at: Utc::now(),
+            memory_total,
+            ip_addresses: HashMap::from([("eth0".to_string(), vec![address.to_string()])]),
+            services: services.iter().map(|s| s.to_string()).collect(),
+            packages,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_emits_typed_events() {
+        let previous = snapshot(
+            vec![
+                Package::new("openssl", "3.0.2-1", "deb"),
+                Package::new("vim", "9.0", "deb"),
+                Package::new("telnet", "0.17", "deb"),
+            ],
+            &["sshd", "cron"],
+            "10.0.0.2",
+            8192,
+        );
+        let current = snapshot(
+            vec![
+                Package::new("openssl", "3.0.2-2", "deb"),
+                Package::new("vim", "9.0", "deb"),
+                Package::new("curl", "8.5", "deb"),
+            ],
+            &["sshd", "nginx"],
+            "10.0.0.3",
+            16384,
+        );
+
+        let events = diff(&previous, &current);
+        let changes: Vec<&Change> = events.iter().map(|e| &e.change).collect();
+        assert!(events.iter().all(|e| e.detected_at == current.collected_at));
+        assert_eq!(changes, vec![
+            &Change::PackageInstalled { name: "curl".to_string(), version: "8.5".to_string() },
+            &Change::PackageUpgraded {
+                name: "openssl".to_string(),
+                from_version: "3.0.2-1".to_string(),
+                to_version: "3.0.2-2".to_string(),
+            },
+            &Change::PackageRemoved { name: "telnet".to_string(), version: "0.17".to_string() },
+            &Change::ServiceStarted { name: "nginx".to_string() },
+            &Change::ServiceStopped { name: "cron".to_string() },
+            &Change::IpChanged {
+                interface: "eth0".to_string(),
+                added: vec!["10.0.0.3".to_string()],
+                removed: vec!["10.0.0.2".to_string()],
+            },
+            &Change::MemoryChanged { from_bytes: 8192, to_bytes: 16384 },
+        ]);
+
+        let json = serde_json::to_value(&events[1]).unwrap();
+        assert_eq!(json["type"], "package_upgraded");
+        assert_eq!(json["to_version"], "3.0.2-2");
+
+        // Sections that were not collected do not produce mass removals
+        let mut without_software = snapshot(vec![], &["sshd", "cron"], "10.0.0.2", 8192);
+        without_software.not_collected = vec!["installed_software".to_string(), "packages".to_string()];
+        assert!(diff(&previous, &without_software).is_empty());
+        assert!(diff(&without_software, &previous).is_empty(), "Re-enabling a collector is not a mass install");
+    }
+
+    #[test]
+    fn test_emptied_sections_are_changes() {
+        let previous = snapshot(vec![Package::new("vim", "9.0", "deb")], &["nginx"], "10.0.0.2", 8192);
+        let current = snapshot(vec![], &[], "10.0.0.2", 8192);
+
+        let changes: Vec<Change> = diff(&previous, &current).into_iter().map(|e| e.change).collect();
+        assert_eq!(changes, vec![
+            Change::PackageRemoved { name: "vim".to_string(), version: "9.0".to_string() },
+            Change::ServiceStopped { name: "nginx".to_string() },
+        ], "The last service stopping or package going away is still reported");
+    }
+
+    #[test]
+    fn test_tracker_persists_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("last_snapshot.json");
+        let path = path.to_str().unwrap();
+
+        let mut first = snapshot(vec![Package::new("vim", "9.0", "deb")], &["sshd"], "10.0.0.2", 8192);
+        ChangeTracker::load(path).track(&mut first);
+        assert!(first.events.is_empty(), "The first collection has nothing to compare with");
+
+        // A new tracker (agent restart) still compares with the stored snapshot
+        let mut second = snapshot(vec![Package::new("vim", "9.1", "deb")], &["sshd"], "10.0.0.2", 8192);
+        ChangeTracker::load(path).track(&mut second);
+        assert_eq!(second.events.len(), 1);
+    }
+}
@@ -0,0 +1,691 @@
+use crate::config::CisConfig;
+use crate::fingerprint;
+use crate::models::{CisReport, CisResult, CisStatus};
+use chrono::{DateTime, Utc};
+use log::{info, debug, warn};
+use regex::Regex;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use sysinfo::System;
+
+const BENCHMARK: &str = "CIS Linux Level 1";
+
+/// Evidence lists are cut after this many entries
+const MAX_LISTED: usize = 10;
+
+/// Nesting limit for sshd_config `Include` (sshd's own limit is 16)
+const MAX_SSHD_INCLUDE_DEPTH: usize = 16;
+
+type Outcome = (CisStatus, String);
+
+/// One built-in check
+struct Check {
+    id: String,
+    section: &'static str,
+    title: String,
+    rationale: &'static str,
+    remediation: String,
+    run: Box<dyn Fn(&Host) -> Outcome>,
+}
+
+/// Read-only view of the host, rooted at `/` (or a test directory)
+struct Host {
+    root: PathBuf,
+    world_writable_paths: Vec<String>,
+    world_writable_max_depth: usize,
+    world_writable_max_entries: usize,
+}
+
+impl Host {
+    fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.path(path)).ok()
+    }
+
+    fn sysctl(&self, key: &str) -> Option<String> {
+        self.read(&format!("/proc/sys/{}", key.replace('.', "/")))
+            .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    /// Mount options of a mount point, None if it isn't a separate mount
+    fn mount_options(&self, mount_point: &str) -> Option<Vec<String>> {
+        // The last matching line is the mount on top
+        self.read("/proc/mounts")?
+            .lines()
+            .rev()
+            .find_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                (fields.len() >= 4 && fields[1] == mount_point)
+                    .then(|| fields[3].split(',').map(str::to_string).collect())
+            })
+    }
+
+    /// sshd_config with `Include` directives expanded in place, as sshd reads it
+    fn sshd_config(&self) -> Option<String> {
+        let main = self.read("/etc/ssh/sshd_config")?;
+        let mut expanded = String::new();
+        self.expand_sshd_includes(&main, 0, &mut expanded);
+        Some(expanded)
+    }
+
+    fn expand_sshd_includes(&self, contents: &str, depth: usize, expanded: &mut String) {
+        for line in contents.lines() {
+            let mut words = line.split_whitespace();
+            let is_include = words.next().is_some_and(|key| key.eq_ignore_ascii_case("include"));
+            if !is_include {
+                expanded.push_str(line);
+                expanded.push('\n');
+                continue;
+            }
+            if depth >= MAX_SSHD_INCLUDE_DEPTH {
+                warn!("sshd_config Include nesting deeper than {}, ignoring: {}", MAX_SSHD_INCLUDE_DEPTH, line.trim());
+                continue;
+            }
+            for pattern in words {
+                for path in self.sshd_include_paths(pattern) {
+                    if let Some(included) = self.read(&path) {
+                        self.expand_sshd_includes(&included, depth + 1, expanded);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Files an `Include` pattern names, sorted like glob(3); relative to /etc/ssh
+    ///
+    /// Wildcards are supported in the file name only.
+    fn sshd_include_paths(&self, pattern: &str) -> Vec<String> {
+        let pattern = if pattern.starts_with('/') {
+            pattern.to_string()
+        } else {
+            format!("/etc/ssh/{}", pattern)
+        };
+        let (dir, name) = pattern.rsplit_once('/').unwrap_or(("", &pattern));
+        if !name.contains(['*', '?']) {
+            return vec![pattern.clone()];
+        }
+
+        let glob = format!("^{}$", regex::escape(name).replace(r"\*", ".*").replace(r"\?", "."));
+        let Ok(glob) = Regex::new(&glob) else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir(self.path(dir)) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries.flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|file| !file.starts_with('.') && glob.is_match(file))
+            .collect();
+        names.sort();
+        names.into_iter().map(|file| format!("{}/{}", dir, file)).collect()
+    }
+
+    /// First value of a keyword in sshd_config (sshd uses the first occurrence)
+    fn sshd_option(&self, config: &str, keyword: &str) -> Option<String> {
+        config.lines()
+            .map(str::trim)
+            .take_while(|line| !line.to_lowercase().starts_with("match "))
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| {
+                let (key, value) = line.split_once(|c: char| c.is_whitespace() || c == '=')?;
+                key.eq_ignore_ascii_case(keyword).then(|| value.trim_start_matches(['=', ' ', '\t']).trim().to_string())
+            })
+    }
+
+    /// Value of a `KEY value` line (login.defs) or `key = value` line (pwquality.conf)
+    fn setting(&self, path: &str, key: &str) -> Option<String> {
+        // Later lines override earlier ones
+        self.read(path)?
+            .lines()
+            .rev()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| {
+                let (name, value) = line.split_once(|c: char| c.is_whitespace() || c == '=')?;
+                (name == key).then(|| value.trim_start_matches(['=', ' ', '\t']).trim().to_string())
+            })
+    }
+
+    /// Whether a process with this name is running
+    fn process_running(&self, name: &str) -> bool {
+        let Ok(entries) = fs::read_dir(self.path("/proc")) else {
+            return false;
+        };
+        entries.flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+            .any(|entry| fs::read_to_string(entry.path().join("comm")).is_ok_and(|comm| comm.trim() == name))
+    }
+
+    /// World-writable regular files below the configured paths
+    ///
+    /// The walk stops descending below `world_writable_max_depth` and stops
+    /// altogether after `world_writable_max_entries` entries; the flag tells
+    /// whether a limit cut the scan short.
+    fn world_writable_files(&self) -> (Vec<PathBuf>, bool) {
+        let mut found = Vec::new();
+        let mut truncated = false;
+        let mut examined = 0;
+        let mut pending: Vec<(PathBuf, usize)> = self.world_writable_paths.iter().map(|p| (self.path(p), 0)).collect();
+        'walk: while let Some((dir, depth)) = pending.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                examined += 1;
+                if examined > self.world_writable_max_entries {
+                    truncated = true;
+                    break 'walk;
+                }
+                // symlink_metadata: links are always 0777 and are not followed
+                let Ok(metadata) = entry.path().symlink_metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    if depth < self.world_writable_max_depth {
+                        pending.push((entry.path(), depth + 1));
+                    } else {
+                        truncated = true;
+                    }
+                } else if metadata.is_file() && mode_and_owner(&metadata).0 & 0o002 != 0 {
+                    found.push(entry.path());
+                }
+            }
+        }
+        found.sort();
+        (found, truncated)
+    }
+}
+
+/// Permission bits and owner uid (the checks only run on Linux)
+#[cfg(unix)]
+fn mode_and_owner(metadata: &Metadata) -> (u32, u32) {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    (metadata.permissions().mode(), metadata.uid())
+}
+
+#[cfg(not(unix))]
+fn mode_and_owner(_metadata: &Metadata) -> (u32, u32) {
+    (0, 0)
+}
+
+fn pass(evidence: String) -> Outcome {
+    (CisStatus::Pass, evidence)
+}
+
+fn fail(evidence: String) -> Outcome {
+    (CisStatus::Fail, evidence)
+}
+
+fn not_applicable(evidence: String) -> Outcome {
+    (CisStatus::NotApplicable, evidence)
+}
+
+fn mount_option_check(mount_point: &'static str, option: &'static str) -> Check {
+    Check {
+        id: String::new(),
+        section: "filesystem",
+        title: format!("Ensure {} option set on {} partition", option, mount_point),
+        rationale: match option {
+            "nodev" => "Prevents device files on a partition that doesn't need them",
+            "nosuid" => "Prevents setuid programs on a partition that doesn't need them",
+            _ => "Prevents running executables from a world-writable partition",
+        },
+        remediation: format!("Add {} to the options of {} in /etc/fstab and remount it", option, mount_point),
+        run: Box::new(move |host| match host.mount_options(mount_point) {
+            None => not_applicable(format!("{} is not a separate mount", mount_point)),
+            Some(options) if options.iter().any(|o| o == option) => pass(format!("{} mounted with {}", mount_point, options.join(","))),
+            Some(options) => fail(format!("{} mounted with {}", mount_point, options.join(","))),
+        }),
+    }
+}
+
+fn sysctl_check(key: &'static str, expected: &'static str, rationale: &'static str) -> Check {
+    Check {
+        id: String::new(),
+        section: "sysctl",
+        title: format!("Ensure {} is {}", key, expected),
+        rationale,
+        remediation: format!("Set {} = {} in /etc/sysctl.d/60-cis.conf and run `sysctl --system`", key, expected),
+        run: Box::new(move |host| match host.sysctl(key) {
+            Some(value) if value == expected => pass(format!("{} = {}", key, value)),
+            Some(value) => fail(format!("{} = {}", key, value)),
+            None => not_applicable(format!("{} is not available on this kernel", key)),
+        }),
+    }
+}
+
+/// `expected` lists accepted values, compared case-insensitively
+fn sshd_check(keyword: &'static str, expected: &'static [&'static str], default: &'static str, rationale: &'static str) -> Check {
+    Check {
+        id: String::new(),
+        section: "ssh",
+        title: format!("Ensure SSH {} is {}", keyword, expected.join(" or ")),
+        rationale,
+        remediation: format!("Set `{} {}` in /etc/ssh/sshd_config, or in the first included /etc/ssh/sshd_config.d file that sets it, and reload sshd", keyword, expected[0]),
+        run: Box::new(move |host| {
+            let Some(config) = host.sshd_config() else {
+                return not_applicable("sshd is not installed".to_string());
+            };
+            let (value, source) = match host.sshd_option(&config, keyword) {
+                Some(value) => (value, "configured"),
+                None => (default.to_string(), "default"),
+            };
+            let evidence = format!("{} {} ({})", keyword, value, source);
+            if expected.iter().any(|e| e.eq_ignore_ascii_case(&value)) {
+                pass(evidence)
+            } else {
+                fail(evidence)
+            }
+        }),
+    }
+}
+
+/// Numeric setting from a config file compared with a bound
+fn numeric_setting_check(
+    path: &'static str,
+    key: &'static str,
+    at_least: bool,
+    bound: i64,
+    rationale: &'static str,
+) -> Check {
+    let relation = if at_least { ">=" } else { "<=" };
+    Check {
+        id: String::new(),
+        section: "password",
+        title: format!("Ensure {} {} {}", key, relation, bound),
+        rationale,
+        remediation: format!("Set {} to {} {} in {}", key, if at_least { "at least" } else { "at most" }, bound, path),
+        run: Box::new(move |host| match host.setting(path, key).and_then(|v| v.parse::<i64>().ok()) {
+            Some(value) if (at_least && value >= bound) || (!at_least && value <= bound) => {
+                pass(format!("{} {} in {}", key, value, path))
+            }
+            Some(value) => fail(format!("{} {} in {}", key, value, path)),
+            None => fail(format!("{} is not set in {}", key, path)),
+        }),
+    }
+}
+
+fn checks() -> Vec<Check> {
+    let mut checks = vec![
+        Check {
+            id: String::new(),
+            section: "filesystem",
+            title: "Ensure /tmp is a separate partition".to_string(),
+            rationale: "A separate /tmp limits the impact of filling it and allows restrictive mount options",
+            remediation: "Mount /tmp as tmpfs or a dedicated partition (e.g. `systemctl enable tmp.mount`)".to_string(),
+            run: Box::new(|host| match host.mount_options("/tmp") {
+                Some(_) => pass("/tmp is a separate mount".to_string()),
+                None => fail("/tmp is part of the root filesystem".to_string()),
+            }),
+        },
+    ];
+    for mount_point in ["/tmp", "/dev/shm", "/var/tmp"] {
+        for option in ["nodev", "nosuid", "noexec"] {
+            checks.push(mount_option_check(mount_point, option));
+        }
+    }
+
+    checks.extend([
+        sysctl_check("net.ipv4.ip_forward", "0", "Hosts that are not routers should not forward packets"),
+        sysctl_check("net.ipv4.conf.all.send_redirects", "0", "ICMP redirects are only needed on routers"),
+        sysctl_check("net.ipv4.conf.all.accept_redirects", "0", "Forged ICMP redirects can alter the routing table"),
+        sysctl_check("net.ipv4.conf.all.accept_source_route", "0", "Source-routed packets can bypass network controls"),
+        sysctl_check("net.ipv4.conf.all.log_martians", "1", "Logging impossible source addresses helps detect spoofing"),
+        sysctl_check("net.ipv4.icmp_echo_ignore_broadcasts", "1", "Prevents the host from taking part in smurf attacks"),
+        sysctl_check("net.ipv4.tcp_syncookies", "1", "SYN cookies keep the host reachable during SYN floods"),
+        sysctl_check("kernel.randomize_va_space", "2", "Address space layout randomization makes memory exploits harder"),
+        sysctl_check("fs.suid_dumpable", "0", "Core dumps of setuid programs can leak sensitive data"),
+    ]);
+
+    checks.extend([
+        sshd_check("PermitRootLogin", &["no"], "prohibit-password", "Administrators should log in as themselves and escalate with sudo"),
+        sshd_check("PermitEmptyPasswords", &["no"], "no", "Accounts without a password must not be reachable over SSH"),
+        sshd_check("HostbasedAuthentication", &["no"], "no", "Host-based trust is easy to abuse"),
+        sshd_check("IgnoreRhosts", &["yes"], "yes", ".rhosts files bypass normal authentication"),
+        sshd_check("X11Forwarding", &["no"], "no", "X11 forwarding exposes the client's display to the server"),
+        sshd_check("LogLevel", &["INFO", "VERBOSE"], "INFO", "Login activity must be logged for investigations"),
+        Check {
+            id: String::new(),
+            section: "ssh",
+            title: "Ensure SSH MaxAuthTries is 4 or less".to_string(),
+            rationale: "Limiting attempts per connection slows down brute-force attacks",
+            remediation: "Set `MaxAuthTries 4` in /etc/ssh/sshd_config, or in the first included /etc/ssh/sshd_config.d file that sets it, and reload sshd".to_string(),
+            run: Box::new(|host| {
+                let Some(config) = host.sshd_config() else {
+                    return not_applicable("sshd is not installed".to_string());
+                };
+                let value = host.sshd_option(&config, "MaxAuthTries").unwrap_or_else(|| "6".to_string());
+                match value.parse::<u32>() {
+                    Ok(tries) if tries <= 4 => pass(format!("MaxAuthTries {}", tries)),
+                    _ => fail(format!("MaxAuthTries {}", value)),
+                }
+            }),
+        },
+        Check {
+            id: String::new(),
+            section: "ssh",
+            title: "Ensure permissions on /etc/ssh/sshd_config are configured".to_string(),
+            rationale: "The SSH server configuration must only be changed by root",
+            remediation: "Run `chown root:root /etc/ssh/sshd_config && chmod 600 /etc/ssh/sshd_config`".to_string(),
+            run: Box::new(|host| match fs::metadata(host.path("/etc/ssh/sshd_config")) {
+                Ok(metadata) => {
+                    let (mode, uid) = mode_and_owner(&metadata);
+                    let evidence = format!("mode {:o}, owner uid {}", mode & 0o777, uid);
+                    if mode & 0o077 == 0 && uid == 0 {
+                        pass(evidence)
+                    } else {
+                        fail(evidence)
+                    }
+                }
+                Err(_) => not_applicable("sshd is not installed".to_string()),
+            }),
+        },
+    ]);
+
+    checks.extend([
+        numeric_setting_check("/etc/login.defs", "PASS_MAX_DAYS", false, 365, "Passwords that never expire stay useful to attackers forever"),
+        numeric_setting_check("/etc/login.defs", "PASS_MIN_DAYS", true, 1, "Prevents cycling through passwords to reuse an old one"),
+        numeric_setting_check("/etc/login.defs", "PASS_WARN_AGE", true, 7, "Users need notice before their password expires"),
+        numeric_setting_check("/etc/security/pwquality.conf", "minlen", true, 14, "Long passwords resist guessing and cracking"),
+    ]);
+
+    checks.extend([
+        Check {
+            id: String::new(),
+            section: "auditing",
+            title: "Ensure auditd is installed".to_string(),
+            rationale: "auditd records security-relevant events for investigations",
+            remediation: "Install the auditd (Debian/Ubuntu) or audit (RHEL) package".to_string(),
+            run: Box::new(|host| {
+                match ["/sbin/auditd", "/usr/sbin/auditd"].iter().find(|p| host.path(p).exists()) {
+                    Some(path) => pass(format!("{} exists", path)),
+                    None => fail("auditd binary not found".to_string()),
+                }
+            }),
+        },
+        Check {
+            id: String::new(),
+            section: "auditing",
+            title: "Ensure auditd service is running".to_string(),
+            rationale: "An installed but stopped audit daemon records nothing",
+            remediation: "Run `systemctl --now enable auditd`".to_string(),
+            run: Box::new(|host| {
+                if host.process_running("auditd") {
+                    pass("auditd process is running".to_string())
+                } else {
+                    fail("no auditd process found".to_string())
+                }
+            }),
+        },
+        Check {
+            id: String::new(),
+            section: "permissions",
+            title: "Ensure no world writable files exist".to_string(),
+            rationale: "World-writable files can be modified by any user on the system",
+            remediation: "Remove write access for others with `chmod o-w <file>`".to_string(),
+            run: Box::new(|host| {
+                let (files, truncated) = host.world_writable_files();
+                let partial = if truncated { " (scan limit reached, partial result)" } else { "" };
+                if files.is_empty() {
+                    return pass(format!("none found under {}{}", host.world_writable_paths.join(", "), partial));
+                }
+                let listed: Vec<String> = files.iter()
+                    .take(MAX_LISTED)
+                    .map(|f| format!("/{}", f.strip_prefix(&host.root).unwrap_or(f).display()))
+                    .collect();
+                let more = files.len().saturating_sub(MAX_LISTED);
+                fail(format!(
+                    "{} file(s): {}{}{}",
+                    files.len(),
+                    listed.join(", "),
+                    if more > 0 { format!(" and {} more", more) } else { String::new() },
+                    partial
+                ))
+            }),
+        },
+    ]);
+
+    // IDs follow the section order so they stay stable as long as checks are only appended
+    let mut counters = std::collections::HashMap::new();
+    for check in &mut checks {
+        let counter = counters.entry(check.section).or_insert(0);
+        *counter += 1;
+        check.id = format!("cis-{}-{}", check.section, counter);
+    }
+    checks
+}
+
+/// Run every check against the host rooted at `root`
+fn run_checks(root: &Path, config: &CisConfig) -> Vec<CisResult> {
+    let host = Host {
+        root: root.to_path_buf(),
+        world_writable_paths: config.world_writable_paths.clone(),
+        world_writable_max_depth: config.world_writable_max_depth,
+        world_writable_max_entries: config.world_writable_max_entries,
+    };
+
+    checks().into_iter()
+        .map(|check| {
+            let (status, evidence) = (check.run)(&host);
+            debug!("CIS {} {:?}: {}", check.id, status, evidence);
+            CisResult {
+                id: check.id,
+                section: check.section.to_string(),
+                title: check.title,
+                rationale: check.rationale.to_string(),
+                remediation: check.remediation,
+                status,
+                evidence,
+            }
+        })
+        .collect()
+}
+
+/// Runs the CIS checks every `[cis] interval_seconds`, independent of collections
+pub struct CisScheduler {
+    config: CisConfig,
+    report_path: PathBuf,
+    last_run: Option<DateTime<Utc>>,
+    /// Latest report not yet delivered to the backend
+    pending: Option<CisReport>,
+}
+
+impl CisScheduler {
+    /// The last run is taken from the saved report, so restarts don't rerun the checks
+    ///
+    /// A report that was never delivered stays pending across restarts.
+    pub fn new(config: &CisConfig, report_path: &str) -> Self {
+        let report = fs::read(report_path).ok()
+            .and_then(|contents| serde_json::from_slice::<CisReport>(&contents).ok());
+        let last_run = report.as_ref().map(|report| report.collected_at);
+        let pending = report.filter(|_| pending_marker(Path::new(report_path)).exists());
+
+        CisScheduler {
+            config: config.clone(),
+            report_path: PathBuf::from(report_path),
+            last_run,
+            pending,
+        }
+    }
+
+    /// Report waiting to be delivered, retried every collection cycle
+    pub fn pending(&self) -> Option<&CisReport> {
+        self.pending.as_ref()
+    }
+
+    /// The pending report was delivered (or there is nowhere to deliver it)
+    pub fn clear_pending(&mut self) {
+        self.pending = None;
+        let marker = pending_marker(&self.report_path);
+        if marker.exists() {
+            if let Err(e) = fs::remove_file(&marker) {
+                warn!("Failed to remove {}: {}", marker.display(), e);
+            }
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        self.last_run.is_none_or(|last| {
+            Utc::now().signed_duration_since(last).num_seconds() >= self.config.interval_seconds as i64
+        })
+    }
+
+    /// Run the checks and save the report next to the other output files
+    pub fn run(&mut self, agent_id: &str) -> CisReport {
+        info!("Running CIS Level 1 checks...");
+        let results = run_checks(Path::new("/"), &self.config);
+        let device_fingerprint = fingerprint::generate_fingerprint().unwrap_or_else(|e| {
+            warn!("Failed to generate device fingerprint for the CIS report: {}", e);
+            String::new()
+        });
+        let hostname = System::host_name().unwrap_or_else(|| "unknown".to_string());
+        let report = build_report(results, agent_id, &device_fingerprint, &hostname);
+        info!("✓ CIS checks: {} passed, {} failed, {} not applicable",
+            report.passed, report.failed, report.not_applicable);
+
+        self.last_run = Some(report.collected_at);
+        if let Err(e) = self.save(&report) {
+            warn!("Failed to save CIS report: {}", e);
+        }
+        if let Err(e) = fs::write(pending_marker(&self.report_path), b"") {
+            warn!("Failed to mark CIS report as pending: {}", e);
+        }
+        self.pending = Some(report.clone());
+        report
+    }
+
+    fn save(&self, report: &CisReport) -> Result<(), String> {
+        if let Some(parent) = self.report_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create output directory: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(report)
+            .map_err(|e| format!("Failed to serialize report: {}", e))?;
+        fs::write(&self.report_path, json)
+            .map_err(|e| format!("Failed to write {}: {}", self.report_path.display(), e))
+    }
+}
+
+/// Exists while the saved report has not been delivered
+fn pending_marker(report_path: &Path) -> PathBuf {
+    let mut marker = report_path.as_os_str().to_owned();
+    marker.push(".pending");
+    PathBuf::from(marker)
+}
+
+fn build_report(results: Vec<CisResult>, agent_id: &str, device_fingerprint: &str, hostname: &str) -> CisReport {
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    CisReport {
+        collected_at: Utc::now(),
+        agent_id: agent_id.to_string(),
+        device_fingerprint: device_fingerprint.to_string(),
+        hostname: hostname.to_string(),
+        benchmark: BENCHMARK.to_string(),
+        distribution: System::distribution_id(),
+        passed: count(CisStatus::Pass),
+        failed: count(CisStatus::Fail),
+        not_applicable: count(CisStatus::NotApplicable),
+        results,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_checks_against_fake_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(root, "proc/mounts", "/dev/sda1 / ext4 rw 0 0\ntmpfs /tmp tmpfs rw,nosuid,nodev 0 0\n");
+        write(root, "proc/sys/net/ipv4/ip_forward", "1\n");
+        write(root, "proc/sys/kernel/randomize_va_space", "2\n");
+        write(root, "proc/1/comm", "systemd\n");
+        write(root, "proc/812/comm", "auditd\n");
+        write(root, "etc/ssh/sshd_config",
+            "Include sshd_config.d/*.conf\n# PermitRootLogin yes\nPermitRootLogin no\nMaxAuthTries 6\nMatch User backup\n  X11Forwarding yes\n");
+        write(root, "etc/ssh/sshd_config.d/20-late.conf", "MaxAuthTries 8\nLogLevel QUIET\n");
+        write(root, "etc/ssh/sshd_config.d/10-early.conf", "MaxAuthTries 3\n");
+        write(root, "etc/ssh/sshd_config.d/README", "PermitRootLogin yes\n");
+        write(root, "etc/login.defs", "PASS_MAX_DAYS\t99999\nPASS_MIN_DAYS 1\n");
+        write(root, "etc/world", "x");
+        fs::set_permissions(root.join("etc/world"), fs::Permissions::from_mode(0o666)).unwrap();
+
+        let config = CisConfig { world_writable_paths: vec!["/etc".to_string()], ..CisConfig::default() };
+        let results = run_checks(root, &config);
+        let find = |title: &str| {
+            results.iter().find(|r| r.title == title).unwrap_or_else(|| panic!("No check titled {}", title))
+        };
+
+        assert_eq!(find("Ensure /tmp is a separate partition").status, CisStatus::Pass);
+        assert_eq!(find("Ensure noexec option set on /tmp partition").status, CisStatus::Fail);
+        assert_eq!(find("Ensure nodev option set on /tmp partition").status, CisStatus::Pass);
+        assert_eq!(find("Ensure nodev option set on /var/tmp partition").status, CisStatus::NotApplicable);
+        assert_eq!(find("Ensure net.ipv4.ip_forward is 0").evidence, "net.ipv4.ip_forward = 1");
+        assert_eq!(find("Ensure kernel.randomize_va_space is 2").status, CisStatus::Pass);
+        assert_eq!(find("Ensure SSH PermitRootLogin is no").status, CisStatus::Pass, "Comments are ignored");
+        assert_eq!(find("Ensure SSH X11Forwarding is no").evidence, "X11Forwarding no (default)",
+            "Match blocks don't change the global value");
+        assert_eq!(find("Ensure SSH MaxAuthTries is 4 or less").evidence, "MaxAuthTries 3",
+            "Included files are read first, in name order");
+        assert_eq!(find("Ensure SSH LogLevel is INFO or VERBOSE").evidence, "LogLevel QUIET (configured)");
+        assert_eq!(find("Ensure PASS_MAX_DAYS <= 365").status, CisStatus::Fail);
+        assert_eq!(find("Ensure PASS_MIN_DAYS >= 1").status, CisStatus::Pass);
+        assert_eq!(find("Ensure auditd service is running").status, CisStatus::Pass);
+        assert_eq!(find("Ensure no world writable files exist").evidence, "1 file(s): /etc/world");
+
+        write(root, "etc/deep/er/world", "x");
+        fs::set_permissions(root.join("etc/deep/er/world"), fs::Permissions::from_mode(0o666)).unwrap();
+        let shallow = CisConfig { world_writable_max_depth: 1, ..config.clone() };
+        let evidence = run_checks(root, &shallow).into_iter()
+            .find(|r| r.title == "Ensure no world writable files exist").unwrap().evidence;
+        assert_eq!(evidence, "1 file(s): /etc/world (scan limit reached, partial result)");
+
+        let ids: std::collections::HashSet<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids.len(), results.len(), "Check IDs must be unique");
+        assert!(results.iter().all(|r| !r.rationale.is_empty() && !r.remediation.is_empty()));
+    }
+
+    #[test]
+    fn test_scheduler_uses_saved_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cis_report.json");
+        let config = CisConfig { interval_seconds: 3600, ..CisConfig::default() };
+
+        let scheduler = CisScheduler::new(&config, path.to_str().unwrap());
+        assert!(scheduler.due(), "Never ran before");
+
+        let report = build_report(Vec::new(), "agent-test", "fp", "host1");
+        scheduler.save(&report).unwrap();
+        assert!(!CisScheduler::new(&config, path.to_str().unwrap()).due(), "Ran less than an hour ago");
+    }
+
+    #[test]
+    fn test_undelivered_report_stays_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cis_report.json");
+        let config = CisConfig { interval_seconds: 3600, world_writable_paths: Vec::new(), ..CisConfig::default() };
+
+        let mut scheduler = CisScheduler::new(&config, path.to_str().unwrap());
+        assert!(scheduler.pending().is_none());
+        let report = scheduler.run("agent-test");
+        assert_eq!(scheduler.pending(), Some(&report));
+
+        let mut restarted = CisScheduler::new(&config, path.to_str().unwrap());
+        assert_eq!(restarted.pending(), Some(&report), "A failed send survives a restart");
+        assert!(!restarted.due());
+
+        restarted.clear_pending();
+        assert!(CisScheduler::new(&config, path.to_str().unwrap()).pending().is_none());
+    }
+}
@@ -0,0 +1,296 @@
+use crate::config;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Device Agent - System Monitoring Agent
+#[derive(Debug, Parser)]
+#[command(name = "device-agent", version, about, after_help = "Get your API token from:\n  http://localhost:8000/admin/agents/agenttoken/")]
+pub struct Cli {
+    /// Configuration file [default: $DEVICE_AGENT_CONFIG, /etc/device-agent/config.toml or ./config.toml]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Log level, overrides [logging] level
+    #[arg(long, global = true, value_parser = ["trace", "debug", "info", "warn", "error"])]
+    pub log_level: Option<String>,
+
+    /// Format of the command result
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Defaults to `run`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// `--config`, else the discovered configuration file
+    pub fn config_path(&self) -> String {
+        config::discover(self.config.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Collect and send on every interval until stopped (default)
+    Run,
+    /// Request registration with the backend
+    Init,
+    /// Check the registration status and save the token once approved
+    Status,
+    /// Store an API token
+    Register {
+        /// Token from the admin, e.g. agt_xxxxxxxxxxxxxxxxxxx
+        token: String,
+    },
+    /// Delete the stored API token
+    Unregister,
+    /// Check that the stored API token can be decrypted
+    Token,
+    /// Run a single collection cycle and exit
+    Collect {
+        /// Only collect and print the payload (no token, delivery or sinks)
+        #[arg(long)]
+        once: bool,
+        /// Collectors to run; system information is always included
+        #[arg(long, value_enum, value_delimiter = ',', requires = "once")]
+        only: Vec<Collector>,
+        /// Write the payload to a file instead of stdout
+        #[arg(long, short = 'o', value_name = "PATH", requires = "once")]
+        file: Option<String>,
+        /// Also send the payload once over the configured transport
+        #[arg(long, requires = "once")]
+        send: bool,
+    },
+    /// Diagnose config, token, fingerprint, network, TLS and the backend
+    Doctor,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Query the local snapshot history
+    History {
+        /// Defaults to `list`
+        #[command(subcommand)]
+        action: Option<HistoryCommand>,
+    },
+    /// Installed software as an SBOM (stdout by default)
+    Sbom {
+        #[arg(long, short, default_value = "cyclonedx", value_parser = ["cyclonedx", "spdx"])]
+        format: String,
+        /// Write the document to a file instead of stdout
+        #[arg(long, short = 'o', value_name = "PATH")]
+        file: Option<String>,
+    },
+    /// Write a tar.gz with the redacted config, logs and diagnostics for support
+    SupportBundle {
+        /// Archive path [default: device-agent-support-<timestamp>.tar.gz]
+        #[arg(long, short = 'o', value_name = "PATH")]
+        file: Option<String>,
+        /// Number of most recent log files to include
+        #[arg(long, default_value_t = 5)]
+        logs: usize,
+    },
+}
+
+/// Optional collectors for `collect --once --only`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Collector {
+    Services,
+    Software,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective configuration
+    Show,
+    /// Check that the configuration file parses
+    Validate,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum HistoryCommand {
+    /// Newest snapshots first
+    List {
+        #[arg(default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print a stored snapshot
+    Show {
+        id: i64,
+    },
+    /// Services, software and packages changed between two snapshots
+    Diff {
+        from: i64,
+        to: i64,
+    },
+}
+
+/// Rewrite the old `--init` style flags into subcommands
+pub fn legacy_args(mut args: Vec<String>) -> Vec<String> {
+    let Some(first) = args.get(1) else {
+        return args;
+    };
+    let command = match first.as_str() {
+        "--init" | "-i" => "init",
+        "--check-status" | "-s" => "status",
+        "--register" | "-r" => "register",
+        "--unregister" | "-u" => "unregister",
+        "--check-token" | "-c" => "token",
+        _ => return args,
+    };
+    eprintln!("⚠ '{}' is deprecated, use 'device-agent {}'", first, command);
+    args[1] = command.to_string();
+    args
+}
+
+/// Outcome of a subcommand, printed as text or as JSON with `--output json`
+#[derive(Debug, Serialize)]
+pub struct CommandResult {
+    pub ok: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub data: Value,
+    /// Next steps for humans, e.g. the command to run next
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<String>,
+    /// The command already wrote its output (documents on stdout)
+    #[serde(skip)]
+    pub printed: bool,
+}
+
+impl CommandResult {
+    pub fn success(message: impl Into<String>) -> Self {
+        CommandResult { ok: true, message: message.into(), data: Value::Null, hints: Vec::new(), printed: false }
+    }
+
+    pub fn failure(message: impl Into<String>) -> Self {
+        CommandResult { ok: false, ..CommandResult::success(message) }
+    }
+
+    pub fn printed() -> Self {
+        CommandResult { printed: true, ..CommandResult::success("") }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hints.push(hint.into());
+        self
+    }
+}
+
+/// Print a command's result and return the process exit code
+pub fn report(result: Result<CommandResult, String>, output: OutputFormat) -> i32 {
+    let result = result.unwrap_or_else(CommandResult::failure);
+    if !result.printed {
+        match output {
+            OutputFormat::Json => match serde_json::to_string_pretty(&result) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("✗ Failed to serialize result: {}", e),
+            },
+            OutputFormat::Text if result.ok => print!("{}", render_text(&result)),
+            OutputFormat::Text => eprint!("{}", render_text(&result)),
+        }
+    }
+    if result.ok { 0 } else { 1 }
+}
+
+fn render_text(result: &CommandResult) -> String {
+    let mut text = format!("{} {}\n", if result.ok { "✓" } else { "✗" }, result.message);
+    match &result.data {
+        Value::Null => {}
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                match value {
+                    Value::String(s) => text.push_str(&format!("  {}: {}\n", key, s)),
+                    value => text.push_str(&format!("  {}: {}\n", key, value)),
+                }
+            }
+        }
+        data => text.push_str(&format!("{}\n", serde_json::to_string_pretty(data).unwrap_or_default())),
+    }
+    if !result.hints.is_empty() {
+        text.push('\n');
+        for hint in &result.hints {
+            text.push_str(&format!("{}\n", hint));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use serde_json::json;
+
+    fn parse(args: &[&str]) -> Cli {
+        let args = args.iter().map(|a| a.to_string()).collect();
+        Cli::try_parse_from(legacy_args(args)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_commands_and_global_flags() {
+        Cli::command().debug_assert();
+
+        let cli = parse(&["device-agent"]);
+        assert!(cli.command.is_none(), "No subcommand means run");
+        assert!(cli.config.is_none());
+
+        let cli = parse(&["device-agent", "status", "--config", "/etc/device-agent/config.toml", "--output", "json"]);
+        assert!(matches!(cli.command, Some(Command::Status)));
+        assert_eq!(cli.config_path(), "/etc/device-agent/config.toml");
+        assert_eq!(cli.output, OutputFormat::Json);
+
+        let cli = parse(&["device-agent", "--register", "agt_123"]);
+        assert!(matches!(cli.command, Some(Command::Register { ref token }) if token == "agt_123"), "Legacy flags still work");
+
+        let cli = parse(&["device-agent", "--log-level", "debug", "sbom", "--format", "spdx", "-o", "sbom.json", "--output", "json"]);
+        assert_eq!(cli.log_level.as_deref(), Some("debug"));
+        assert_eq!(cli.output, OutputFormat::Json, "Global flags work after a subcommand");
+        assert!(matches!(cli.command, Some(Command::Sbom { ref format, ref file })
+            if format == "spdx" && file.as_deref() == Some("sbom.json")));
+
+        let cli = parse(&["device-agent", "collect", "--once", "--only", "services,software", "-o", "out.json"]);
+        assert!(matches!(cli.command, Some(Command::Collect { once: true, ref only, send: false, .. })
+            if only == &[Collector::Services, Collector::Software]));
+        assert!(Cli::try_parse_from(["device-agent", "collect", "--send"]).is_err(), "--send needs --once");
+
+        let cli = parse(&["device-agent", "support-bundle", "--logs", "2"]);
+        assert!(matches!(cli.command, Some(Command::SupportBundle { file: None, logs: 2 })));
+
+        let cli = parse(&["device-agent", "history", "diff", "41", "42", "--config", "agent.toml"]);
+        assert!(matches!(cli.command, Some(Command::History { action: Some(HistoryCommand::Diff { from: 41, to: 42 }) })));
+        assert_eq!(cli.config_path(), "agent.toml");
+        let cli = parse(&["device-agent", "history", "list"]);
+        assert!(matches!(cli.command, Some(Command::History { action: Some(HistoryCommand::List { limit: 20 }) })));
+        assert!(Cli::try_parse_from(["device-agent", "history", "show", "latest"]).is_err(), "Snapshot IDs are numbers");
+
+        assert!(Cli::try_parse_from(["device-agent", "--log-level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn test_render_result() {
+        let result = CommandResult::failure("No API token found")
+            .with_data(json!({ "location": "/etc/device-agent/token", "length": 32 }))
+            .with_hint("Register with: device-agent register <token>");
+        assert_eq!(render_text(&result),
+            "✗ No API token found\n  length: 32\n  location: /etc/device-agent/token\n\nRegister with: device-agent register <token>\n");
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["ok"], false);
+        assert!(json.get("printed").is_none());
+    }
+}
@@ -1,7 +1,29 @@
 use crate::config::Config;
-use sysinfo::{System};
+use crate::models::{DiskInfo, LoadAverage};
+use sysinfo::{Disks, System};
 use std::collections::HashMap;
 
+/// Linux distribution, e.g. the purl namespace and advisory ecosystem of deb/rpm packages
+#[derive(Debug, Clone)]
+pub struct Distro {
+    pub id: String,
+    pub version: Option<String>,
+}
+
+impl Distro {
+    /// `ID` and `VERSION_ID` from os-release
+    pub fn local() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let id = System::distribution_id();
+        if id.is_empty() || id == "linux" {
+            return None;
+        }
+        Some(Distro { id, version: System::os_version() })
+    }
+}
+
 /// Basic system information structure (internal use)
 pub struct BasicInfo {
     pub hostname: String,
@@ -10,6 +32,10 @@ pub struct BasicInfo {
     pub cpu_info: String,
     pub memory_total: u64,
     pub memory_available: u64,
+    pub cpu_count: usize,
+    pub cpu_usage_percent: f32,
+    pub load_average: LoadAverage,
+    pub disks: Vec<DiskInfo>,
     pub ip_addresses: HashMap<String, Vec<String>>,
 }
 
@@ -36,6 +62,22 @@ pub fn collect_basic_info(_config: &Config) -> BasicInfo {
     let memory_total = sys.total_memory();
     let memory_available = get_available_memory(&sys);
     
+    // Get CPU usage (needs two samples at least MINIMUM_CPU_UPDATE_INTERVAL apart)
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    sys.refresh_cpu_usage();
+    let cpu_count = sys.cpus().len();
+    let cpu_usage_percent = sys.global_cpu_info().cpu_usage();
+    
+    let load = System::load_average();
+    let load_average = LoadAverage {
+        one: load.one,
+        five: load.five,
+        fifteen: load.fifteen,
+    };
+    
+    // Get disk info
+    let disks = get_disks();
+    
     // Get IP addresses
     let ip_addresses = get_ip_addresses();
     
@@ -46,10 +88,27 @@ pub fn collect_basic_info(_config: &Config) -> BasicInfo {
         cpu_info,
         memory_total,
         memory_available,
+        cpu_count,
+        cpu_usage_percent,
+        load_average,
+        disks,
         ip_addresses,
     }
 }
 
+/// Get mounted filesystems with capacity
+pub fn get_disks() -> Vec<DiskInfo> {
+    Disks::new_with_refreshed_list()
+        .iter()
+        .map(|disk| DiskInfo {
+            mount_point: disk.mount_point().to_string_lossy().to_string(),
+            file_system: disk.file_system().to_string_lossy().to_string(),
+            total_bytes: disk.total_space(),
+            available_bytes: disk.available_space(),
+        })
+        .collect()
+}
+
 /// Get OS type as string
 fn get_os_type() -> String {
     #[cfg(target_os = "macos")]
@@ -66,7 +125,7 @@ fn get_os_type() -> String {
 }
 
 /// Get available memory (works across all platforms)
-fn get_available_memory(sys: &System) -> u64 {
+pub fn get_available_memory(sys: &System) -> u64 {
     let available = sys.available_memory();
     
     // If available_memory() returns 0 (common on macOS), calculate it
@@ -150,7 +209,7 @@ fn get_ip_addresses() -> HashMap<String, Vec<String>> {
     {
         use std::collections::HashMap as TempMap;
         
-        if let Ok(output) = std::process::Command::new("ip").args(&["addr"]).output() {
+        if let Ok(output) = std::process::Command::new("ip").args(["addr"]).output() {
             let output_str = String::from_utf8_lossy(&output.stdout);
             
             let mut interface_ipv4: TempMap<String, String> = TempMap::new();
@@ -168,7 +227,7 @@ fn get_ip_addresses() -> HashMap<String, Vec<String>> {
                         let ip = ip_part.split('/').next().unwrap_or("");
                         if ip != "127.0.0.1" && !current_interface.starts_with("lo") && !current_interface.is_empty() {
                             interface_ipv4.insert(current_interface.clone(), ip.to_string());
-                            addresses.entry(ip.to_string()).or_insert_with(Vec::new);
+                            addresses.entry(ip.to_string()).or_default();
                         }
                     }
                 }
@@ -191,7 +250,7 @@ fn get_ip_addresses() -> HashMap<String, Vec<String>> {
                         if !ipv6.starts_with("fe80") && ipv6 != "::1" && !current_interface.is_empty() {
                             if let Some(ipv4) = interface_ipv4.get(&current_interface) {
                                 addresses.entry(ipv4.clone())
-                                    .or_insert_with(Vec::new)
+                                    .or_default()
                                     .push(ipv6.to_string());
                             }
                         }
@@ -1,3 +1,4 @@
+use crate::models::Package;
 use std::process::Command;
 use log::{debug, warn};
 
@@ -9,7 +10,7 @@ pub fn get_services() -> Vec<String> {
     
     // Get systemd services
     if let Ok(output) = Command::new("systemctl")
-        .args(&["list-units", "--type=service", "--state=running", "--no-pager", "--no-legend"])
+        .args(["list-units", "--type=service", "--state=running", "--no-pager", "--no-legend"])
         .output()
     {
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -29,43 +30,88 @@ pub fn get_services() -> Vec<String> {
     services
 }
 
-/// Get installed software on Linux
-pub fn get_software() -> Vec<String> {
-    let mut software = Vec::new();
-    
+/// Get installed packages on Linux (dpkg or rpm)
+pub fn get_software() -> Vec<Package> {
     debug!("Collecting Linux packages...");
     
     // Try dpkg (Debian/Ubuntu)
-    if let Ok(output) = Command::new("dpkg")
-        .args(&["--get-selections"])
+    let mut software = if let Ok(output) = Command::new("dpkg-query")
+        .args(["-W", "-f", "${Package}\t${Version}\t${Architecture}\t${db:Status-Abbrev}\t${source:Package}\n"])
         .output()
     {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        
-        for line in stdout.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 && parts[1] == "install" {
-                software.push(parts[0].to_string());
-            }
-        }
+        parse_dpkg(&String::from_utf8_lossy(&output.stdout))
     }
     // Try rpm (RedHat/CentOS/Fedora)
     else if let Ok(output) = Command::new("rpm")
-        .args(&["-qa", "--qf", "%{NAME}\n"])
+        .args(["-qa", "--qf", "%{NAME}\t%|EPOCH?{%{EPOCH}:}:{}|%{VERSION}-%{RELEASE}\t%{ARCH}\n"])
         .output()
     {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        
-        for line in stdout.lines() {
-            if !line.is_empty() {
-                software.push(line.to_string());
-            }
-        }
+        parse_rpm(&String::from_utf8_lossy(&output.stdout))
     } else {
         warn!("Failed to get Linux packages");
-    }
+        Vec::new()
+    };
     
-    software.sort();
+    software.sort_by(|a, b| a.name.cmp(&b.name));
     debug!("Found {} packages", software.len());
     software
-}
\ No newline at end of file
+}
+
+/// Parse `dpkg-query` output, keeping installed packages only
+fn parse_dpkg(stdout: &str) -> Vec<Package> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split('\t').collect();
+            // Status "ii" (or "hi" when held): selected for install and installed
+            if parts.len() < 4 || !matches!(parts[3].trim(), "ii" | "hi") {
+                return None;
+            }
+            Some(Package {
+                arch: Some(parts[2].to_string()).filter(|a| !a.is_empty()),
+                source: parts.get(4)
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty() && s != parts[0]),
+                ..Package::new(parts[0], parts[1], "deb")
+            })
+        })
+        .collect()
+}
+
+/// Parse `rpm -qa` output produced by the query format above
+fn parse_rpm(stdout: &str) -> Vec<Package> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split('\t').collect();
+            if parts.len() < 2 || parts[0].is_empty() {
+                return None;
+            }
+            Some(Package {
+                // Source-less packages such as gpg-pubkey report "(none)"
+                arch: parts.get(2).map(|a| a.to_string()).filter(|a| !a.is_empty() && a != "(none)"),
+                ..Package::new(parts[0], parts[1], "rpm")
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_lists() {
+        let dpkg = parse_dpkg("openssl\t3.0.2-0ubuntu1.15\tamd64\tii \topenssl\nold-kernel\t5.15.0-1\tamd64\trc \tlinux\nlibc6\t2.35-0ubuntu3\tamd64\tii \tglibc\n");
+        assert_eq!(dpkg.len(), 2, "Removed packages (rc) should be skipped");
+        assert_eq!(dpkg[0].version, "3.0.2-0ubuntu1.15");
+        assert_eq!(dpkg[0].arch.as_deref(), Some("amd64"));
+        assert_eq!(dpkg[0].source, None, "Source names equal to the package name are dropped");
+        assert_eq!(dpkg[1].source.as_deref(), Some("glibc"));
+
+        let rpm = parse_rpm("bash\t5.1.8-6.el9\tx86_64\nperl-IO\t4:1.43-481.el9\tx86_64\ngpg-pubkey\tfd431d51-4ae0493b\t(none)\n");
+        assert_eq!(rpm.len(), 3);
+        assert_eq!(rpm[1].version, "4:1.43-481.el9", "Epoch should be kept");
+        assert_eq!(rpm[2].arch, None);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::models::Package;
 use std::process::Command;
 use log::{debug, warn};
 
@@ -32,41 +33,45 @@ pub fn get_services() -> Vec<String> {
 }
 
 /// Get installed applications on macOS
-pub fn get_software() -> Vec<String> {
-    let mut software = Vec::new();
+pub fn get_software() -> Vec<Package> {
+    let mut software: Vec<Package> = Vec::new();
     
     debug!("Collecting macOS applications...");
     
-    // Get applications from /Applications
-    if let Ok(entries) = std::fs::read_dir("/Applications") {
+    let mut directories = vec!["/Applications".to_string()];
+    // Get applications from ~/Applications
+    if let Ok(home) = std::env::var("HOME") {
+        directories.push(format!("{}/Applications", home));
+    }
+    
+    for directory in directories {
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
         for entry in entries.flatten() {
             let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("app") {
-                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
-                    software.push(name.to_string());
-                }
+            if path.extension().and_then(|s| s.to_str()) != Some("app") {
+                continue;
             }
-        }
-    }
-    
-    // Get applications from ~/Applications
-    if let Ok(home) = std::env::var("HOME") {
-        let user_apps = format!("{}/Applications", home);
-        if let Ok(entries) = std::fs::read_dir(user_apps) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("app") {
-                    if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
-                        if !software.contains(&name.to_string()) {
-                            software.push(name.to_string());
-                        }
-                    }
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                if !software.iter().any(|p| p.name == name) {
+                    software.push(Package::new(name, &bundle_version(&path).unwrap_or_default(), "macos"));
                 }
             }
         }
     }
     
-    software.sort();
+    software.sort_by(|a, b| a.name.cmp(&b.name));
     debug!("Found {} applications", software.len());
     software
-}
\ No newline at end of file
+}
+
+/// `CFBundleShortVersionString` from an app's XML Info.plist (binary plists are skipped)
+fn bundle_version(app: &std::path::Path) -> Option<String> {
+    let plist = std::fs::read_to_string(app.join("Contents/Info.plist")).ok()?;
+    let after_key = plist.split("<key>CFBundleShortVersionString</key>").nth(1)?;
+    let value = after_key.trim_start().strip_prefix("<string>")?;
+    let version = value.split("</string>").next()?.trim();
+    Some(version.to_string()).filter(|v| !v.is_empty())
+}
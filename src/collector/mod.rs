@@ -12,7 +12,7 @@ pub mod linux;
 pub mod windows;
 
 use crate::config::Config;
-use crate::models::SystemInfo;
+use crate::models::{Package, SystemInfo};
 use crate::fingerprint;
 use chrono::Utc;
 use log::{error, debug, info};
@@ -40,14 +40,35 @@ pub fn collect_all_info(config: &Config) -> SystemInfo {
     };
 
     // Collect services
-    info!("Collecting services...");
-    let services = get_services();
-    info!("✓ Found {} services", services.len());
+    let services = if config.collection.include_services {
+        info!("Collecting services...");
+        let services = get_services();
+        info!("✓ Found {} services", services.len());
+        services
+    } else {
+        debug!("Service collection disabled");
+        Vec::new()
+    };
     
     // Collect installed software
-    info!("Collecting installed software...");
-    let installed_software = get_software();
-    info!("✓ Found {} installed applications", installed_software.len());
+    let packages = if config.collection.include_software {
+        info!("Collecting installed software...");
+        let packages = get_software();
+        info!("✓ Found {} installed applications", packages.len());
+        packages
+    } else {
+        debug!("Software collection disabled");
+        Vec::new()
+    };
+    let installed_software = packages.iter().map(|p| p.name.clone()).collect();
+
+    let mut not_collected = Vec::new();
+    if !config.collection.include_services {
+        not_collected.push("services".to_string());
+    }
+    if !config.collection.include_software {
+        not_collected.extend(["installed_software".to_string(), "packages".to_string()]);
+    }
     
     // Return system info (no services/software for Phase 2)
     SystemInfo {
@@ -60,15 +81,25 @@ pub fn collect_all_info(config: &Config) -> SystemInfo {
         cpu_info: basic.cpu_info,
         memory_total: basic.memory_total,
         memory_available: basic.memory_available,
+        cpu_count: basic.cpu_count,
+        cpu_usage_percent: basic.cpu_usage_percent,
+        load_average: basic.load_average,
+        disks: basic.disks,
+        software_violations: Vec::new(),
         ip_addresses: basic.ip_addresses,
         services,
         installed_software,
+        packages,
+        not_collected,
+        events: Vec::new(),
+        vulnerabilities: Vec::new(),
+        compliance: Vec::new(),
         collected_at: Utc::now(),
     }
 }
 
 /// Get running services based on OS
-fn get_services() -> Vec<String> {
+pub fn get_services() -> Vec<String> {
     #[cfg(target_os = "macos")]
     {
         macos::get_services()
@@ -91,7 +122,7 @@ fn get_services() -> Vec<String> {
 }
 
 /// Get installed software based on OS
-fn get_software() -> Vec<String> {
+pub fn get_software() -> Vec<Package> {
     #[cfg(target_os = "macos")]
     {
         macos::get_software()
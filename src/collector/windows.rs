@@ -1,3 +1,4 @@
+use crate::models::Package;
 use std::process::Command;
 use log::{debug, warn};
 
@@ -29,29 +30,33 @@ pub fn get_services() -> Vec<String> {
 }
 
 /// Get installed software on Windows
-pub fn get_software() -> Vec<String> {
+pub fn get_software() -> Vec<Package> {
     let mut software = Vec::new();
     
     debug!("Collecting Windows programs...");
     
-    // Get installed programs using wmic
+    // Get installed programs using wmic (CSV columns: Node,Name,Version)
     if let Ok(output) = Command::new("wmic")
-        .args(&["product", "get", "Name"])
+        .args(["product", "get", "Name,Version", "/format:csv"])
         .output()
     {
         let stdout = String::from_utf8_lossy(&output.stdout);
         
-        for line in stdout.lines().skip(1) {
-            let program = line.trim();
-            if !program.is_empty() && program != "Name" {
-                software.push(program.to_string());
+        for line in stdout.lines() {
+            let parts: Vec<&str> = line.trim().split(',').collect();
+            if parts.len() < 3 || parts[1].is_empty() || parts[1] == "Name" {
+                continue;
             }
+            // Program names may contain commas; the version is always last
+            let version = parts[parts.len() - 1];
+            let name = parts[1..parts.len() - 1].join(",");
+            software.push(Package::new(&name, version, "windows"));
         }
     } else {
         warn!("Failed to get Windows programs");
     }
     
-    software.sort();
+    software.sort_by(|a, b| a.name.cmp(&b.name));
     debug!("Found {} programs", software.len());
     software
-}
\ No newline at end of file
+}
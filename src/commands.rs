@@ -0,0 +1,436 @@
+use crate::config::{CommandsConfig, Config};
+use crate::crypto;
+use crate::fingerprint;
+use crate::models::RemoteCommand;
+use crate::sbom;
+use crate::support_bundle;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{info, debug, warn, error};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process::Command;
+use std::time::Duration;
+
+/// Largest command output kept in results and the audit log
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// How many executed command IDs are remembered to drop duplicates
+const SEEN_COMMANDS: usize = 256;
+
+/// Log files included in an uploaded diagnostics bundle
+const DIAGNOSTICS_LOGS: usize = 5;
+
+/// Actions the agent knows how to run; anything else is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    CollectNow,
+    Diagnostics,
+    RestartService,
+    Refingerprint,
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "collect_now" => Some(Action::CollectNow),
+            "diagnostics" => Some(Action::Diagnostics),
+            "restart_service" => Some(Action::RestartService),
+            "refingerprint" => Some(Action::Refingerprint),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a command, posted back to the backend and written to the audit log
+#[derive(Debug, Serialize)]
+pub struct CommandResult {
+    pub command_id: String,
+    pub action: String,
+    pub status: CommandStatus,
+    pub output: Value,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandStatus {
+    Succeeded,
+    Failed,
+    Rejected,
+}
+
+/// Executes allow-listed remote commands and reports their results
+pub struct CommandRunner {
+    config: CommandsConfig,
+    audit_path: String,
+    seen: VecDeque<String>,
+}
+
+impl CommandRunner {
+    pub fn new(config: &Config) -> Self {
+        CommandRunner {
+            config: config.commands.clone(),
+            audit_path: format!("{}/commands_audit.log", config.logging.log_directory),
+            seen: VecDeque::new(),
+        }
+    }
+
+    /// Fetch pending commands from the backend
+    pub fn poll(&self, config: &Config) -> Vec<RemoteCommand> {
+        if !self.config.poll {
+            return Vec::new();
+        }
+
+        match fetch_commands(config) {
+            Ok(commands) => {
+                if !commands.is_empty() {
+                    info!("Received {} command(s) from backend", commands.len());
+                }
+                commands
+            }
+            Err(e) => {
+                warn!("Failed to poll commands: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Run commands in order; returns true if an immediate collection was requested
+    pub fn run(&mut self, commands: Vec<RemoteCommand>, config: &Config) -> bool {
+        let mut collect_now = false;
+
+        for command in commands {
+            if self.seen.contains(&command.id) {
+                debug!("Skipping already executed command {}", command.id);
+                continue;
+            }
+            self.seen.push_back(command.id.clone());
+            if self.seen.len() > SEEN_COMMANDS {
+                self.seen.pop_front();
+            }
+
+            let result = self.execute(&command, config);
+            if result.status == CommandStatus::Succeeded && result.action == "collect_now" {
+                collect_now = true;
+            }
+
+            self.audit(&command, &result);
+            if let Err(e) = report_result(&result, config) {
+                warn!("Failed to report result of command {}: {}", command.id, e);
+            }
+        }
+
+        collect_now
+    }
+
+    fn execute(&self, command: &RemoteCommand, config: &Config) -> CommandResult {
+        let started_at = Utc::now();
+        info!("Executing remote command {} ({})", command.id, command.action);
+
+        let outcome = match self.authorize(command) {
+            Ok(action) => run_action(action, command, config)
+                .map_err(|e| (CommandStatus::Failed, e)),
+            Err(e) => Err((CommandStatus::Rejected, e)),
+        };
+
+        let (status, output) = match outcome {
+            Ok(output) => (CommandStatus::Succeeded, output),
+            Err((status, e)) => {
+                warn!("Command {} {:?}: {}", command.id, status, e);
+                (status, json!({ "error": e }))
+            }
+        };
+
+        CommandResult {
+            command_id: command.id.clone(),
+            action: command.action.clone(),
+            status,
+            output: truncate_output(output),
+            started_at,
+            finished_at: Utc::now(),
+        }
+    }
+
+    /// Check the action against the compiled-in set and the configured allow-list
+    fn authorize(&self, command: &RemoteCommand) -> Result<Action, String> {
+        if !valid_command_id(&command.id) {
+            return Err(format!("Invalid command id: {:?}", command.id));
+        }
+
+        let action = Action::from_name(&command.action)
+            .ok_or_else(|| format!("Unknown action: {}", command.action))?;
+
+        if !self.config.allowed_actions.iter().any(|a| a == &command.action) {
+            return Err(format!("Action not allowed by config: {}", command.action));
+        }
+
+        if action == Action::RestartService {
+            let service = command.args.get("service")
+                .ok_or_else(|| "Missing argument: service".to_string())?;
+            if !self.config.restartable_services.contains(service) {
+                return Err(format!("Service not in restartable_services: {}", service));
+            }
+        }
+
+        Ok(action)
+    }
+
+    /// Append one JSON line per command to the local audit log
+    fn audit(&self, command: &RemoteCommand, result: &CommandResult) {
+        let entry = json!({
+            "timestamp": Utc::now(),
+            "command": command,
+            "status": result.status,
+            "output": result.output,
+        });
+
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.audit_path)
+            .and_then(|mut file| writeln!(file, "{}", entry));
+
+        if let Err(e) = written {
+            error!("Failed to write command audit log {}: {}", self.audit_path, e);
+        }
+    }
+}
+
+fn run_action(action: Action, command: &RemoteCommand, config: &Config) -> Result<Value, String> {
+    match action {
+        // The main loop performs the collection once the command is acknowledged
+        Action::CollectNow => Ok(json!({ "message": "Collection scheduled" })),
+        Action::Diagnostics => send_diagnostics(command, config),
+        Action::RestartService => {
+            // Presence was checked in authorize()
+            let service = &command.args["service"];
+            restart_service(service).map(|output| json!({ "service": service, "output": output }))
+        }
+        Action::Refingerprint => {
+            let fingerprint = fingerprint::generate_fingerprint()?;
+            Ok(json!({ "device_fingerprint": fingerprint }))
+        }
+    }
+}
+
+/// Build a support bundle and upload it; the result describes what was sent
+fn send_diagnostics(command: &RemoteCommand, config: &Config) -> Result<Value, String> {
+    // Unpredictable name, created exclusively and removed on drop
+    let archive = tempfile::Builder::new()
+        .prefix("device-agent-diagnostics-")
+        .suffix(".tar.gz")
+        .tempfile()
+        .map_err(|e| format!("Failed to create temporary file: {}", e))?;
+    let bundle = support_bundle::create(config, "running agent", archive.path(), DIAGNOSTICS_LOGS)?;
+    let contents = fs::read(archive.path())
+        .map_err(|e| format!("Failed to read {}: {}", archive.path().display(), e))?;
+
+    let sha256 = format!("{:x}", Sha256::digest(&contents));
+    let size_bytes = contents.len();
+    upload_bundle(&command.id, contents, config)?;
+    info!("✓ Uploaded diagnostics bundle ({} bytes) for command {}", size_bytes, command.id);
+
+    Ok(json!({
+        "agent_version": env!("CARGO_PKG_VERSION"),
+        "bundle_sha256": sha256,
+        "bundle_size_bytes": size_bytes,
+        "files": bundle.files,
+        "missing": bundle.missing,
+        "token_location": crypto::get_token_location(),
+    }))
+}
+
+fn restart_service(service: &str) -> Result<String, String> {
+    #[cfg(target_os = "linux")]
+    let output = Command::new("systemctl").args(["restart", service]).output();
+
+    #[cfg(target_os = "macos")]
+    let output = Command::new("launchctl")
+        .args(["kickstart", "-k", &format!("system/{}", service)])
+        .output();
+
+    #[cfg(target_os = "windows")]
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command", "Restart-Service", "-Name", service])
+        .output();
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    let output: std::io::Result<std::process::Output> = Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "service restart not supported on this platform",
+    ));
+
+    let output = output.map_err(|e| format!("Failed to run restart command: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+    if output.status.success() {
+        info!("✓ Restarted service: {}", service);
+        Ok(stdout)
+    } else {
+        Err(format!("Restart of {} failed ({}): {}", service, output.status, stderr))
+    }
+}
+
+fn truncate_output(output: Value) -> Value {
+    let serialized = output.to_string();
+    if serialized.len() <= MAX_OUTPUT_BYTES {
+        return output;
+    }
+
+    let mut end = MAX_OUTPUT_BYTES;
+    while !serialized.is_char_boundary(end) {
+        end -= 1;
+    }
+    json!({ "truncated": true, "output": &serialized[..end] })
+}
+
+/// Command IDs end up in URLs and file names: `[A-Za-z0-9_-]{1,64}` only
+fn valid_command_id(id: &str) -> bool {
+    (1..=64).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Commands endpoint derived from the heartbeat URL
+fn commands_url(config: &Config) -> String {
+    config.server.url.replace("/heartbeat/", "/agents/commands/")
+}
+
+fn http_client(config: &Config) -> Result<Client, String> {
+    Client::builder()
+        .timeout(Duration::from_secs(config.server.timeout_seconds))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn fetch_commands(config: &Config) -> Result<Vec<RemoteCommand>, String> {
+    let api_token = crypto::load_token()?;
+
+    let response = http_client(config)?
+        .get(commands_url(config))
+        .header("Authorization", format!("Bearer {}", api_token))
+        .send()
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Backend error ({})", status));
+    }
+
+    #[derive(Deserialize)]
+    struct CommandList {
+        #[serde(default)]
+        commands: Vec<RemoteCommand>,
+    }
+
+    response
+        .json::<CommandList>()
+        .map(|list| list.commands)
+        .map_err(|e| format!("Failed to parse commands: {}", e))
+}
+
+/// POST a result with an HMAC-SHA256 signature keyed by the API token
+fn report_result(result: &CommandResult, config: &Config) -> Result<(), String> {
+    let api_token = crypto::load_token()?;
+    let body = serde_json::to_vec(result)
+        .map_err(|e| format!("Failed to serialize result: {}", e))?;
+    let signature = sign(&api_token, &body);
+
+    let url = format!("{}{}/result/", commands_url(config), sbom::encode(&result.command_id));
+    let response = http_client(config)?
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .header("X-Agent-Signature", format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    if response.status().is_success() {
+        debug!("Reported result of command {}", result.command_id);
+        Ok(())
+    } else {
+        Err(format!("Backend error ({})", response.status()))
+    }
+}
+
+/// POST a diagnostics bundle, signed the same way as results
+fn upload_bundle(command_id: &str, bundle: Vec<u8>, config: &Config) -> Result<(), String> {
+    let api_token = crypto::load_token()?;
+    let signature = sign(&api_token, &bundle);
+
+    let url = format!("{}{}/bundle/", commands_url(config), sbom::encode(command_id));
+    let response = http_client(config)?
+        .post(&url)
+        .header("Content-Type", "application/gzip")
+        .header("Authorization", format!("Bearer {}", api_token))
+        .header("X-Agent-Signature", format!("sha256={}", signature))
+        .body(bundle)
+        .send()
+        .map_err(|e| format!("Failed to upload diagnostics bundle: {}", e))?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Backend error ({})", response.status()))
+    }
+}
+
+fn sign(key: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(action: &str, args: &[(&str, &str)]) -> RemoteCommand {
+        RemoteCommand {
+            id: "cmd-1".to_string(),
+            action: action.to_string(),
+            args: args.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_authorize_enforces_allow_lists() {
+        let mut config = Config::default();
+        config.commands.allowed_actions = vec!["collect_now".to_string(), "restart_service".to_string()];
+        config.commands.restartable_services = vec!["nginx".to_string()];
+        let runner = CommandRunner::new(&config);
+
+        assert_eq!(runner.authorize(&command("collect_now", &[])), Ok(Action::CollectNow));
+        assert!(runner.authorize(&command("diagnostics", &[])).is_err(), "Not enabled in config");
+        assert!(runner.authorize(&command("rm_rf", &[])).is_err(), "Not a compiled-in action");
+        assert!(runner.authorize(&command("restart_service", &[("service", "nginx")])).is_ok());
+        assert!(runner.authorize(&command("restart_service", &[("service", "sshd")])).is_err());
+        assert!(runner.authorize(&command("restart_service", &[])).is_err());
+
+        for id in ["../x", "a/b", "", "id with space", &"x".repeat(65)] {
+            let unsafe_id = RemoteCommand { id: id.to_string(), ..command("collect_now", &[]) };
+            assert!(runner.authorize(&unsafe_id).is_err(), "Command id {:?} must be refused", id);
+        }
+    }
+
+    #[test]
+    fn test_sign_matches_known_hmac() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -1,8 +1,21 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Deserialize, Clone)]
+/// Replacement for secrets in `Config::redacted`
+const MASK: &str = "********";
+
+/// Environment variable naming the configuration file
+pub const CONFIG_ENV: &str = "DEVICE_AGENT_CONFIG";
+/// Locations probed when neither `--config` nor `DEVICE_AGENT_CONFIG` is set
+const CONFIG_CANDIDATES: &[&str] = &["/etc/device-agent/config.toml", "config.toml"];
+/// Drop-in directory next to the configuration file
+const DROP_IN_DIR: &str = "config.d";
+/// Prefix of `DEVICE_AGENT__SECTION__KEY` overrides
+const ENV_PREFIX: &str = "DEVICE_AGENT__";
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub collection: CollectionConfig,
     pub output: OutputConfig,
@@ -10,9 +23,40 @@ pub struct Config {
     pub agent: AgentConfig,
     pub retry: RetryConfig,
     pub server: ServerConfig, 
+    #[serde(default)]
+    pub spool: SpoolConfig,
+    #[serde(default)]
+    pub delta: DeltaConfig,
+    #[serde(default)]
+    pub commands: CommandsConfig,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub exporter: ExporterConfig,
+    #[serde(default)]
+    pub otlp: OtlpConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub sbom: SbomConfig,
+    #[serde(default)]
+    pub vulnerabilities: VulnerabilityConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub software_lists: SoftwareListConfig,
+    #[serde(default)]
+    pub cis: CisConfig,
+    #[serde(default)]
+    pub alerts: AlertConfig,
+    /// Outputs besides the primary `[server]` backend; see `effective_sinks`
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RetryConfig {
     pub max_retries: u32,
     pub initial_delay_ms: u64,
@@ -20,19 +64,73 @@ pub struct RetryConfig {
 }
 
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CollectionConfig {
     pub interval_seconds: u64,
+    #[serde(default = "default_true")]
+    pub include_services: bool,
+    #[serde(default = "default_true")]
+    pub include_software: bool,
+    /// Attach change events (package installed, service stopped, ...) to each snapshot
+    #[serde(default = "default_true")]
+    pub track_changes: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct OutputConfig {
     pub output_directory: String,
     pub save_to_file: bool,
     pub timestamp_format: String,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// When appended files (ndjson, csv, msgpack) are rotated
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Size limit for `rotation = "size"`
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Keep at most this many old files (0 = unlimited)
+    #[serde(default)]
+    pub max_files: usize,
+    /// Delete old files after this many days (0 = never)
+    #[serde(default)]
+    pub max_age_days: u64,
+    /// Gzip rotated files
+    #[serde(default)]
+    pub compress_rotated: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// One pretty-printed file per collection
+    #[default]
+    Json,
+    /// One JSON document per line, appended
+    Ndjson,
+    /// Scalar metrics, one row per collection
+    Csv,
+    /// Concatenated MessagePack documents, appended
+    Msgpack,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    #[default]
+    Daily,
+    Size,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+fn default_max_file_bytes() -> u64 {
+    10 * 1024 * 1024 // 10 MB
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct LoggingConfig {
     pub level: String,
     pub console: bool,
@@ -40,41 +138,623 @@ pub struct LoggingConfig {
     pub log_directory: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AgentConfig {
     pub agent_id: String,
     pub agent_name: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ServerConfig {
     pub enabled: bool,
     pub url: String,
     pub timeout_seconds: u64,
+    /// How heartbeats are delivered (`http` or `mqtt`)
+    #[serde(default)]
+    pub transport: TransportKind,
+    /// Request body compression (`none`, `gzip` or `zstd`)
+    #[serde(default)]
+    pub compression: Compression,
+    /// Bodies smaller than this are sent uncompressed
+    #[serde(default = "default_compression_threshold")]
+    pub compression_threshold_bytes: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Http,
+    Mqtt,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+fn default_compression_threshold() -> usize {
+    1024
+}
+
+/// Offline queue for heartbeats that could not be delivered
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SpoolConfig {
+    pub enabled: bool,
+    /// Defaults to `<output_directory>/spool` when not set
+    pub directory: Option<String>,
+    pub max_entries: usize,
+    pub max_bytes: u64,
+    pub max_age_hours: u64,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        SpoolConfig {
+            enabled: true,
+            directory: None,
+            max_entries: 1000,
+            max_bytes: 50 * 1024 * 1024, // 50 MB
+            max_age_hours: 72,
+        }
+    }
+}
+
+/// Delta heartbeats: send only what changed since the last acknowledged snapshot
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DeltaConfig {
+    pub enabled: bool,
+    /// Send a full snapshot every N heartbeats (0 = only when the backend asks)
+    pub full_snapshot_every: u32,
+}
+
+impl Default for DeltaConfig {
+    fn default() -> Self {
+        DeltaConfig {
+            enabled: true,
+            full_snapshot_every: 12,
+        }
+    }
+}
+
+/// Remote commands the backend may trigger on this device
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CommandsConfig {
+    pub enabled: bool,
+    /// Poll the commands endpoint each cycle (commands in heartbeat responses run either way)
+    pub poll: bool,
+    /// Subset of: collect_now, diagnostics, restart_service, refingerprint
+    pub allowed_actions: Vec<String>,
+    /// Services `restart_service` may act on
+    pub restartable_services: Vec<String>,
+    /// Minimum seconds between the starts of two collections when one is requested early
+    pub min_collect_gap_seconds: u64,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        CommandsConfig {
+            enabled: false,
+            poll: true,
+            allowed_actions: vec![
+                "collect_now".to_string(),
+                "diagnostics".to_string(),
+                "refingerprint".to_string(),
+            ],
+            restartable_services: Vec::new(),
+            min_collect_gap_seconds: 60,
+        }
+    }
+}
+
+/// Persistent WebSocket connection for real-time control, with HTTP as fallback
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct WebSocketConfig {
+    pub enabled: bool,
+    /// Defaults to the heartbeat URL with `/api/heartbeat/` replaced by `/ws/agents/`
+    pub url: Option<String>,
+    pub reconnect_initial_ms: u64,
+    pub reconnect_max_ms: u64,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            enabled: false,
+            url: None,
+            reconnect_initial_ms: 1000,
+            reconnect_max_ms: 60000,
+        }
+    }
+}
+
+/// MQTT broker settings, used when `server.transport = "mqtt"`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub broker_host: String,
+    pub broker_port: u16,
+    /// Defaults to `device-agent-<agent_id>`
+    pub client_id: Option<String>,
+    /// Topic templates; `{agent_id}` is substituted
+    pub heartbeat_topic: String,
+    pub status_topic: String,
+    pub qos: u8,
+    pub keep_alive_seconds: u64,
+    pub tls: bool,
+    /// PEM CA bundle; the system trust store is used when not set
+    pub ca_file: Option<String>,
+    /// PKCS#12 client certificate for mutual TLS
+    pub client_pkcs12_file: Option<String>,
+    pub client_pkcs12_password: Option<String>,
+    /// Defaults to the agent ID
+    pub username: Option<String>,
+    /// Authenticate with the stored API token as password
+    pub password_from_token: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            broker_host: "localhost".to_string(),
+            broker_port: 1883,
+            client_id: None,
+            heartbeat_topic: "devices/{agent_id}/heartbeat".to_string(),
+            status_topic: "devices/{agent_id}/status".to_string(),
+            qos: 1,
+            keep_alive_seconds: 30,
+            tls: false,
+            ca_file: None,
+            client_pkcs12_file: None,
+            client_pkcs12_password: None,
+            username: None,
+            password_from_token: true,
+        }
+    }
+}
+
+/// Local HTTP listener serving the latest collection in Prometheus format
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ExporterConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl Default for ExporterConfig {
+    fn default() -> Self {
+        ExporterConfig {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 9100,
+        }
+    }
+}
+
+/// OpenTelemetry collector (OTLP/HTTP, JSON encoding) for metrics and agent logs
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct OtlpConfig {
+    pub enabled: bool,
+    /// Base URL; `/v1/metrics` and `/v1/logs` are appended
+    pub endpoint: String,
+    pub timeout_seconds: u64,
+    /// Extra request headers, e.g. for collector authentication
+    pub headers: HashMap<String, String>,
+    pub export_logs: bool,
+    /// Minimum level of exported log records
+    pub log_level: String,
+    /// Oldest records are dropped while the collector is unreachable
+    pub max_buffered_logs: usize,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            enabled: false,
+            endpoint: "http://localhost:4318".to_string(),
+            timeout_seconds: 10,
+            headers: HashMap::new(),
+            export_logs: true,
+            log_level: "info".to_string(),
+            max_buffered_logs: 1000,
+        }
+    }
+}
+
+/// Local SQLite store of past snapshots, queried with `device-agent history`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    /// Defaults to `<output_directory>/history.db`
+    pub path: Option<String>,
+    /// Delete snapshots older than this (0 = keep forever)
+    pub max_age_days: u64,
+    /// Keep at most this many snapshots (0 = unlimited)
+    pub max_snapshots: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            enabled: false,
+            path: None,
+            max_age_days: 30,
+            max_snapshots: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON
+    CycloneDx,
+    /// SPDX 2.3 JSON
+    Spdx,
+}
+
+/// Software bill of materials rewritten after every collection
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SbomConfig {
+    pub enabled: bool,
+    pub formats: Vec<SbomFormat>,
+    /// Defaults to `<output_directory>/sbom`
+    pub directory: Option<String>,
+}
+
+impl Default for SbomConfig {
+    fn default() -> Self {
+        SbomConfig {
+            enabled: false,
+            formats: vec![SbomFormat::CycloneDx, SbomFormat::Spdx],
+            directory: None,
+        }
+    }
+}
+
+/// Offline matching of installed packages against local advisory feeds
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct VulnerabilityConfig {
+    pub enabled: bool,
+    /// OSV JSON files/directories or Debian security tracker JSON files
+    pub feeds: Vec<String>,
+    /// Release codename for Debian tracker feeds; read from os-release when not set
+    pub debian_release: Option<String>,
+}
+
+/// Compliance policies evaluated against every snapshot
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct PolicyConfig {
+    pub enabled: bool,
+    /// Policy files or directories of `*.toml` / `*.yaml` policies
+    pub paths: Vec<String>,
+}
+
+/// Software allow/deny lists checked against the package and service inventory
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SoftwareListConfig {
+    pub enabled: bool,
+    /// List files or directories of `*.toml` / `*.yaml` lists
+    pub paths: Vec<String>,
+    /// Re-check between collections and send right away on new violations (0 = off)
+    pub check_interval_seconds: u64,
+}
+
+impl Default for SoftwareListConfig {
+    fn default() -> Self {
+        SoftwareListConfig {
+            enabled: false,
+            paths: Vec::new(),
+            check_interval_seconds: 60,
+        }
+    }
+}
+
+/// Built-in CIS Level 1 checks (Linux), run on their own schedule
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CisConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    /// Directories searched for world-writable files
+    pub world_writable_paths: Vec<String>,
+    /// Directory levels below each path that are searched
+    pub world_writable_max_depth: usize,
+    /// The search stops after examining this many entries
+    pub world_writable_max_entries: usize,
+}
+
+impl Default for CisConfig {
+    fn default() -> Self {
+        CisConfig {
+            enabled: false,
+            interval_seconds: 86400,
+            world_writable_paths: ["/etc", "/usr", "/bin", "/sbin", "/boot", "/opt", "/var"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            world_writable_max_depth: 8,
+            world_writable_max_entries: 200_000,
+        }
+    }
+}
+
+/// Local thresholds checked by a fast sampler between collections
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub enabled: bool,
+    pub sample_interval_seconds: u64,
+    /// Minimum time between two notifications of the same rule
+    pub cooldown_seconds: u64,
+    /// Send alerts to the `[server]` backend as `alert` payloads
+    pub send_to_backend: bool,
+    /// Also POST alerts as JSON to this URL
+    pub webhook_url: Option<String>,
+    pub webhook_timeout_seconds: u64,
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        AlertConfig {
+            enabled: false,
+            sample_interval_seconds: 15,
+            cooldown_seconds: 300,
+            send_to_backend: true,
+            webhook_url: None,
+            webhook_timeout_seconds: 10,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(default = "default_alert_severity")]
+    pub severity: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+}
+
+fn default_alert_severity() -> String {
+    "warning".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Fires when the metric goes above `above` (or below `below`) and
+    /// resolves once it is back past `clear` (defaults to the trigger value)
+    Threshold {
+        metric: AlertMetric,
+        /// Disk metrics only; all disks are checked when not set
+        #[serde(default)]
+        mount: Option<String>,
+        #[serde(default)]
+        above: Option<f64>,
+        #[serde(default)]
+        below: Option<f64>,
+        #[serde(default)]
+        clear: Option<f64>,
+    },
+    /// Fires when a service that was running is no longer running
+    ServiceDown { service: String },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    MemoryAvailablePercent,
+    DiskFreePercent,
+    CpuUsagePercent,
+    #[serde(rename = "load_1m")]
+    Load1m,
+    #[serde(rename = "load_5m")]
+    Load5m,
+    #[serde(rename = "load_15m")]
+    Load15m,
+}
+
+/// Output for collected snapshots, written after the primary backend
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SinkConfig {
+    /// Shown in logs; defaults to the sink type
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub kind: SinkKind,
+    /// Overrides `[retry]` for this sink
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkKind {
+    /// JSON files; defaults to `[output]` settings
+    File { directory: Option<String> },
+    /// POST the full snapshot as JSON to another endpoint
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        #[serde(default = "default_sink_timeout")]
+        timeout_seconds: u64,
+    },
+    /// OpenTelemetry collector configured in `[otlp]`
+    Otlp,
+    /// Prometheus exporter configured in `[exporter]`
+    Prometheus,
+    /// SQLite history configured in `[history]`
+    History,
+    /// RFC 5424 syslog messages for a SIEM
+    Syslog(SyslogConfig),
+    /// CycloneDX/SPDX documents configured in `[sbom]`
+    Sbom,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogProtocol {
+    #[default]
+    Udp,
+    Tcp,
+    Tls,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SyslogConfig {
+    pub host: String,
+    /// Defaults to 514 (UDP/TCP) or 6514 (TLS)
+    pub port: Option<u16>,
+    pub protocol: SyslogProtocol,
+    pub facility: String,
+    /// Private enterprise number used in structured-data IDs (`snapshot@<id>`)
+    pub enterprise_id: String,
+    /// PEM CA bundle for TLS; the system trust store is used when not set
+    pub ca_file: Option<String>,
+    /// Bounds each connect attempt, the TLS handshake and every write
+    pub timeout_seconds: u64,
 }
 
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        SyslogConfig {
+            host: "localhost".to_string(),
+            port: None,
+            protocol: SyslogProtocol::Udp,
+            facility: "user".to_string(),
+            enterprise_id: "32473".to_string(),
+            ca_file: None,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+impl SyslogConfig {
+    pub fn port(&self) -> u16 {
+        match (self.port, self.protocol) {
+            (Some(port), _) => port,
+            (None, SyslogProtocol::Tls) => 6514,
+            (None, _) => 514,
+        }
+    }
+}
+
+fn default_sink_timeout() -> u64 {
+    30
+}
+
+impl SinkConfig {
+    fn implicit(kind: SinkKind) -> Self {
+        SinkConfig { name: None, kind, retry: None }
+    }
+}
 
 impl Config {
-    /// Load configuration from file
+    /// Load configuration from file, then `config.d/*.toml` and the environment
     pub fn load(path: &str) -> Result<Self, String> {
-        // Check if file exists
-        if !Path::new(path).exists() {
+        Self::load_layered(path).map(|(config, _)| config)
+    }
+
+    /// Load configuration and list where it came from, lowest precedence first
+    ///
+    /// Drop-ins in the `config.d` directory next to `path` are merged in file
+    /// name order, then `DEVICE_AGENT__SECTION__KEY` variables are applied.
+    pub fn load_layered(path: &str) -> Result<(Self, Vec<String>), String> {
+        Self::load_from(path, std::env::vars())
+    }
+
+    fn load_from(path: &str, env: impl IntoIterator<Item = (String, String)>) -> Result<(Self, Vec<String>), String> {
+        let mut overrides: Vec<(String, String)> = env.into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        overrides.sort();
+
+        // Without a file, environment overrides apply to the defaults (containers)
+        let (mut merged, mut sources) = if Path::new(path).exists() {
+            (read_toml(Path::new(path))?, vec![path.to_string()])
+        } else if !overrides.is_empty() {
+            let defaults = toml::Value::try_from(Config::default())
+                .map_err(|e| format!("Failed to serialize default config: {}", e))?;
+            (defaults, vec![format!("defaults ({} not found)", path)])
+        } else {
             return Err(format!(
                 "Configuration file not found: {}\n\
-                Please copy config.example.toml to config.toml and customize it.",
+                Please copy config.example.toml to config.toml (or /etc/device-agent/config.toml) and customize it, \
+                or point --config or DEVICE_AGENT_CONFIG at it.",
                 path
             ));
-        }
+        };
 
-        // Read file
-        let contents = fs::read_to_string(path)
-            .map_err(|e| format!("Failed to read config file: {}", e))?;
+        let drop_in_dir = Path::new(path).parent().unwrap_or(Path::new("")).join(DROP_IN_DIR);
+        for drop_in in drop_in_files(&drop_in_dir)? {
+            merge(&mut merged, read_toml(&drop_in)?);
+            sources.push(drop_in.display().to_string());
+        }
+        for (name, value) in overrides {
+            apply_env_override(&mut merged, &name, &value)?;
+            sources.push(format!("env {}", name));
+        }
 
         // Parse TOML
-        let config: Config = toml::from_str(&contents)
+        let config: Config = merged.try_into()
             .map_err(|e| format!("Failed to parse config file: {}", e))?;
 
-        Ok(config)
+        Ok((config, sources))
+    }
+
+    /// Copy safe to share: passwords, header values, URL credentials and
+    /// query values, and webhook paths masked
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+
+        if config.mqtt.client_pkcs12_password.is_some() {
+            config.mqtt.client_pkcs12_password = Some(MASK.to_string());
+        }
+        for value in config.otlp.headers.values_mut() {
+            *value = MASK.to_string();
+        }
+        for sink in &mut config.sinks {
+            if let SinkKind::Http { url, headers, .. } = &mut sink.kind {
+                *url = redact_url(url);
+                for value in headers.values_mut() {
+                    *value = MASK.to_string();
+                }
+            }
+        }
+        config.server.url = redact_url(&config.server.url);
+        config.otlp.endpoint = redact_url(&config.otlp.endpoint);
+        if let Some(url) = &mut config.websocket.url {
+            *url = redact_url(url);
+        }
+        // Webhook URLs usually carry their secret in the path
+        if let Some(url) = &mut config.alerts.webhook_url {
+            if let Some((scheme, rest)) = url.split_once("://") {
+                let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+                let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+                *url = format!("{}://{}/{}", scheme, host, MASK);
+            }
+        }
+        config
     }
 
     /// Create default configuration
@@ -82,11 +762,20 @@ impl Config {
         Config {
             collection: CollectionConfig {
                 interval_seconds: 300,
+                include_services: true,
+                include_software: true,
+                track_changes: true,
             },
             output: OutputConfig {
                 output_directory: "./data".to_string(),
                 save_to_file: true,
                 timestamp_format: "%Y%m%d_%H%M%S".to_string(),
+                format: OutputFormat::Json,
+                rotation: Rotation::Daily,
+                max_file_bytes: default_max_file_bytes(),
+                max_files: 0,
+                max_age_days: 0,
+                compress_rotated: false,
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
@@ -107,7 +796,90 @@ impl Config {
                 enabled: false,
                 url: "http://localhost:8000/api/heartbeat/".to_string(),
                 timeout_seconds: 30,
-            }
+                transport: TransportKind::Http,
+                compression: Compression::None,
+                compression_threshold_bytes: default_compression_threshold(),
+            },
+            spool: SpoolConfig::default(),
+            delta: DeltaConfig::default(),
+            commands: CommandsConfig::default(),
+            websocket: WebSocketConfig::default(),
+            mqtt: MqttConfig::default(),
+            exporter: ExporterConfig::default(),
+            otlp: OtlpConfig::default(),
+            history: HistoryConfig::default(),
+            sbom: SbomConfig::default(),
+            vulnerabilities: VulnerabilityConfig::default(),
+            policy: PolicyConfig::default(),
+            software_lists: SoftwareListConfig::default(),
+            cis: CisConfig::default(),
+            alerts: AlertConfig::default(),
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Sinks to write to: `[[sinks]]` when given, otherwise the ones implied by
+    /// `output.save_to_file` and the `enabled` flags of `[otlp]`, `[exporter]` and `[history]`
+    pub fn effective_sinks(&self) -> Vec<SinkConfig> {
+        if !self.sinks.is_empty() {
+            return self.sinks.clone();
+        }
+
+        let mut sinks = Vec::new();
+        if self.output.save_to_file {
+            sinks.push(SinkConfig::implicit(SinkKind::File { directory: None }));
+        }
+        if self.otlp.enabled {
+            sinks.push(SinkConfig::implicit(SinkKind::Otlp));
+        }
+        if self.exporter.enabled {
+            sinks.push(SinkConfig::implicit(SinkKind::Prometheus));
+        }
+        if self.history.enabled {
+            sinks.push(SinkConfig::implicit(SinkKind::History));
+        }
+        if self.sbom.enabled {
+            sinks.push(SinkConfig::implicit(SinkKind::Sbom));
+        }
+        sinks
+    }
+
+    /// File holding the last snapshot acknowledged by the backend
+    pub fn delta_state_path(&self) -> String {
+        format!("{}/delta_state.json", self.output.output_directory)
+    }
+
+    /// Previous snapshot used to detect changes
+    pub fn change_state_path(&self) -> String {
+        format!("{}/last_snapshot.json", self.output.output_directory)
+    }
+
+    /// Latest CIS report; its timestamp also schedules the next run
+    pub fn cis_report_path(&self) -> String {
+        format!("{}/cis_report.json", self.output.output_directory)
+    }
+
+    /// SQLite database holding the snapshot history
+    pub fn history_path(&self) -> String {
+        match &self.history.path {
+            Some(path) => path.clone(),
+            None => format!("{}/history.db", self.output.output_directory),
+        }
+    }
+
+    /// Directory the periodic SBOM documents are written to
+    pub fn sbom_directory(&self) -> String {
+        match &self.sbom.directory {
+            Some(dir) => dir.clone(),
+            None => format!("{}/sbom", self.output.output_directory),
+        }
+    }
+
+    /// Directory holding spooled (not yet delivered) heartbeats
+    pub fn spool_directory(&self) -> String {
+        match &self.spool.directory {
+            Some(dir) => dir.clone(),
+            None => format!("{}/spool", self.output.output_directory),
         }
     }
 
@@ -119,12 +891,215 @@ impl Config {
                 .map_err(|e| format!("Failed to create output directory: {}", e))?;
         }
 
-        // Create log directory
-        if self.logging.file {
+        // Create log directory (also holds the command audit log)
+        if self.logging.file || self.commands.enabled {
             fs::create_dir_all(&self.logging.log_directory)
                 .map_err(|e| format!("Failed to create log directory: {}", e))?;
         }
 
+        // Create spool directory
+        if self.server.enabled && self.spool.enabled {
+            fs::create_dir_all(self.spool_directory())
+                .map_err(|e| format!("Failed to create spool directory: {}", e))?;
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Configuration file to load: `--config`, then `DEVICE_AGENT_CONFIG`, then the
+/// first of `/etc/device-agent/config.toml` and `./config.toml` that exists
+pub fn discover(explicit: Option<&str>) -> String {
+    if let Some(path) = explicit {
+        return path.to_string();
+    }
+    if let Some(path) = std::env::var(CONFIG_ENV).ok().filter(|p| !p.is_empty()) {
+        return path;
+    }
+    CONFIG_CANDIDATES.iter()
+        .find(|candidate| Path::new(candidate).is_file())
+        .unwrap_or(&"config.toml")
+        .to_string()
+}
+
+fn read_toml(path: &Path) -> Result<toml::Value, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    toml::from_str(&contents)
+        .map_err(|e| format!("Failed to parse config file {}: {}", path.display(), e))
+}
+
+/// `*.toml` files in `dir`, sorted by name; none if the directory is missing
+fn drop_in_files(dir: &Path) -> Result<Vec<std::path::PathBuf>, String> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Merge tables recursively; any other value in `overlay` replaces the base
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Set `section.key` from `DEVICE_AGENT__SECTION__KEY=value`
+///
+/// The value is read as a TOML literal (`true`, `60`, `["a", "b"]`) and falls
+/// back to a plain string. It stays a string where the key already holds one,
+/// or where the field does not accept the literal (`USERNAME=1234`).
+fn apply_env_override(config: &mut toml::Value, name: &str, value: &str) -> Result<(), String> {
+    let keys: Vec<String> = name[ENV_PREFIX.len()..].split("__").map(str::to_lowercase).collect();
+    if keys.iter().any(String::is_empty) {
+        return Err(format!("Invalid configuration override {}: expected DEVICE_AGENT__SECTION__KEY", name));
+    }
+
+    let text = toml::Value::String(value.to_string());
+    let literal = match lookup(config, &keys) {
+        Some(toml::Value::String(_)) => None,
+        _ => toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut literal| literal.remove("value")),
+    };
+    let Some(literal) = literal else {
+        return set_key(config, &keys, text).map_err(|e| format!("Invalid configuration override {}: {}", name, e));
+    };
+
+    let mut with_literal = config.clone();
+    set_key(&mut with_literal, &keys, literal).map_err(|e| format!("Invalid configuration override {}: {}", name, e))?;
+    if with_literal.clone().try_into::<Config>().is_err() {
+        let mut with_text = config.clone();
+        set_key(&mut with_text, &keys, text).map_err(|e| format!("Invalid configuration override {}: {}", name, e))?;
+        if with_text.clone().try_into::<Config>().is_ok() {
+            *config = with_text;
+            return Ok(());
+        }
+    }
+    *config = with_literal;
+    Ok(())
+}
+
+fn lookup<'a>(config: &'a toml::Value, keys: &[String]) -> Option<&'a toml::Value> {
+    keys.iter().try_fold(config, |value, key| value.get(key.as_str()))
+}
+
+fn set_key(config: &mut toml::Value, keys: &[String], value: toml::Value) -> Result<(), String> {
+    let mut table = config.as_table_mut().ok_or("configuration root is not a table")?;
+    let (last, sections) = keys.split_last().expect("split yields at least one key");
+    for section in sections {
+        table = table.entry(section.as_str())
+            .or_insert_with(|| toml::Value::Table(toml::map::Map::new()))
+            .as_table_mut()
+            .ok_or_else(|| format!("{} is not a section", section))?;
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// `url` without credentials in the authority, with query values and any fragment masked
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let (rest, fragment) = match rest.split_once('#') {
+        Some((rest, _)) => (rest, format!("#{}", MASK)),
+        None => (rest, String::new()),
+    };
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, query)) => {
+            let pairs: Vec<String> = query.split('&')
+                .map(|pair| match pair.split_once('=') {
+                    Some((key, _)) => format!("{}={}", key, MASK),
+                    None => pair.to_string(),
+                })
+                .collect();
+            (rest, format!("?{}", pairs.join("&")))
+        }
+        None => (rest, String::new()),
+    };
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    format!("{}://{}{}{}{}", scheme, host, path, query, fragment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_merges_drop_ins_and_env_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, include_str!("../config.example.toml")).unwrap();
+        fs::create_dir(dir.path().join("config.d")).unwrap();
+        fs::write(dir.path().join("config.d/20-site.toml"), "[server]\nurl = \"https://site.example.com/api/heartbeat/\"\n").unwrap();
+        fs::write(dir.path().join("config.d/10-base.toml"),
+            "[server]\nurl = \"https://base.example.com/api/heartbeat/\"\ntimeout_seconds = 5\n").unwrap();
+        fs::write(dir.path().join("config.d/README"), "not toml").unwrap();
+
+        let env = [
+            ("DEVICE_AGENT__COLLECTION__INTERVAL_SECONDS", "90"),
+            ("DEVICE_AGENT__SERVER__ENABLED", "true"),
+            ("DEVICE_AGENT__AGENT__AGENT_ID", "1234"),
+            ("DEVICE_AGENT_CONFIG", "ignored"),
+            ("PATH", "/usr/bin"),
+        ].map(|(k, v)| (k.to_string(), v.to_string()));
+        let (config, sources) = Config::load_from(path.to_str().unwrap(), env).unwrap();
+
+        assert_eq!(config.server.url, "https://site.example.com/api/heartbeat/", "Later drop-ins win");
+        assert_eq!(config.server.timeout_seconds, 5, "Drop-ins merge keys, not whole sections");
+        assert_eq!(config.collection.interval_seconds, 90);
+        assert!(config.server.enabled);
+        assert_eq!(config.agent.agent_id, "1234", "String keys stay strings");
+        assert_eq!(sources.len(), 6, "{:?}", sources);
+        assert!(sources[1].ends_with("10-base.toml") && sources[2].ends_with("20-site.toml"), "{:?}", sources);
+        assert_eq!(sources[3], "env DEVICE_AGENT__AGENT__AGENT_ID");
+
+        let bad = [("DEVICE_AGENT__SERVER__URL__HOST".to_string(), "x".to_string())];
+        assert!(Config::load_from(path.to_str().unwrap(), bad).is_err(), "Cannot descend into a value");
+    }
+
+    #[test]
+    fn test_env_overrides_for_missing_keys_and_missing_file() {
+        let env = [
+            ("DEVICE_AGENT__MQTT__USERNAME", "1234"),
+            ("DEVICE_AGENT__MQTT__KEEP_ALIVE_SECONDS", "45"),
+            ("DEVICE_AGENT__SERVER__ENABLED", "true"),
+        ].map(|(k, v)| (k.to_string(), v.to_string()));
+
+        // Keys the file does not set get the type the field expects
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, include_str!("../config.example.toml")).unwrap();
+        let (config, _) = Config::load_from(path.to_str().unwrap(), env.clone()).unwrap();
+        assert_eq!(config.mqtt.username.as_deref(), Some("1234"), "Numbers stay strings for string fields");
+        assert_eq!(config.mqtt.keep_alive_seconds, 45);
+        assert!(config.server.enabled);
+
+        // No file: the overrides apply to the defaults
+        let missing = dir.path().join("missing.toml");
+        let (config, sources) = Config::load_from(missing.to_str().unwrap(), env).unwrap();
+        assert_eq!(config.mqtt.username.as_deref(), Some("1234"));
+        assert!(config.server.enabled);
+        assert!(sources[0].starts_with("defaults"), "{:?}", sources);
+        assert!(Config::load_from(missing.to_str().unwrap(), Vec::new()).is_err(), "Nothing to load");
+    }
+}
@@ -0,0 +1,103 @@
+use crate::config::Config;
+use crate::crypto;
+use crate::models::HeartbeatResponse;
+use log::{info, debug, warn, error};
+
+/// Shortest and longest collection interval the backend may set
+const MIN_INTERVAL_SECONDS: u64 = 10;
+const MAX_INTERVAL_SECONDS: u64 = 86_400;
+
+/// Apply the directives carried by a heartbeat response
+///
+/// Changes only affect the running agent; `config.toml` is left untouched,
+/// so a restart returns to the locally configured behaviour.
+pub fn apply_response(config: &mut Config, response: &HeartbeatResponse) {
+    if let Some(device_id) = response.device_id {
+        debug!("Backend device ID: {}", device_id);
+    }
+    if let Some(message) = &response.message {
+        debug!("Backend message: {}", message);
+    }
+    if let Some(device) = &response.device {
+        debug!("Backend device summary: {:?}", device);
+    }
+
+    if let Some(interval) = response.collection_interval_seconds {
+        set_interval(config, interval);
+    }
+
+    for (collector, enabled) in &response.collectors {
+        set_collector(config, collector, *enabled);
+    }
+
+    if let Some(rotation) = &response.token_rotation {
+        if let Some(message) = &rotation.message {
+            info!("Token rotation: {}", message);
+        }
+
+        match &rotation.new_token {
+            Some(token) => match crypto::save_token(token) {
+                Ok(_) => info!("✓ API token rotated by backend"),
+                Err(e) => error!("✗ Failed to store rotated API token: {}", e),
+            },
+            None => {
+                warn!("Backend is rotating this agent's API token");
+                warn!("   Check registration status: device-agent status");
+            }
+        }
+    }
+}
+
+fn set_interval(config: &mut Config, interval: u64) {
+    let interval = interval.clamp(MIN_INTERVAL_SECONDS, MAX_INTERVAL_SECONDS);
+    if interval != config.collection.interval_seconds {
+        info!(
+            "Backend changed collection interval: {}s -> {}s",
+            config.collection.interval_seconds, interval
+        );
+        config.collection.interval_seconds = interval;
+    }
+}
+
+fn set_collector(config: &mut Config, collector: &str, enabled: bool) {
+    let setting = match collector {
+        "services" => &mut config.collection.include_services,
+        "software" => &mut config.collection.include_software,
+        _ => {
+            warn!("Backend referenced unknown collector: {}", collector);
+            return;
+        }
+    };
+
+    if *setting != enabled {
+        info!(
+            "Backend {} collector: {}",
+            if enabled { "enabled" } else { "disabled" },
+            collector
+        );
+        *setting = enabled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_interval_and_collectors() {
+        let mut config = Config::default();
+        let response = HeartbeatResponse {
+            collection_interval_seconds: Some(1),
+            collectors: [("software".to_string(), false), ("bogus".to_string(), false)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        apply_response(&mut config, &response);
+
+        assert_eq!(config.collection.interval_seconds, MIN_INTERVAL_SECONDS, "Interval should be clamped");
+        assert!(!config.collection.include_software);
+        assert!(config.collection.include_services, "Unknown collectors must not affect others");
+    }
+}
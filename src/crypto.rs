@@ -141,7 +141,7 @@ pub fn load_token() -> Result<String, String> {
     } else if Path::new(DEV_TOKEN_FILE).exists() {
         DEV_TOKEN_FILE
     } else {
-        return Err("Token file not found. Please run: device-agent --register <token>".to_string());
+        return Err("Token file not found. Please run: device-agent register <token>".to_string());
     };
     
     // Read encrypted token
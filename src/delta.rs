@@ -0,0 +1,399 @@
+use crate::config::DeltaConfig;
+use crate::models::{HeartbeatResponse, SystemInfo};
+use log::{info, debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Fields sent as-is with every delta: identity so the backend can route and
+/// authenticate it, plus the change events and disabled collectors of this collection
+const IDENTITY_FIELDS: &[&str] = &["agent_id", "agent_name", "device_fingerprint", "collected_at", "events", "not_collected"];
+
+/// Snapshot the backend has acknowledged, persisted across restarts
+#[derive(Serialize, Deserialize)]
+struct AckedSnapshot {
+    version: u64,
+    snapshot: Value,
+}
+
+/// A heartbeat body ready to be posted
+pub struct Payload {
+    pub body: Value,
+    pub is_delta: bool,
+}
+
+/// Tracks the last acknowledged snapshot and decides between full and delta heartbeats
+///
+/// Protocol: the backend acknowledges a heartbeat with `snapshot_version` in its
+/// response. Deltas reference that version as `base_version`; the backend can
+/// answer `full_snapshot_required: true` (or reject the delta) to force a resync.
+/// Without a `snapshot_version` in the ack the agent keeps sending full snapshots.
+pub struct DeltaTracker {
+    config: DeltaConfig,
+    state_path: PathBuf,
+    acked: Option<AckedSnapshot>,
+    since_full: u32,
+    force_full: bool,
+}
+
+impl DeltaTracker {
+    /// Load the last acknowledged snapshot from `state_path` if present
+    pub fn load(state_path: &str, config: &DeltaConfig) -> Self {
+        let acked = if config.enabled {
+            match read_state(Path::new(state_path)) {
+                Ok(acked) => acked,
+                Err(e) => {
+                    warn!("Ignoring delta state {}: {}", state_path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(acked) = &acked {
+            debug!("Loaded acknowledged snapshot version {}", acked.version);
+        }
+
+        DeltaTracker {
+            config: config.clone(),
+            state_path: PathBuf::from(state_path),
+            acked,
+            since_full: 0,
+            force_full: false,
+        }
+    }
+
+    /// Build the body for the next heartbeat
+    pub fn build_payload(&self, info: &SystemInfo) -> Result<Payload, String> {
+        let mut current = serde_json::to_value(info)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+
+        if !self.config.enabled {
+            return Ok(Payload { body: current, is_delta: false });
+        }
+
+        let acked = match &self.acked {
+            Some(acked) if !self.full_snapshot_due() => acked,
+            _ => {
+                if let Value::Object(map) = &mut current {
+                    map.insert("payload_type".to_string(), json!("full"));
+                }
+                return Ok(Payload { body: current, is_delta: false });
+            }
+        };
+
+        let mut body = Map::new();
+        body.insert("payload_type".to_string(), json!("delta"));
+        body.insert("base_version".to_string(), json!(acked.version));
+        for field in IDENTITY_FIELDS {
+            if let Some(value) = current.get(*field) {
+                body.insert(field.to_string(), value.clone());
+            }
+        }
+        body.insert("changes".to_string(), Value::Object(diff(&acked.snapshot, &current)));
+
+        Ok(Payload { body: Value::Object(body), is_delta: true })
+    }
+
+    /// Record the backend's response to a successfully sent heartbeat
+    pub fn acknowledge(&mut self, info: &SystemInfo, payload: &Payload, response: &HeartbeatResponse) {
+        if !self.config.enabled {
+            return;
+        }
+
+        if payload.is_delta {
+            self.since_full += 1;
+        } else {
+            self.since_full = 0;
+        }
+        self.force_full = response.full_snapshot_required;
+
+        let version = match response.snapshot_version {
+            Some(version) => version,
+            None => {
+                // Backend without delta support: keep sending full snapshots
+                if self.acked.take().is_some() {
+                    info!("Backend no longer reports snapshot versions, sending full snapshots");
+                }
+                self.remove_state();
+                return;
+            }
+        };
+
+        let mut snapshot = match serde_json::to_value(info) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Failed to record acknowledged snapshot: {}", e);
+                self.acked = None;
+                return;
+            }
+        };
+
+        // Sections that were not collected were not sent; keep what the backend last saw
+        if let (Some(previous), Value::Object(map)) = (&self.acked, &mut snapshot) {
+            for field in &info.not_collected {
+                match previous.snapshot.get(field) {
+                    Some(value) => map.insert(field.clone(), value.clone()),
+                    None => map.remove(field),
+                };
+            }
+        }
+
+        self.acked = Some(AckedSnapshot { version, snapshot });
+        if let Err(e) = self.save_state() {
+            warn!("Failed to persist delta state: {}", e);
+        }
+    }
+
+    /// Send a full snapshot next time, e.g. after a failed or rejected delta
+    pub fn request_full(&mut self) {
+        self.force_full = true;
+    }
+
+    fn full_snapshot_due(&self) -> bool {
+        self.force_full
+            || (self.config.full_snapshot_every > 0 && self.since_full + 1 >= self.config.full_snapshot_every)
+    }
+
+    fn save_state(&self) -> Result<(), String> {
+        if let Some(parent) = self.state_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create state directory: {}", e))?;
+        }
+
+        let json = serde_json::to_vec(&self.acked)
+            .map_err(|e| format!("Failed to serialize delta state: {}", e))?;
+        let tmp_path = self.state_path.with_extension("tmp");
+        fs::write(&tmp_path, json)
+            .map_err(|e| format!("Failed to write delta state: {}", e))?;
+        fs::rename(&tmp_path, &self.state_path)
+            .map_err(|e| format!("Failed to commit delta state: {}", e))
+    }
+
+    fn remove_state(&self) {
+        if self.state_path.exists() {
+            if let Err(e) = fs::remove_file(&self.state_path) {
+                warn!("Failed to remove delta state: {}", e);
+            }
+        }
+    }
+}
+
+fn read_state(path: &Path) -> Result<Option<AckedSnapshot>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| format!("Failed to parse file: {}", e))
+}
+
+/// Compare two snapshots section by section
+///
+/// Lists become `{"added": [...], "removed": [...]}`, any other changed
+/// section is sent with its new value. Unchanged sections are omitted, and so
+/// are sections listed in `not_collected`. A section missing from `current`
+/// (empty lists are not serialized) is sent as removed: lists with all their
+/// items under `removed`, anything else as `null`.
+fn diff(previous: &Value, current: &Value) -> Map<String, Value> {
+    let mut changes = Map::new();
+
+    let current = match current.as_object() {
+        Some(current) => current,
+        None => return changes,
+    };
+    let not_collected: Vec<&str> = current.get("not_collected")
+        .and_then(|fields| fields.as_array())
+        .map(|fields| fields.iter().filter_map(|field| field.as_str()).collect())
+        .unwrap_or_default();
+
+    for (key, value) in current {
+        if IDENTITY_FIELDS.contains(&key.as_str()) || not_collected.contains(&key.as_str()) {
+            continue;
+        }
+
+        let old = previous.get(key);
+        if old == Some(value) {
+            continue;
+        }
+
+        match (old.and_then(|v| v.as_array()), value.as_array()) {
+            (Some(old_items), Some(new_items)) => {
+                let added = difference(new_items, old_items);
+                let removed = difference(old_items, new_items);
+                if !added.is_empty() || !removed.is_empty() {
+                    changes.insert(key.clone(), json!({ "added": added, "removed": removed }));
+                }
+            }
+            _ => {
+                changes.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    let removed = previous.as_object().into_iter().flatten()
+        .filter(|(key, _)| !current.contains_key(*key))
+        .filter(|(key, _)| !IDENTITY_FIELDS.contains(&key.as_str()) && !not_collected.contains(&key.as_str()));
+    for (key, value) in removed {
+        let change = match value.as_array() {
+            Some(items) => json!({ "added": [], "removed": items }),
+            None => Value::Null,
+        };
+        changes.insert(key.clone(), change);
+    }
+
+    changes
+}
+
+/// Items of `items` not present in `other` (package lists run into the thousands)
+fn difference<'a>(items: &'a [Value], other: &[Value]) -> Vec<&'a Value> {
+    let other: HashSet<String> = other.iter().map(|v| v.to_string()).collect();
+    items.iter().filter(|v| !other.contains(&v.to_string())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_tracker(dir: &Path, full_snapshot_every: u32) -> DeltaTracker {
+        let config = DeltaConfig { enabled: true, full_snapshot_every };
+        DeltaTracker::load(dir.join("delta_state.json").to_str().unwrap(), &config)
+    }
+
+    fn ack(version: Option<u64>, full_snapshot_required: bool) -> HeartbeatResponse {
+        HeartbeatResponse { snapshot_version: version, full_snapshot_required, ..Default::default() }
+    }
+
+    fn info(memory_available: u64) -> SystemInfo {
+        SystemInfo { agent_id: "agent-001".to_string(), memory_available, ..Default::default() }
+    }
+
+    /// Build, "send" and acknowledge one heartbeat; returns whether it was a delta
+    fn exchange(tracker: &mut DeltaTracker, info: &SystemInfo, response: &HeartbeatResponse) -> bool {
+        let payload = tracker.build_payload(info).unwrap();
+        tracker.acknowledge(info, &payload, response);
+        payload.is_delta
+    }
+
+    #[test]
+    fn test_full_snapshot_every_n_heartbeats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tracker = new_tracker(dir.path(), 3);
+
+        let kinds: Vec<bool> = (0..7u64).map(|n| exchange(&mut tracker, &info(n), &ack(Some(n + 1), false))).collect();
+        assert_eq!(kinds, vec![false, true, true, false, true, true, false], "Every third heartbeat is full");
+
+        let mut tracker = new_tracker(dir.path(), 0);
+        let kinds: Vec<bool> = (0..4u64).map(|n| exchange(&mut tracker, &info(n), &ack(Some(n + 10), false))).collect();
+        assert_eq!(kinds, vec![true, true, true, true], "0 only sends full snapshots on request, state survives a restart");
+    }
+
+    #[test]
+    fn test_rejected_delta_forces_full_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tracker = new_tracker(dir.path(), 0);
+        exchange(&mut tracker, &info(100), &ack(Some(1), false));
+
+        let payload = tracker.build_payload(&info(80)).unwrap();
+        assert!(payload.is_delta);
+        assert_eq!(payload.body["base_version"], json!(1));
+        assert_eq!(payload.body["changes"], json!({ "memory_available": 80 }));
+
+        // Delivery failed or the backend rejected the delta
+        tracker.request_full();
+        assert!(!tracker.build_payload(&info(80)).unwrap().is_delta);
+
+        // The backend can also ask for one in its acknowledgement
+        assert!(!exchange(&mut tracker, &info(80), &ack(Some(2), false)));
+        assert!(exchange(&mut tracker, &info(70), &ack(Some(3), true)));
+        assert!(!exchange(&mut tracker, &info(70), &ack(Some(4), false)));
+        assert!(exchange(&mut tracker, &info(60), &ack(Some(5), false)));
+    }
+
+    #[test]
+    fn test_snapshot_version_drives_deltas() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tracker = new_tracker(dir.path(), 0);
+        let state = dir.path().join("delta_state.json");
+
+        assert!(!exchange(&mut tracker, &info(100), &ack(None, false)));
+        assert!(!exchange(&mut tracker, &info(90), &ack(None, false)), "No version, no deltas");
+        assert!(!state.exists());
+
+        exchange(&mut tracker, &info(80), &ack(Some(7), false));
+        assert!(state.exists(), "The acknowledged snapshot is persisted");
+        let reloaded = new_tracker(dir.path(), 0);
+        assert_eq!(reloaded.build_payload(&info(80)).unwrap().body["base_version"], json!(7));
+
+        assert!(exchange(&mut tracker, &info(70), &ack(None, false)));
+        assert!(!tracker.build_payload(&info(70)).unwrap().is_delta, "A backend that drops versions gets full snapshots");
+        assert!(!state.exists());
+    }
+
+    #[test]
+    fn test_diff_lists_and_scalars() {
+        let previous = json!({
+            "agent_id": "agent-001",
+            "hostname": "box",
+            "memory_available": 100,
+            "services": ["cron", "sshd"],
+            "installed_software": ["bash", "vim"],
+        });
+        let current = json!({
+            "agent_id": "agent-001",
+            "hostname": "box",
+            "memory_available": 80,
+            "services": ["sshd", "nginx"],
+            "installed_software": ["vim", "bash"],
+        });
+
+        let changes = diff(&previous, &current);
+
+        assert_eq!(changes.get("memory_available"), Some(&json!(80)));
+        assert_eq!(
+            changes.get("services"),
+            Some(&json!({ "added": ["nginx"], "removed": ["cron"] }))
+        );
+        assert!(!changes.contains_key("hostname"), "Unchanged fields should be omitted");
+        assert!(!changes.contains_key("installed_software"), "Reordered lists are unchanged");
+        assert!(!changes.contains_key("agent_id"), "Identity fields are not part of the diff");
+
+        let disabled = json!({ "services": [], "not_collected": ["services"] });
+        assert!(!diff(&previous, &disabled).contains_key("services"), "A disabled collector is not a removal");
+    }
+
+    #[test]
+    fn test_uncollected_sections_keep_the_acknowledged_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tracker = new_tracker(dir.path(), 0);
+        let with_services = |services: &[&str]| SystemInfo {
+            services: services.iter().map(|s| s.to_string()).collect(),
+            ..info(100)
+        };
+
+        exchange(&mut tracker, &with_services(&["cron", "sshd"]), &ack(Some(1), false));
+        let disabled = SystemInfo { not_collected: vec!["services".to_string()], ..with_services(&[]) };
+        exchange(&mut tracker, &disabled, &ack(Some(2), false));
+
+        // cron stopped while service collection was off
+        let payload = tracker.build_payload(&with_services(&["sshd"])).unwrap();
+        assert_eq!(payload.body["changes"]["services"], json!({ "added": [], "removed": ["cron"] }));
+    }
+
+    #[test]
+    fn test_cleared_sections_are_sent_as_removed() {
+        let violation = json!({ "list": "corp", "rule": "torrent", "subject": "transmission" });
+        let previous = json!({ "hostname": "box", "software_violations": [violation], "cpu_info": "x86" });
+        // Empty lists are skipped when serializing, so the key disappears
+        let current = json!({ "hostname": "box" });
+
+        let changes = diff(&previous, &current);
+        assert_eq!(changes.get("software_violations"), Some(&json!({ "added": [], "removed": [violation] })));
+        assert_eq!(changes.get("cpu_info"), Some(&Value::Null));
+        assert!(!changes.contains_key("hostname"));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::collector::collect_all_info;
+use crate::config::{Config, TransportKind};
+use crate::crypto;
+use crate::fingerprint;
+use crate::sender;
+use native_tls::TlsConnector;
+use reqwest::Url;
+use serde::Serialize;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// One diagnostic step
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check { name, status: CheckStatus::Pass, detail: detail.into(), hint: None }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check { name, status: CheckStatus::Fail, detail: detail.into(), hint: Some(hint.into()) }
+    }
+
+    fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+        Check { name, status: CheckStatus::Skip, detail: detail.into(), hint: None }
+    }
+}
+
+/// Backend host, port and whether it speaks TLS
+#[derive(Debug, PartialEq)]
+struct Endpoint {
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+fn endpoint(url: &str) -> Result<Endpoint, String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    let tls = matches!(url.scheme(), "https" | "wss");
+    let host = url.host_str()
+        .ok_or_else(|| format!("No host in {}", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port().unwrap_or(if tls { 443 } else { 80 });
+    Ok(Endpoint { host, port, tls })
+}
+
+/// Run every step in order; later steps are skipped when what they need failed
+///
+/// `loaded` is the result of loading `config_path`; on failure the defaults are
+/// diagnosed instead. The last step sends one regular heartbeat.
+pub fn run(config_path: &str, loaded: Result<Config, String>) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let config = match loaded {
+        Ok(config) => {
+            checks.push(Check::pass("config", format!("Loaded {}", config_path)));
+            config
+        }
+        Err(e) => {
+            checks.push(Check::fail("config", format!("{} (diagnosing the defaults)", e),
+                "Create it from config.example.toml or pass --config <path>"));
+            Config::default()
+        }
+    };
+
+    let token = match crypto::load_token() {
+        Ok(token) => {
+            checks.push(Check::pass("token", format!("Decrypted {} characters from {}",
+                token.len(), crypto::get_token_location())));
+            true
+        }
+        Err(e) if !crypto::has_token() => {
+            checks.push(Check::fail("token", e,
+                "Register with: device-agent init (then device-agent status) or device-agent register <token>"));
+            false
+        }
+        Err(e) => {
+            checks.push(Check::fail("token", format!("Token file exists but cannot be decrypted: {}", e),
+                "The key may belong to another machine or user; re-register with: device-agent register <token>"));
+            false
+        }
+    };
+
+    let fingerprint_index = checks.len();
+    match fingerprint::generate_fingerprint() {
+        Ok(fp) => checks.push(Check::pass("fingerprint", fp)),
+        Err(e) => checks.push(Check::fail("fingerprint", e,
+            "Run as a user that can read the machine ID and hardware serials")),
+    }
+
+    let reachable = network_checks(&config, &mut checks);
+
+    if !config.server.enabled {
+        checks.push(Check::skip("heartbeat", "server.enabled = false"));
+    } else if !token || !reachable {
+        checks.push(Check::skip("heartbeat", "Needs a token and a reachable backend"));
+    } else {
+        let heartbeat = heartbeat_check(&config);
+        if heartbeat.status == CheckStatus::Fail && is_fingerprint_mismatch(&heartbeat.detail) {
+            let check = &mut checks[fingerprint_index];
+            check.status = CheckStatus::Fail;
+            check.detail = format!("{} is not the fingerprint the token is bound to", check.detail);
+            check.hint = Some("If the agent moved to new hardware: device-agent unregister, then device-agent init \
+                and ask for approval. Otherwise treat the token as compromised and revoke it.".to_string());
+        }
+        checks.push(heartbeat);
+    }
+    checks
+}
+
+/// DNS, TCP and TLS against the server URL; true when the backend is reachable
+fn network_checks(config: &Config, checks: &mut Vec<Check>) -> bool {
+    let endpoint = match config.server.transport {
+        TransportKind::Mqtt => Ok(Endpoint {
+            host: config.mqtt.broker_host.clone(),
+            port: config.mqtt.broker_port,
+            tls: config.mqtt.tls,
+        }),
+        _ => endpoint(&config.server.url),
+    };
+    let endpoint = match endpoint {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            checks.push(Check::fail("dns", e, "Fix server.url in the configuration"));
+            checks.push(Check::skip("tcp", "No valid URL"));
+            checks.push(Check::skip("tls", "No valid URL"));
+            return false;
+        }
+    };
+
+    let addresses: Vec<SocketAddr> = match (endpoint.host.as_str(), endpoint.port).to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(e) => {
+            checks.push(Check::fail("dns", format!("Cannot resolve {}: {}", endpoint.host, e),
+                "Check the host name in the configuration and the DNS servers in /etc/resolv.conf"));
+            checks.push(Check::skip("tcp", "Host not resolved"));
+            checks.push(Check::skip("tls", "Host not resolved"));
+            return false;
+        }
+    };
+    let listed: Vec<String> = addresses.iter().map(|a| a.ip().to_string()).collect();
+    checks.push(Check::pass("dns", format!("{} → {}", endpoint.host, listed.join(", "))));
+
+    let timeout = Duration::from_secs(config.server.timeout_seconds.max(1));
+    let mut errors = Vec::new();
+    let stream = addresses.iter().find_map(|address| match TcpStream::connect_timeout(address, timeout) {
+        Ok(stream) => Some((address, stream)),
+        Err(e) => {
+            errors.push(format!("{}: {}", address, e));
+            None
+        }
+    });
+    let Some((address, stream)) = stream else {
+        checks.push(Check::fail("tcp", errors.join("; "),
+            "Check that the backend is running and that no firewall or proxy blocks the port"));
+        checks.push(Check::skip("tls", "No TCP connection"));
+        return false;
+    };
+    checks.push(Check::pass("tcp", format!("Connected to {}", address)));
+
+    if !endpoint.tls {
+        checks.push(Check::skip("tls", "Plain connection (no TLS in the URL)"));
+        return true;
+    }
+    let handshake = TlsConnector::new()
+        .map_err(|e| e.to_string())
+        .and_then(|connector| connector.connect(&endpoint.host, stream).map_err(|e| e.to_string()));
+    match handshake {
+        Ok(_) => {
+            checks.push(Check::pass("tls", format!("Certificate for {} verified", endpoint.host)));
+            true
+        }
+        Err(e) => {
+            checks.push(Check::fail("tls", format!("Handshake with {} failed: {}", endpoint.host, e),
+                "Check the system CA bundle, the server certificate's host name and expiry, and the clock"));
+            false
+        }
+    }
+}
+
+/// Send one regular heartbeat over the configured transport
+fn heartbeat_check(config: &Config) -> Check {
+    let transport = match sender::create_transport(config) {
+        Ok(transport) => transport,
+        Err(e) => return Check::fail("heartbeat", format!("Failed to set up transport: {}", e),
+            "Check the [server] and transport sections of the configuration"),
+    };
+    let payload = match serde_json::to_value(collect_all_info(config)) {
+        Ok(payload) => payload,
+        Err(e) => return Check::fail("heartbeat", format!("Failed to serialize data: {}", e), "This is a bug"),
+    };
+
+    match transport.send(&payload) {
+        Ok(response) => Check::pass("heartbeat", match response.message {
+            Some(message) => format!("{} over {}: {}", response.status, transport.name(), message),
+            None => format!("{} over {}", response.status, transport.name()),
+        }),
+        Err(e) => {
+            let hint = if e.contains("401") {
+                "The token was rejected: check device-agent status, or re-register"
+            } else if e.contains("403") {
+                "The token may have been revoked: ask an administrator"
+            } else if e.contains("400") {
+                "The backend rejected the payload: compare agent and backend versions"
+            } else {
+                "Check the backend logs for this agent ID"
+            };
+            Check::fail("heartbeat", e, hint)
+        }
+    }
+}
+
+fn is_fingerprint_mismatch(error: &str) -> bool {
+    error.to_lowercase().contains("fingerprint mismatch")
+}
+
+/// Human-readable report
+pub fn render(checks: &[Check]) -> String {
+    let mut text = String::from("=== Device Agent Doctor ===\n\n");
+    for check in checks {
+        let symbol = match check.status {
+            CheckStatus::Pass => "✓",
+            CheckStatus::Fail => "✗",
+            CheckStatus::Skip => "-",
+        };
+        text.push_str(&format!("{} {:<12} {}\n", symbol, check.name, check.detail));
+        if let Some(hint) = &check.hint {
+            text.push_str(&format!("  {:<12} → {}\n", "", hint));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_and_error_analysis() {
+        assert_eq!(endpoint("https://backend.example.com/api/heartbeat/").unwrap(),
+            Endpoint { host: "backend.example.com".to_string(), port: 443, tls: true });
+        assert_eq!(endpoint("http://[::1]:8000/api/heartbeat/").unwrap(),
+            Endpoint { host: "::1".to_string(), port: 8000, tls: false });
+        assert_eq!(endpoint("wss://backend.example.com:8443/ws/").unwrap().port, 8443);
+        assert!(endpoint("not a url").is_err());
+
+        assert!(is_fingerprint_mismatch(
+            r#"Backend error (401 Unauthorized): {"message": "Device fingerprint mismatch. This token is bound to a different device."}"#));
+        assert!(!is_fingerprint_mismatch("Backend error (401 Unauthorized): invalid token"));
+
+        let report = render(&[
+            Check::pass("config", "Loaded config.toml"),
+            Check::fail("tcp", "Connection refused", "Check the firewall"),
+        ]);
+        assert!(report.contains("✓ config       Loaded config.toml\n"), "{}", report);
+        assert!(report.contains("✗ tcp          Connection refused\n               → Check the firewall\n"), "{}", report);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::config::ExporterConfig;
+use crate::models::SystemInfo;
+use crate::sinks::Sink;
+use chrono::Utc;
+use log::{info, debug, warn};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Latest collection plus the agent's own counters, shared with the HTTP listener
+#[derive(Clone, Default)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    snapshot: Option<SystemInfo>,
+    successful_collections: u64,
+    failed_collections: u64,
+    last_send_success: Option<bool>,
+    last_send_timestamp: Option<i64>,
+}
+
+impl Metrics {
+    /// Store the latest collected snapshot
+    pub fn record_snapshot(&self, info: &SystemInfo) {
+        self.lock().snapshot = Some(info.clone());
+    }
+
+    /// Count a finished collection cycle
+    pub fn record_collection(&self, success: bool) {
+        let mut state = self.lock();
+        if success {
+            state.successful_collections += 1;
+        } else {
+            state.failed_collections += 1;
+        }
+    }
+
+    /// Remember whether the last heartbeat reached the backend
+    pub fn record_send(&self, success: bool) {
+        let mut state = self.lock();
+        state.last_send_success = Some(success);
+        state.last_send_timestamp = Some(Utc::now().timestamp());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricsState> {
+        // A panic while holding the lock only affects metrics, keep serving
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Render everything in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.lock();
+        let mut out = String::new();
+
+        if let Some(info) = &state.snapshot {
+            let host = [("hostname", info.hostname.as_str())];
+
+            metric(&mut out, "device_agent_info", "gauge", "Static information about the device", &[
+                (&[
+                    ("agent_id", info.agent_id.as_str()),
+                    ("hostname", info.hostname.as_str()),
+                    ("os_type", info.os_type.as_str()),
+                    ("os_version", info.os_version.as_str()),
+                    ("cpu", info.cpu_info.as_str()),
+                ], 1.0),
+            ]);
+            metric(&mut out, "device_memory_total_bytes", "gauge", "Total memory in bytes", &[
+                (&host, info.memory_total as f64),
+            ]);
+            metric(&mut out, "device_memory_available_bytes", "gauge", "Available memory in bytes", &[
+                (&host, info.memory_available as f64),
+            ]);
+            metric(&mut out, "device_cpu_count", "gauge", "Number of logical CPUs", &[
+                (&host, info.cpu_count as f64),
+            ]);
+            metric(&mut out, "device_cpu_usage_percent", "gauge", "Global CPU usage in percent", &[
+                (&host, info.cpu_usage_percent as f64),
+            ]);
+
+            let load = &info.load_average;
+            metric(&mut out, "device_load_average", "gauge", "System load average", &[
+                (&[("hostname", info.hostname.as_str()), ("period", "1m")], load.one),
+                (&[("hostname", info.hostname.as_str()), ("period", "5m")], load.five),
+                (&[("hostname", info.hostname.as_str()), ("period", "15m")], load.fifteen),
+            ]);
+
+            let disk_labels: Vec<[(&str, &str); 3]> = info.disks.iter()
+                .map(|d| [
+                    ("hostname", info.hostname.as_str()),
+                    ("mount_point", d.mount_point.as_str()),
+                    ("fs_type", d.file_system.as_str()),
+                ])
+                .collect();
+            let total: Vec<_> = disk_labels.iter().zip(&info.disks)
+                .map(|(labels, d)| (&labels[..], d.total_bytes as f64))
+                .collect();
+            let available: Vec<_> = disk_labels.iter().zip(&info.disks)
+                .map(|(labels, d)| (&labels[..], d.available_bytes as f64))
+                .collect();
+            metric(&mut out, "device_disk_total_bytes", "gauge", "Filesystem size in bytes", &total);
+            metric(&mut out, "device_disk_available_bytes", "gauge", "Filesystem free space in bytes", &available);
+
+            let service_labels: Vec<[(&str, &str); 2]> = info.services.iter()
+                .map(|s| [("hostname", info.hostname.as_str()), ("service", s.as_str())])
+                .collect();
+            let services: Vec<_> = service_labels.iter().map(|l| (&l[..], 1.0)).collect();
+            metric(&mut out, "device_service_up", "gauge", "Running services (1 = running)", &services);
+
+            metric(&mut out, "device_installed_software_count", "gauge", "Number of installed packages", &[
+                (&host, info.installed_software.len() as f64),
+            ]);
+            metric(&mut out, "device_agent_last_collection_timestamp_seconds", "gauge", "Time of the last collection", &[
+                (&[], info.collected_at.timestamp() as f64),
+            ]);
+        }
+
+        metric(&mut out, "device_agent_successful_collections_total", "counter", "Successful collection cycles", &[
+            (&[], state.successful_collections as f64),
+        ]);
+        metric(&mut out, "device_agent_failed_collections_total", "counter", "Failed collection cycles", &[
+            (&[], state.failed_collections as f64),
+        ]);
+        if let Some(success) = state.last_send_success {
+            metric(&mut out, "device_agent_last_send_success", "gauge", "Whether the last heartbeat was delivered", &[
+                (&[], if success { 1.0 } else { 0.0 }),
+            ]);
+        }
+        if let Some(timestamp) = state.last_send_timestamp {
+            metric(&mut out, "device_agent_last_send_timestamp_seconds", "gauge", "Time of the last send attempt", &[
+                (&[], timestamp as f64),
+            ]);
+        }
+
+        out
+    }
+}
+
+impl Sink for Metrics {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        self.record_snapshot(info);
+        Ok(())
+    }
+}
+
+type Sample<'a> = (&'a [(&'a str, &'a str)], f64);
+
+/// Append one metric family; families without samples are skipped
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[Sample]) {
+    if samples.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let labels: Vec<String> = labels.iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                .collect();
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+    }
+}
+
+/// Escape a label value (backslash, double quote and newline)
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Start the HTTP listener serving `/metrics` in a background thread
+pub fn start(config: &ExporterConfig, metrics: Metrics) -> Result<(), String> {
+    let address = format!("{}:{}", config.bind_address, config.port);
+    let listener = TcpListener::bind(&address)
+        .map_err(|e| format!("Failed to bind exporter to {}: {}", address, e))?;
+
+    info!("✓ Prometheus exporter listening on http://{}/metrics", address);
+
+    thread::Builder::new()
+        .name("exporter".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle_request(stream, &metrics) {
+                            debug!("Exporter request failed: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Exporter failed to accept connection: {}", e),
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start exporter thread: {}", e))?;
+
+    Ok(())
+}
+
+fn handle_request(mut stream: TcpStream, metrics: &Metrics) -> Result<(), String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("Failed to set read timeout: {}", e))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)
+        .map_err(|e| format!("Failed to read request: {}", e))?;
+
+    // Drain headers; the request body (if any) is ignored
+    let mut line = String::new();
+    while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) && line.trim_end() != "" {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", metrics.render()),
+        ("GET", _) => ("404 Not Found", "text/plain; charset=utf-8", "Not Found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "Method Not Allowed\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes())
+        .map_err(|e| format!("Failed to write response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DiskInfo;
+    use std::io::Read;
+
+    #[test]
+    fn test_render_snapshot_and_counters() {
+        let metrics = Metrics::default();
+        metrics.record_snapshot(&SystemInfo {
+            collected_at: Utc::now(),
+            hostname: "host\"1".to_string(),
+            memory_total: 2048,
+            disks: vec![DiskInfo {
+                mount_point: "/".to_string(),
+                file_system: "ext4".to_string(),
+                total_bytes: 100,
+                available_bytes: 40,
+            }],
+            services: vec!["sshd".to_string()],
+            ..Default::default()
+        });
+        metrics.record_collection(true);
+        metrics.record_collection(false);
+        metrics.record_send(true);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE device_memory_total_bytes gauge"));
+        assert!(text.contains("device_memory_total_bytes{hostname=\"host\\\"1\"} 2048"),
+            "Label values should be escaped: {}", text);
+        assert!(text.contains("device_disk_available_bytes{hostname=\"host\\\"1\",mount_point=\"/\",fs_type=\"ext4\"} 40"));
+        assert!(text.contains("device_service_up{hostname=\"host\\\"1\",service=\"sshd\"} 1"));
+        assert!(text.contains("device_agent_successful_collections_total 1"));
+        assert!(text.contains("device_agent_failed_collections_total 1"));
+        assert!(text.contains("device_agent_last_send_success 1"));
+    }
+
+    #[test]
+    fn test_serves_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let metrics = Metrics::default();
+        metrics.record_collection(true);
+
+        let served = metrics.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                handle_request(stream.unwrap(), &served).unwrap();
+            }
+        });
+
+        let fetch = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = fetch("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("text/plain; version=0.0.4"));
+        assert!(response.contains("device_agent_successful_collections_total 1"));
+
+        assert!(fetch("/other").starts_with("HTTP/1.1 404"));
+    }
+}
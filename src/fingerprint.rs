@@ -1,3 +1,4 @@
+#[cfg(any(target_os = "macos", target_os = "windows"))]
 use std::process::Command;
 use log::{debug};
 use sha2::{Sha256, Digest};
@@ -9,6 +10,23 @@ use sha2::{Sha256, Digest};
 pub fn generate_fingerprint() -> Result<String, String> {
     debug!("Generating device fingerprint");
     
+    let components = components()?;
+    
+    // Combine components and hash
+    let combined = components.join("|");
+    let mut hasher = Sha256::new();
+    hasher.update(combined.as_bytes());
+    let result = hasher.finalize();
+    let fingerprint = format!("{:x}", result);
+    
+    debug!("Device fingerprint generated: {}...", &fingerprint[..16]);
+    debug!("Components used: {}", components.len());
+    
+    Ok(fingerprint)
+}
+
+/// Hardware identifiers the fingerprint is built from, as `kind:value`
+pub fn components() -> Result<Vec<String>, String> {
     let mut components = Vec::new();
     
     // Get hardware components based on OS
@@ -30,18 +48,7 @@ pub fn generate_fingerprint() -> Result<String, String> {
     if components.is_empty() {
         return Err("Failed to collect any hardware identifiers".to_string());
     }
-    
-    // Combine components and hash
-    let combined = components.join("|");
-    let mut hasher = Sha256::new();
-    hasher.update(combined.as_bytes());
-    let result = hasher.finalize();
-    let fingerprint = format!("{:x}", result);
-    
-    debug!("Device fingerprint generated: {}...", &fingerprint[..16]);
-    debug!("Components used: {}", components.len());
-    
-    Ok(fingerprint)
+    Ok(components)
 }
 
 #[cfg(target_os = "macos")]
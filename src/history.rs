@@ -0,0 +1,596 @@
+use crate::config::HistoryConfig;
+use crate::models::{DiskInfo, LoadAverage, Package, SystemInfo};
+use crate::sinks::Sink;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use log::debug;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    collected_at TEXT NOT NULL,
+    agent_id TEXT NOT NULL,
+    agent_name TEXT NOT NULL,
+    device_fingerprint TEXT NOT NULL,
+    hostname TEXT NOT NULL,
+    os_type TEXT NOT NULL,
+    os_version TEXT NOT NULL,
+    cpu_info TEXT NOT NULL,
+    cpu_count INTEGER NOT NULL,
+    cpu_usage_percent REAL NOT NULL,
+    load_1m REAL NOT NULL,
+    load_5m REAL NOT NULL,
+    load_15m REAL NOT NULL,
+    memory_total INTEGER NOT NULL,
+    memory_available INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS snapshots_collected_at ON snapshots (collected_at);
+CREATE TABLE IF NOT EXISTS snapshot_addresses (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    interface TEXT NOT NULL,
+    address TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshot_disks (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    mount_point TEXT NOT NULL,
+    file_system TEXT NOT NULL,
+    total_bytes INTEGER NOT NULL,
+    available_bytes INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshot_services (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshot_software (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshot_packages (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    manager TEXT NOT NULL,
+    arch TEXT,
+    source TEXT
+);
+CREATE TABLE IF NOT EXISTS snapshot_not_collected (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    section TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS snapshot_addresses_id ON snapshot_addresses (snapshot_id);
+CREATE INDEX IF NOT EXISTS snapshot_disks_id ON snapshot_disks (snapshot_id);
+CREATE INDEX IF NOT EXISTS snapshot_services_id ON snapshot_services (snapshot_id);
+CREATE INDEX IF NOT EXISTS snapshot_software_id ON snapshot_software (snapshot_id);
+CREATE INDEX IF NOT EXISTS snapshot_packages_id ON snapshot_packages (snapshot_id);
+CREATE INDEX IF NOT EXISTS snapshot_not_collected_id ON snapshot_not_collected (snapshot_id);
+";
+
+/// One row of `history list`
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    pub id: i64,
+    pub collected_at: DateTime<Utc>,
+    pub hostname: String,
+    pub services: usize,
+    pub software: usize,
+}
+
+/// Services, software and packages that differ between two snapshots
+///
+/// Sections that either snapshot did not collect are listed in `not_compared`
+/// and left empty.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SnapshotDiff {
+    pub services_added: Vec<String>,
+    pub services_removed: Vec<String>,
+    pub software_added: Vec<String>,
+    pub software_removed: Vec<String>,
+    /// `name version`
+    pub packages_added: Vec<String>,
+    pub packages_removed: Vec<String>,
+    pub packages_changed: Vec<VersionChange>,
+    pub not_compared: Vec<String>,
+}
+
+impl SnapshotDiff {
+    pub fn has_changes(&self) -> bool {
+        !(self.services_added.is_empty()
+            && self.services_removed.is_empty()
+            && self.software_added.is_empty()
+            && self.software_removed.is_empty()
+            && self.packages_added.is_empty()
+            && self.packages_removed.is_empty()
+            && self.packages_changed.is_empty())
+    }
+}
+
+/// Package whose version differs between two snapshots
+#[derive(Debug, PartialEq, Serialize)]
+pub struct VersionChange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Local SQLite store of past snapshots, normalised by section
+pub struct History {
+    conn: Connection,
+    config: HistoryConfig,
+}
+
+impl History {
+    pub fn open(path: &str, config: &HistoryConfig) -> Result<Self, String> {
+        let conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open history database {}: {}", path, e))?;
+
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .map_err(|e| format!("Failed to configure history database: {}", e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to create history schema: {}", e))?;
+
+        Ok(History { conn, config: config.clone() })
+    }
+
+    /// Store a snapshot and return its ID
+    pub fn record(&mut self, info: &SystemInfo) -> Result<i64, String> {
+        let tx = self.conn.transaction()
+            .map_err(|e| format!("Failed to start history transaction: {}", e))?;
+
+        tx.execute(
+            "INSERT INTO snapshots (collected_at, agent_id, agent_name, device_fingerprint, hostname,
+                os_type, os_version, cpu_info, cpu_count, cpu_usage_percent, load_1m, load_5m, load_15m,
+                memory_total, memory_available)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                timestamp(info.collected_at),
+                info.agent_id,
+                info.agent_name,
+                info.device_fingerprint,
+                info.hostname,
+                info.os_type,
+                info.os_version,
+                info.cpu_info,
+                info.cpu_count as i64,
+                info.cpu_usage_percent as f64,
+                info.load_average.one,
+                info.load_average.five,
+                info.load_average.fifteen,
+                info.memory_total as i64,
+                info.memory_available as i64,
+            ],
+        ).map_err(|e| format!("Failed to store snapshot: {}", e))?;
+        let id = tx.last_insert_rowid();
+
+        let insert = |sql: &str, rows: &mut dyn Iterator<Item = Vec<rusqlite::types::Value>>| -> Result<(), String> {
+            let mut statement = tx.prepare_cached(sql)
+                .map_err(|e| format!("Failed to prepare history insert: {}", e))?;
+            for row in rows {
+                statement.execute(rusqlite::params_from_iter(row))
+                    .map_err(|e| format!("Failed to store snapshot section: {}", e))?;
+            }
+            Ok(())
+        };
+
+        use rusqlite::types::Value as V;
+        insert(
+            "INSERT INTO snapshot_addresses (snapshot_id, interface, address) VALUES (?1, ?2, ?3)",
+            &mut info.ip_addresses.iter().flat_map(|(interface, addresses)| {
+                addresses.iter().map(move |address| {
+                    vec![V::Integer(id), V::Text(interface.clone()), V::Text(address.clone())]
+                })
+            }),
+        )?;
+        insert(
+            "INSERT INTO snapshot_disks (snapshot_id, mount_point, file_system, total_bytes, available_bytes)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            &mut info.disks.iter().map(|disk| vec![
+                V::Integer(id),
+                V::Text(disk.mount_point.clone()),
+                V::Text(disk.file_system.clone()),
+                V::Integer(disk.total_bytes as i64),
+                V::Integer(disk.available_bytes as i64),
+            ]),
+        )?;
+        insert(
+            "INSERT INTO snapshot_services (snapshot_id, name) VALUES (?1, ?2)",
+            &mut info.services.iter().map(|name| vec![V::Integer(id), V::Text(name.clone())]),
+        )?;
+        insert(
+            "INSERT INTO snapshot_software (snapshot_id, name) VALUES (?1, ?2)",
+            &mut info.installed_software.iter().map(|name| vec![V::Integer(id), V::Text(name.clone())]),
+        )?;
+        let optional = |value: &Option<String>| value.clone().map(V::Text).unwrap_or(V::Null);
+        insert(
+            "INSERT INTO snapshot_packages (snapshot_id, name, version, manager, arch, source)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            &mut info.packages.iter().map(|package| vec![
+                V::Integer(id),
+                V::Text(package.name.clone()),
+                V::Text(package.version.clone()),
+                V::Text(package.manager.clone()),
+                optional(&package.arch),
+                optional(&package.source),
+            ]),
+        )?;
+        insert(
+            "INSERT INTO snapshot_not_collected (snapshot_id, section) VALUES (?1, ?2)",
+            &mut info.not_collected.iter().map(|section| vec![V::Integer(id), V::Text(section.clone())]),
+        )?;
+
+        tx.commit()
+            .map_err(|e| format!("Failed to commit snapshot: {}", e))?;
+        Ok(id)
+    }
+
+    /// Delete snapshots beyond the configured age and count limits
+    pub fn prune(&self) -> Result<usize, String> {
+        let mut removed = 0;
+
+        if self.config.max_age_days > 0 {
+            let cutoff = Utc::now() - Duration::days(self.config.max_age_days as i64);
+            removed += self.conn.execute(
+                "DELETE FROM snapshots WHERE collected_at < ?1",
+                params![timestamp(cutoff)],
+            ).map_err(|e| format!("Failed to prune history: {}", e))?;
+        }
+
+        if self.config.max_snapshots > 0 {
+            removed += self.conn.execute(
+                "DELETE FROM snapshots WHERE id NOT IN
+                    (SELECT id FROM snapshots ORDER BY collected_at DESC, id DESC LIMIT ?1)",
+                params![self.config.max_snapshots as i64],
+            ).map_err(|e| format!("Failed to prune history: {}", e))?;
+        }
+
+        if removed > 0 {
+            debug!("Pruned {} snapshot(s) from history", removed);
+        }
+        Ok(removed)
+    }
+
+    /// Most recent snapshots first
+    pub fn list(&self, limit: usize) -> Result<Vec<SnapshotSummary>, String> {
+        let mut statement = self.conn.prepare(
+            "SELECT s.id, s.collected_at, s.hostname,
+                (SELECT COUNT(*) FROM snapshot_services WHERE snapshot_id = s.id),
+                (SELECT COUNT(*) FROM snapshot_software WHERE snapshot_id = s.id)
+             FROM snapshots s ORDER BY s.collected_at DESC, s.id DESC LIMIT ?1",
+        ).map_err(|e| format!("Failed to query history: {}", e))?;
+
+        let rows = statement.query_map(params![limit as i64], |row| {
+            Ok(SnapshotSummary {
+                id: row.get(0)?,
+                collected_at: row.get(1)?,
+                hostname: row.get(2)?,
+                services: row.get::<_, i64>(3)? as usize,
+                software: row.get::<_, i64>(4)? as usize,
+            })
+        }).map_err(|e| format!("Failed to query history: {}", e))?;
+
+        rows.collect::<Result<_, _>>()
+            .map_err(|e| format!("Failed to read history: {}", e))
+    }
+
+    /// Rebuild a stored snapshot
+    pub fn load(&self, id: i64) -> Result<SystemInfo, String> {
+        let mut info = self.conn.query_row(
+            "SELECT collected_at, agent_id, agent_name, device_fingerprint, hostname, os_type, os_version,
+                cpu_info, cpu_count, cpu_usage_percent, load_1m, load_5m, load_15m, memory_total, memory_available
+             FROM snapshots WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(SystemInfo {
+                    collected_at: row.get(0)?,
+                    agent_id: row.get(1)?,
+                    agent_name: row.get(2)?,
+                    device_fingerprint: row.get(3)?,
+                    hostname: row.get(4)?,
+                    os_type: row.get(5)?,
+                    os_version: row.get(6)?,
+                    cpu_info: row.get(7)?,
+                    cpu_count: row.get::<_, i64>(8)? as usize,
+                    cpu_usage_percent: row.get::<_, f64>(9)? as f32,
+                    load_average: LoadAverage {
+                        one: row.get(10)?,
+                        five: row.get(11)?,
+                        fifteen: row.get(12)?,
+                    },
+                    memory_total: row.get::<_, i64>(13)? as u64,
+                    memory_available: row.get::<_, i64>(14)? as u64,
+                    ..Default::default()
+                })
+            },
+        ).optional()
+            .map_err(|e| format!("Failed to query history: {}", e))?
+            .ok_or_else(|| format!("Snapshot {} not found", id))?;
+
+        let mut addresses: HashMap<String, Vec<String>> = HashMap::new();
+        for (interface, address) in self.rows::<(String, String)>(
+            "SELECT interface, address FROM snapshot_addresses WHERE snapshot_id = ?1 ORDER BY rowid", id,
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )? {
+            addresses.entry(interface).or_default().push(address);
+        }
+        info.ip_addresses = addresses;
+
+        info.disks = self.rows(
+            "SELECT mount_point, file_system, total_bytes, available_bytes FROM snapshot_disks
+             WHERE snapshot_id = ?1 ORDER BY rowid", id,
+            |row| Ok(DiskInfo {
+                mount_point: row.get(0)?,
+                file_system: row.get(1)?,
+                total_bytes: row.get::<_, i64>(2)? as u64,
+                available_bytes: row.get::<_, i64>(3)? as u64,
+            }),
+        )?;
+        info.services = self.rows(
+            "SELECT name FROM snapshot_services WHERE snapshot_id = ?1 ORDER BY rowid", id,
+            |row| row.get(0),
+        )?;
+        info.installed_software = self.rows(
+            "SELECT name FROM snapshot_software WHERE snapshot_id = ?1 ORDER BY rowid", id,
+            |row| row.get(0),
+        )?;
+        info.packages = self.rows(
+            "SELECT name, version, manager, arch, source FROM snapshot_packages
+             WHERE snapshot_id = ?1 ORDER BY rowid", id,
+            |row| Ok(Package {
+                name: row.get(0)?,
+                version: row.get(1)?,
+                manager: row.get(2)?,
+                arch: row.get(3)?,
+                source: row.get(4)?,
+            }),
+        )?;
+        info.not_collected = self.rows(
+            "SELECT section FROM snapshot_not_collected WHERE snapshot_id = ?1 ORDER BY rowid", id,
+            |row| row.get(0),
+        )?;
+
+        Ok(info)
+    }
+
+    /// Changes going from snapshot `from` to `to`, skipping sections either one did not collect
+    pub fn diff(&self, from: i64, to: i64) -> Result<SnapshotDiff, String> {
+        let old = self.load(from)?;
+        let new = self.load(to)?;
+
+        let mut diff = SnapshotDiff::default();
+        for section in ["services", "installed_software", "packages"] {
+            if old.not_collected.iter().chain(&new.not_collected).any(|s| s == section) {
+                diff.not_compared.push(section.to_string());
+                continue;
+            }
+            match section {
+                "services" => {
+                    (diff.services_added, diff.services_removed) = set_diff(&old.services, &new.services);
+                }
+                "installed_software" => {
+                    (diff.software_added, diff.software_removed) =
+                        set_diff(&old.installed_software, &new.installed_software);
+                }
+                _ => package_diff(&old.packages, &new.packages, &mut diff),
+            }
+        }
+
+        Ok(diff)
+    }
+
+    fn rows<T>(
+        &self,
+        sql: &str,
+        id: i64,
+        map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, String> {
+        let mut statement = self.conn.prepare(sql)
+            .map_err(|e| format!("Failed to query history: {}", e))?;
+        let rows = statement.query_map(params![id], map)
+            .map_err(|e| format!("Failed to query history: {}", e))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|e| format!("Failed to read history: {}", e))
+    }
+}
+
+impl Sink for History {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        let id = self.record(info)?;
+        debug!("Stored snapshot #{} in history", id);
+        self.prune()?;
+        Ok(())
+    }
+}
+
+/// Fixed-width UTC timestamps so text comparison matches time order
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// (added, removed), sorted
+fn set_diff(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let old: BTreeSet<&String> = old.iter().collect();
+    let new: BTreeSet<&String> = new.iter().collect();
+    (
+        new.difference(&old).map(|s| s.to_string()).collect(),
+        old.difference(&new).map(|s| s.to_string()).collect(),
+    )
+}
+
+/// Packages keyed by name, manager and architecture, so versions are compared per install
+fn package_diff(old: &[Package], new: &[Package], diff: &mut SnapshotDiff) {
+    let key = |p: &Package| (p.name.clone(), p.manager.clone(), p.arch.clone());
+    let old: BTreeMap<_, &Package> = old.iter().map(|p| (key(p), p)).collect();
+    let new: BTreeMap<_, &Package> = new.iter().map(|p| (key(p), p)).collect();
+
+    for (k, package) in &new {
+        match old.get(k) {
+            None => diff.packages_added.push(format!("{} {}", package.name, package.version)),
+            Some(before) if before.version != package.version => diff.packages_changed.push(VersionChange {
+                name: package.name.clone(),
+                from: before.version.clone(),
+                to: package.version.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (k, package) in &old {
+        if !new.contains_key(k) {
+            diff.packages_removed.push(format!("{} {}", package.name, package.version));
+        }
+    }
+}
+
+/// Open the history database for the `history` command, which never creates it
+pub fn open_existing(path: &str, config: &HistoryConfig) -> Result<History, String> {
+    if !std::path::Path::new(path).exists() {
+        return Err(format!(
+            "No history database at {} (enable [history] in config.toml)", path
+        ));
+    }
+    History::open(path, config)
+}
+
+/// `history list` as a table
+pub fn print_list(snapshots: &[SnapshotSummary]) {
+    if snapshots.is_empty() {
+        println!("No snapshots recorded yet");
+        return;
+    }
+
+    println!("{:>6}  {:<25}  {:<24}  {:>8}  {:>8}", "ID", "COLLECTED AT", "HOSTNAME", "SERVICES", "SOFTWARE");
+    for s in snapshots {
+        println!(
+            "{:>6}  {:<25}  {:<24}  {:>8}  {:>8}",
+            s.id,
+            s.collected_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            s.hostname,
+            s.services,
+            s.software
+        );
+    }
+}
+
+/// `history diff` as added/removed lists
+pub fn print_diff(from: i64, to: i64, diff: &SnapshotDiff) {
+    println!("=== Snapshot #{} → #{} ===", from, to);
+    print_changes("Services started", '+', &diff.services_added);
+    print_changes("Services stopped", '-', &diff.services_removed);
+    print_changes("Software installed", '+', &diff.software_added);
+    print_changes("Software removed", '-', &diff.software_removed);
+    print_changes("Packages installed", '+', &diff.packages_added);
+    print_changes("Packages removed", '-', &diff.packages_removed);
+    let changed: Vec<String> = diff.packages_changed.iter()
+        .map(|c| format!("{} {} → {}", c.name, c.from, c.to))
+        .collect();
+    print_changes("Packages changed", '~', &changed);
+    if !diff.not_compared.is_empty() {
+        println!();
+        println!("Not compared (not collected): {}", diff.not_compared.join(", "));
+    }
+    if !diff.has_changes() {
+        println!("No service, software or package changes");
+    }
+}
+
+fn print_changes(title: &str, marker: char, names: &[String]) {
+    if names.is_empty() {
+        return;
+    }
+    println!();
+    println!("{} ({}):", title, names.len());
+    for name in names {
+        println!("  {} {}", marker, name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(hours_ago: i64, services: &[&str], software: &[&str]) -> SystemInfo {
+        SystemInfo {
+            collected_at: Utc::now() - Duration::hours(hours_ago),
+            hostname: "host-1".to_string(),
+            ip_addresses: HashMap::from([("eth0".to_string(), vec!["10.0.0.2".to_string()])]),
+            disks: vec![DiskInfo {
+                mount_point: "/".to_string(),
+                file_system: "ext4".to_string(),
+                total_bytes: 100,
+                available_bytes: 40,
+            }],
+            services: services.iter().map(|s| s.to_string()).collect(),
+            installed_software: software.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_load_diff_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let config = HistoryConfig { max_snapshots: 2, ..HistoryConfig::default() };
+        let mut history = History::open(path.to_str().unwrap(), &config).unwrap();
+
+        let first = history.record(&snapshot(3, &["sshd", "cron"], &["vim"])).unwrap();
+        let second = history.record(&snapshot(2, &["sshd", "nginx"], &["vim", "curl"])).unwrap();
+
+        let loaded = history.load(first).unwrap();
+        assert_eq!(loaded.services, vec!["sshd", "cron"]);
+        assert_eq!(loaded.ip_addresses["eth0"], vec!["10.0.0.2"]);
+        assert_eq!(loaded.disks[0].available_bytes, 40);
+
+        let diff = history.diff(first, second).unwrap();
+        assert_eq!(diff.services_added, vec!["nginx"]);
+        assert_eq!(diff.services_removed, vec!["cron"]);
+        assert_eq!(diff.software_added, vec!["curl"]);
+        assert!(diff.software_removed.is_empty());
+
+        history.write(&snapshot(1, &[], &[])).unwrap();
+        let ids: Vec<i64> = history.list(10).unwrap().iter().map(|s| s.id).collect();
+        assert_eq!(ids.len(), 2, "Only max_snapshots should be kept");
+        assert!(!ids.contains(&first), "The oldest snapshot should be pruned");
+        assert!(history.load(first).is_err());
+
+        let orphans: i64 = history.conn
+            .query_row("SELECT COUNT(*) FROM snapshot_services WHERE snapshot_id = ?1", params![first], |r| r.get(0))
+            .unwrap();
+        assert_eq!(orphans, 0, "Sections should be deleted with their snapshot");
+    }
+
+    #[test]
+    fn test_diff_reports_package_versions_and_skips_uncollected_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let mut history = History::open(path.to_str().unwrap(), &HistoryConfig::default()).unwrap();
+
+        let mut before = snapshot(3, &["sshd"], &["vim"]);
+        before.packages = vec![
+            Package { arch: Some("amd64".to_string()), ..Package::new("openssl", "3.0.2", "deb") },
+            Package::new("curl", "7.81", "deb"),
+        ];
+        let mut after = snapshot(2, &[], &["vim"]);
+        after.packages = vec![
+            Package { arch: Some("amd64".to_string()), ..Package::new("openssl", "3.0.13", "deb") },
+            Package::new("jq", "1.6", "deb"),
+        ];
+        after.not_collected = vec!["services".to_string()];
+        let first = history.record(&before).unwrap();
+        let second = history.record(&after).unwrap();
+
+        let loaded = history.load(second).unwrap();
+        assert_eq!(loaded.packages, after.packages, "Package versions and arch should round-trip");
+        assert_eq!(loaded.not_collected, vec!["services"]);
+
+        let diff = history.diff(first, second).unwrap();
+        assert_eq!(diff.packages_changed, vec![VersionChange {
+            name: "openssl".to_string(),
+            from: "3.0.2".to_string(),
+            to: "3.0.13".to_string(),
+        }]);
+        assert_eq!(diff.packages_added, vec!["jq 1.6"]);
+        assert_eq!(diff.packages_removed, vec!["curl 7.81"]);
+        assert!(diff.services_removed.is_empty(), "Uncollected services are not reported as stopped");
+        assert_eq!(diff.not_compared, vec!["services"]);
+    }
+}
@@ -5,96 +5,134 @@ mod retry;
 mod sender;
 mod crypto;
 mod fingerprint;
+mod spool;
+mod delta;
+mod control;
+mod commands;
+mod websocket;
+mod exporter;
+mod otlp;
+mod sinks;
+mod history;
+mod changes;
+mod sbom;
+mod version;
+mod vulnerabilities;
+mod policy;
+mod software_lists;
+mod cis;
+mod cli;
+mod doctor;
+mod alerts;
+mod support_bundle;
+mod watch;
 
 use collector::collect_all_info;
 use config::Config;
+use cli::{Cli, Collector, Command, CommandResult, ConfigCommand, HistoryCommand, OutputFormat};
+use clap::Parser;
+use models::HeartbeatResponse;
 use retry::retry_with_backoff;
-use sender::send_to_backend; 
+use sender::Transport;
+use spool::Spool;
+use delta::DeltaTracker;
+use changes::ChangeTracker;
+use vulnerabilities::VulnerabilityDb;
+use policy::PolicyEngine;
+use software_lists::SoftwareLists;
+use cis::CisScheduler;
+use alerts::Alerter;
+use commands::CommandRunner;
+use websocket::WebSocketLink;
+use exporter::Metrics;
+use otlp::LogBuffer;
+use sinks::Sinks;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::path::Path;
+use std::time::{Duration, Instant};
 use std::env;
-use std::fs;
 use log::{info, error, warn, debug};
 
 fn main() {
-    // ✅ Handle CLI arguments BEFORE loading config
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() > 1 {
-        match args[1].as_str() {
-            "--init" | "-i" => {
-                handle_init();
-                return;
-            }
-            "--check-status" | "-s" => {
-                handle_check_status();
-                return;
-            }
-            "--register" | "-r" => {
-                handle_register(&args);
-                return;
-            }
-            "--unregister" | "-u" => {
-                handle_unregister();
-                return;
-            }
-            "--check-token" | "-c" => {
-                handle_check_token();
-                return;
-            }
-            "--help" | "-h" => {
-                print_help();
-                return;
-            }
-            _ => {
-                eprintln!("✗ Unknown option: {}", args[1]);
-                eprintln!("\nRun 'device-agent --help' for usage information");
-                std::process::exit(1);
-            }
+    let cli = Cli::parse_from(cli::legacy_args(env::args().collect()));
+    let command = cli.command.clone().unwrap_or(Command::Run);
+
+    if !matches!(command, Command::Run | Command::Collect { once: false, .. }) {
+        if let Err(e) = init_cli_logger(cli.log_level.as_deref().unwrap_or("warn")) {
+            eprintln!("✗ Error initializing logger: {}", e);
         }
     }
-    
+
+    let result = match command {
+        Command::Run => run_agent(&cli, false),
+        Command::Collect { once: false, .. } => run_agent(&cli, true),
+        Command::Collect { once: true, only, file, send } => handle_collect_once(&cli, &only, file.as_deref(), send),
+        Command::Init => handle_init(&cli),
+        Command::Status => handle_check_status(&cli),
+        Command::Register { token } => handle_register(&token),
+        Command::Unregister => handle_unregister(),
+        Command::Token => handle_check_token(),
+        Command::Doctor => handle_doctor(&cli),
+        Command::Config { action } => handle_config(&cli, action),
+        Command::History { action } => handle_history(&cli, action.unwrap_or(HistoryCommand::List { limit: 20 })),
+        Command::Sbom { format, file } => handle_sbom(&cli, &format, file.as_deref()),
+        Command::SupportBundle { file, logs } => handle_support_bundle(&cli, file, logs),
+    };
+    std::process::exit(cli::report(result, cli.output));
+}
+
+/// Load the configuration named by `--config`, applying `--log-level`
+fn load_config(cli: &Cli) -> Result<Config, String> {
+    let mut config = Config::load(&cli.config_path())?;
+    apply_overrides(cli, &mut config);
+    Ok(config)
+}
+
+fn apply_overrides(cli: &Cli, config: &mut Config) {
+    if let Some(level) = &cli.log_level {
+        config.logging.level = level.clone();
+    }
+}
+
+/// Run the agent loop, or a single collection cycle when `once` is set
+fn run_agent(cli: &Cli, once: bool) -> Result<CommandResult, String> {
     // ✅ Check if token is registered before starting
     if !crypto::has_token() {
-        eprintln!("");
-        eprintln!("✗ Error: No API token registered");
-        eprintln!("");
-        eprintln!("To get started:");
-        eprintln!("1. Request registration: device-agent --init");
-        eprintln!("2. Wait for admin approval");
-        eprintln!("3. Check status: device-agent --check-status");
-        eprintln!("");
-        eprintln!("Alternative: If you have a token:");
-        eprintln!("  device-agent --register <token>");
-        eprintln!("");
-        std::process::exit(1);
+        return Ok(CommandResult::failure("No API token registered")
+            .with_hint("To get started:")
+            .with_hint("1. Request registration: device-agent init")
+            .with_hint("2. Wait for admin approval")
+            .with_hint("3. Check status: device-agent status")
+            .with_hint("")
+            .with_hint("Alternative: If you have a token:")
+            .with_hint("  device-agent register <token>"));
     }
     // Load configuration
-    let config = match Config::load("config.toml") {
-        Ok(cfg) => {
-            println!("✓ Configuration loaded from config.toml");
+    let mut config = match Config::load_layered(&cli.config_path()) {
+        Ok((mut cfg, sources)) => {
+            apply_overrides(cli, &mut cfg);
+            println!("✓ Configuration loaded from {}", sources.join(", "));
             cfg
         }
         Err(e) => {
             eprintln!("⚠ Warning: {}", e);
             eprintln!("⚠ Using default configuration");
-            Config::default()
+            let mut cfg = Config::default();
+            apply_overrides(cli, &mut cfg);
+            cfg
         }
     };
 
     // Ensure directories exist
-    if let Err(e) = config.ensure_directories() {
-        eprintln!("✗ Error: {}", e);
-        std::process::exit(1);
-    }
+    config.ensure_directories()?;
 
     // Initialize logger
-    if let Err(e) = init_logger(&config) {
-        eprintln!("✗ Error initializing logger: {}", e);
-        std::process::exit(1);
-    }
+    let otlp_logs = sinks::wants_otlp_logs(&config)
+        .then(|| LogBuffer::new(config.otlp.max_buffered_logs));
+    init_logger(&config, otlp_logs.as_ref())
+        .map_err(|e| format!("Error initializing logger: {}", e))?;
 
     info!("=== Device Agent Starting ===");
     info!("Agent ID: {}", config.agent.agent_id);
@@ -115,197 +153,227 @@ fn main() {
     info!("Press Ctrl+C to stop");
     info!("");
 
+    let transport = if config.server.enabled {
+        let transport = sender::create_transport(&config)
+            .map_err(|e| format!("Failed to set up {:?} transport: {}", config.server.transport, e))?;
+        Some(transport)
+    } else {
+        None
+    };
+    let mut sinks = Sinks::from_config(&config, otlp_logs);
+    let mut delivery = Delivery {
+        spool: open_spool(&config),
+        delta: DeltaTracker::load(&config.delta_state_path(), &config.delta),
+        websocket: open_websocket(&config),
+        transport,
+        metrics: sinks.metrics(),
+        changes: config.collection.track_changes
+            .then(|| ChangeTracker::load(&config.change_state_path())),
+        vulnerabilities: config.vulnerabilities.enabled
+            .then(|| VulnerabilityDb::load(&config.vulnerabilities)),
+        policies: config.policy.enabled
+            .then(|| PolicyEngine::load(&config.policy)),
+        software_lists: config.software_lists.enabled
+            .then(|| SoftwareLists::load(&config)),
+    };
+    let mut command_runner = if config.server.enabled && config.commands.enabled {
+        info!("Remote commands enabled: {}", config.commands.allowed_actions.join(", "));
+        Some(CommandRunner::new(&config))
+    } else {
+        None
+    };
+
+    let mut cis = match (config.cis.enabled, cfg!(target_os = "linux")) {
+        (true, true) => Some(CisScheduler::new(&config.cis, &config.cis_report_path())),
+        (true, false) => {
+            warn!("CIS checks are only available on Linux, ignoring [cis]");
+            None
+        }
+        _ => None,
+    };
+
+    let mut alerter = if config.alerts.enabled {
+        match Alerter::new(&config.alerts, &config.agent.agent_id) {
+            Ok(alerter) => {
+                info!("Local alerting enabled: {} rule(s)", config.alerts.rules.len());
+                Some(alerter)
+            }
+            Err(e) => {
+                error!("✗ Alerting disabled: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Main collection loop
     let mut iteration = 0;
     let mut successful_collections = 0;
     let mut failed_collections = 0;
     while running.load(Ordering::SeqCst) {
         iteration += 1;
+        let collection_started = Instant::now();
         
         info!("=== Collection Iteration #{} ===", iteration);
         
-        // ✅ UPDATED: Use retry logic
-        match retry_with_backoff(
-            "collect_and_save",
-            &retry::RetryConfig {
-                max_retries: config.retry.max_retries,
-                initial_delay_ms: config.retry.initial_delay_ms,
-                max_delay_ms: config.retry.max_delay_ms,
-            },
-            || collect_and_save(&config),
-        ) {
-            Ok(_) => {
+        let mut pending_commands = Vec::new();
+
+        match collect_and_save(&config, &mut delivery, &mut sinks) {
+            Ok(response) => {
                 successful_collections += 1;
+                if let Some(metrics) = &delivery.metrics {
+                    metrics.record_collection(true);
+                }
                 info!("✓ Collection and save completed successfully");
+
+                if let Some(response) = response {
+                    control::apply_response(&mut config, &response);
+                    pending_commands = response.commands;
+                }
             }
             Err(e) => {
                 failed_collections += 1;
-                error!("✗ All retry attempts failed: {}", e);
+                if let Some(metrics) = &delivery.metrics {
+                    metrics.record_collection(false);
+                }
+                error!("✗ Collection cycle failed: {}", e);
                 warn!("Will try again in next collection cycle");
             }
         }
-        
+
+        if once {
+            break;
+        }
+
+        if let Some(cis) = cis.as_mut() {
+            run_cis_checks(cis, &config, &delivery);
+        }
+
         debug!("Statistics: Total={}, Successful={}, Failed={}", 
             iteration, successful_collections, failed_collections);
         
         if !running.load(Ordering::SeqCst) {
             break;
         }
-        
-        info!("Waiting {} seconds until next collection...", config.collection.interval_seconds);
+
+        // Early collections are rate limited so a backend cannot keep the agent collecting back to back
+        let min_gap = Duration::from_secs(config.commands.min_collect_gap_seconds);
+        let mut collect_requested = false;
+        if let Some(runner) = command_runner.as_mut() {
+            pending_commands.extend(runner.poll(&config));
+            collect_requested = runner.run(pending_commands, &config);
+        }
+
+        let interval = if collect_requested {
+            let wait = min_gap.saturating_sub(collection_started.elapsed()).as_secs();
+            info!("Collection requested by backend, starting in {} seconds", wait);
+            wait
+        } else {
+            info!("Waiting {} seconds until next collection...", config.collection.interval_seconds);
+            config.collection.interval_seconds
+        };
         info!("");
         
-        sleep_with_interrupt(&running, config.collection.interval_seconds);
+        sleep_with_interrupt(&running, interval, || {
+            if let Some(alerter) = alerter.as_mut() {
+                for alert in alerter.poll() {
+                    send_alert(alerter, &alert, &config, &delivery);
+                }
+            }
+            let new_violations = delivery.software_lists.as_mut().is_some_and(|lists| lists.check_due(&config.collection));
+            collect_requested |= handle_pushes(&mut config, delivery.websocket.as_ref(), command_runner.as_mut()) || new_violations;
+            collect_requested && collection_started.elapsed() >= min_gap
+        });
     }
 
     info!("=== Device Agent Stopped ===");
     info!("Total iterations: {}", iteration);
     info!("Successful collections: {}", successful_collections);
     info!("Failed collections: {}", failed_collections);
+
+    sinks.flush_all();
+
+    let data = serde_json::json!({
+        "iterations": iteration,
+        "successful": successful_collections,
+        "failed": failed_collections,
+    });
+    Ok(match (once, failed_collections) {
+        (true, 0) => CommandResult::success("Collection cycle completed"),
+        (true, _) => CommandResult::failure("Collection cycle failed"),
+        (false, _) => CommandResult::success("Agent stopped"),
+    }.with_data(data))
 }
 
 // ✅ NEW: Check registration status
-fn handle_check_status() {
-    println!("");
-    println!("=== Checking Registration Status ===");
-    println!("");
-    
-    let config = match Config::load("config.toml") {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("✗ Error loading config: {}", e);
-            std::process::exit(1);
-        }
-    };
-    
+fn handle_check_status(cli: &Cli) -> Result<CommandResult, String> {
+    let config = load_config(cli)?;
+
     let status_url = config.server.url
         .replace("/api/heartbeat/", "/api/agents/register/")
         .replace("/heartbeat/", "/agents/register/") + &config.agent.agent_id + "/status/";
-    
-    println!("Checking status for: {}", config.agent.agent_id);
-    println!("URL: {}", status_url);
-    println!("");
-    
+    debug!("Checking status for {} at {}", config.agent.agent_id, status_url);
+
     let client = reqwest::blocking::Client::new();
-    match client.get(&status_url).send() {
-        Ok(response) => {
-            match response.json::<serde_json::Value>() {
-                Ok(json) => {
-                    let status = json.get("status").and_then(|v| v.as_str()).unwrap_or("unknown");
-                    
-                    match status {
-                        "approved" => {
-                            println!("✓ Status: APPROVED");
-                            println!("");
-                            
-                            if let Some(token) = json.get("token").and_then(|v| v.as_str()) {
-                                println!("Your device has been approved!");
-                                println!("");
-                                println!("Saving token automatically...");
-                                
-                                match crypto::save_token(token) {
-                                    Ok(_) => {
-                                        println!("✓ Token saved successfully!");
-                                        println!("");
-                                        println!("You can now start the agent:");
-                                        println!("  device-agent");
-                                    }
-                                    Err(e) => {
-                                        eprintln!("✗ Failed to save token: {}", e);
-                                        println!("");
-                                        println!("Please save manually:");
-                                        println!("  device-agent --register {}", token);
-                                    }
-                                }
-                            } else {
-                                println!("Token already saved. You can start the agent:");
-                                println!("  device-agent");
-                            }
-                        }
-                        "pending" => {
-                            println!("⏳ Status: PENDING APPROVAL");
-                            println!("");
-                            if let Some(msg) = json.get("message").and_then(|v| v.as_str()) {
-                                println!("{}", msg);
-                            }
-                            println!("");
-                            println!("Please wait for admin approval.");
-                            println!("Check again later: device-agent --check-status");
-                        }
-                        "rejected" => {
-                            println!("✗ Status: REJECTED");
-                            println!("");
-                            println!("Your registration was rejected.");
-                            println!("Please contact your administrator for more information.");
-                        }
-                        "not_found" => {
-                            println!("✗ Status: NOT FOUND");
-                            println!("");
-                            println!("No registration found for this device.");
-                            println!("Please register first: device-agent --init");
-                        }
-                        _ => {
-                            println!("Status: {}", status);
-                            println!("{}", serde_json::to_string_pretty(&json).unwrap_or_default());
-                        }
-                    }
+    let response = client.get(&status_url).send()
+        .map_err(|e| format!("Failed to check status: {} (make sure the backend is accessible)", e))?;
+    let json = response.json::<serde_json::Value>()
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+    let status = json.get("status").and_then(|v| v.as_str()).unwrap_or("unknown");
+    let mut data = serde_json::json!({
+        "agent_id": config.agent.agent_id,
+        "url": status_url,
+        "status": status,
+    });
+    if let Some(msg) = json.get("message").and_then(|v| v.as_str()) {
+        data["message"] = serde_json::json!(msg);
+    }
+
+    let result = match status {
+        "approved" => match json.get("token").and_then(|v| v.as_str()) {
+            Some(token) => match crypto::save_token(token) {
+                Ok(_) => {
+                    data["token_saved"] = serde_json::json!(true);
+                    CommandResult::success("Status: APPROVED, token saved")
+                        .with_hint("You can now start the agent: device-agent run")
                 }
                 Err(e) => {
-                    eprintln!("Failed to parse response: {}", e);
+                    data["token_saved"] = serde_json::json!(false);
+                    CommandResult::failure(format!("Status: APPROVED, but the token could not be saved: {}", e))
+                        .with_hint(format!("Please save it manually: device-agent register {}", token))
                 }
-            }
-        }
-        Err(e) => {
-            eprintln!("✗ Failed to check status: {}", e);
-            eprintln!("");
-            eprintln!("Make sure the backend is accessible.");
+            },
+            None => CommandResult::success("Status: APPROVED")
+                .with_hint("Token already saved. You can start the agent: device-agent run"),
+        },
+        "pending" => CommandResult::success("Status: PENDING APPROVAL")
+            .with_hint("Please wait for admin approval. Check again later: device-agent status"),
+        "rejected" => CommandResult::failure("Status: REJECTED")
+            .with_hint("Please contact your administrator for more information."),
+        "not_found" => CommandResult::failure("Status: NOT FOUND")
+            .with_hint("No registration found for this device. Please register first: device-agent init"),
+        _ => {
+            data["response"] = json.clone();
+            CommandResult::success(format!("Status: {}", status))
         }
-    }
-    
-    println!("");
+    };
+    Ok(result.with_data(data))
 }
 
 // ✅ NEW: Initialize and request registration
-fn handle_init() {
-    println!("");
-    println!("=== Device Agent Initialization ===");
-    println!("");
-    
-    // Load config
-    let config = match Config::load("config.toml") {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("✗ Error loading config: {}", e);
-            eprintln!("\nPlease create config.toml first:");
-            eprintln!("  cp config.example.toml config.toml");
-            std::process::exit(1);
-        }
-    };
-    
-    println!("Requesting registration for:");
-    println!("  Agent ID:   {}", config.agent.agent_id);
-    println!("  Agent Name: {}", config.agent.agent_name);
-    println!("");
-    
+fn handle_init(cli: &Cli) -> Result<CommandResult, String> {
+    let config = load_config(cli)?;
+
     // Collect system info
-    println!("Collecting system information...");
+    info!("Collecting system information...");
     let info = collector::common::collect_basic_info(&config);
-    
+
     // Generate fingerprint
-    println!("Generating device fingerprint...");
-    let device_fingerprint = match fingerprint::generate_fingerprint() {
-        Ok(fp) => {
-            println!("✓ Fingerprint: {}...", &fp[..16]);
-            fp
-        }
-        Err(e) => {
-            eprintln!("✗ Failed to generate fingerprint: {}", e);
-            std::process::exit(1);
-        }
-    };
-    
-    println!("");
-    
+    let device_fingerprint = fingerprint::generate_fingerprint()
+        .map_err(|e| format!("Failed to generate fingerprint: {}", e))?;
+
     // Prepare registration request
     let registration_data = serde_json::json!({
         "agent_id": config.agent.agent_id,
@@ -315,230 +383,600 @@ fn handle_init() {
         "os_version": info.os_version,
         "device_fingerprint": device_fingerprint,
     });
-    
+
     // Send registration request
     let registration_url = config.server.url.replace("/heartbeat/", "/agents/register/");
-    
-    println!("Sending registration request to:");
-    println!("  {}", registration_url);
-    println!("");
-    
+    info!("Sending registration request to {}", registration_url);
+
     let client = reqwest::blocking::Client::new();
-    match client
+    let response = client
         .post(&registration_url)
         .header("Content-Type", "application/json")
         .json(&registration_data)
         .send()
-    {
-        Ok(response) => {
-            let status_code = response.status();
-            
-            match response.json::<serde_json::Value>() {
-                Ok(json) => {
-                    if status_code.is_success() || status_code.as_u16() == 202 {
-                        println!("✓ Registration request submitted successfully!");
-                        println!("");
-                        if let Some(msg) = json.get("message").and_then(|v| v.as_str()) {
-                            println!("{}", msg);
-                        }
-                        println!("");
-                        println!("Next steps:");
-                        println!("1. Admin will review your request in Django admin");
-                        println!("2. Check status: device-agent --check-status");
-                        println!("3. Once approved, token will be saved automatically");
-                        println!("");
-                    } else {
-                        println!("✗ Registration failed ({})", status_code);
-                        println!("");
-                        if let Some(error) = json.get("error").and_then(|v| v.as_str()) {
-                            println!("Error: {}", error);
-                        }
-                        if let Some(msg) = json.get("message").and_then(|v| v.as_str()) {
-                            println!("{}", msg);
-                        }
-                        println!("");
-                        std::process::exit(1);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("✗ Failed to parse response: {}", e);
-                    std::process::exit(1);
-                }
+        .map_err(|e| format!(
+            "Failed to send registration request: {} (check that the backend is running, \
+            server.url is correct and the network is reachable)", e))?;
+
+    let status_code = response.status();
+    let json = response.json::<serde_json::Value>()
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let field = |name: &str| json.get(name).and_then(|v| v.as_str()).map(str::to_string);
+
+    let mut data = serde_json::json!({
+        "agent_id": config.agent.agent_id,
+        "agent_name": config.agent.agent_name,
+        "device_fingerprint": device_fingerprint,
+        "url": registration_url,
+    });
+    if let Some(msg) = field("message") {
+        data["message"] = serde_json::json!(msg);
+    }
+
+    if status_code.is_success() {
+        Ok(CommandResult::success("Registration request submitted successfully")
+            .with_data(data)
+            .with_hint("Next steps:")
+            .with_hint("1. Admin will review your request in Django admin")
+            .with_hint("2. Check status: device-agent status")
+            .with_hint("3. Once approved, token will be saved automatically"))
+    } else {
+        if let Some(error) = field("error") {
+            data["error"] = serde_json::json!(error);
+        }
+        Ok(CommandResult::failure(format!("Registration failed ({})", status_code)).with_data(data))
+    }
+}
+
+// ✅ NEW: Handle registration
+fn handle_register(token: &str) -> Result<CommandResult, String> {
+    crypto::save_token(token)
+        .map_err(|e| format!("Registration failed: {}", e))?;
+
+    Ok(CommandResult::success("Registration successful")
+        .with_data(serde_json::json!({ "location": crypto::get_token_location() }))
+        .with_hint("Your API token has been encrypted and stored securely.")
+        .with_hint("You can now start the agent: device-agent run"))
+}
+
+// ✅ NEW: Handle unregistration
+fn handle_unregister() -> Result<CommandResult, String> {
+    crypto::delete_token()
+        .map_err(|e| format!("Unregistration failed: {}", e))?;
+
+    Ok(CommandResult::success("Unregistration successful, API token deleted")
+        .with_hint("To use the agent again, register with: device-agent register <token>"))
+}
+
+// ✅ NEW: Check token status
+fn handle_check_token() -> Result<CommandResult, String> {
+    if !crypto::has_token() {
+        return Ok(CommandResult::failure("No API token found")
+            .with_hint("Register your device with: device-agent register <token>"));
+    }
+
+    let location = crypto::get_token_location();
+    // Try to load token to verify it's valid
+    Ok(match crypto::load_token() {
+        Ok(token) => CommandResult::success("API token is registered and can be decrypted")
+            .with_data(serde_json::json!({ "location": location, "length": token.len() })),
+        Err(e) => CommandResult::failure(format!("Token file exists but cannot be decrypted: {}", e))
+            .with_data(serde_json::json!({ "location": location }))
+            .with_hint("Try re-registering: device-agent register <token>"),
+    })
+}
+
+/// Collect once and print or save the payload, optionally sending it
+///
+/// Nothing is persisted: change tracking, the spool, delta state and sinks are
+/// left to the running agent, so this works without a registered token.
+fn handle_collect_once(cli: &Cli, only: &[Collector], file: Option<&str>, send: bool) -> Result<CommandResult, String> {
+    let mut config = load_config(cli).unwrap_or_else(|e| {
+        warn!("{}; using default configuration", e);
+        let mut config = Config::default();
+        apply_overrides(cli, &mut config);
+        config
+    });
+    if !only.is_empty() {
+        config.collection.include_services = only.contains(&Collector::Services);
+        config.collection.include_software = only.contains(&Collector::Software);
+    }
+
+    let mut info = collect_system_data(&config)?;
+    if config.vulnerabilities.enabled {
+        VulnerabilityDb::load(&config.vulnerabilities).scan(&mut info);
+    }
+    if config.policy.enabled {
+        PolicyEngine::load(&config.policy).evaluate(&mut info);
+    }
+    if config.software_lists.enabled {
+        SoftwareLists::load(&config).evaluate(&mut info);
+    }
+
+    let payload = serde_json::to_value(&info)
+        .map_err(|e| format!("Failed to serialize data: {}", e))?;
+    let json = serde_json::to_string_pretty(&payload)
+        .map_err(|e| format!("Failed to serialize data: {}", e))?;
+    match file {
+        Some(path) => std::fs::write(path, &json)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?,
+        None => println!("{}", json),
+    }
+
+    if send {
+        let transport = sender::create_transport(&config)
+            .map_err(|e| format!("Failed to set up {:?} transport: {}", config.server.transport, e))?;
+        transport.send(&payload)
+            .map_err(|e| format!("Failed to send payload: {}", e))?;
+        if file.is_none() {
+            eprintln!("✓ Payload sent over {}", transport.name());
+        }
+    }
+
+    Ok(match file {
+        Some(path) => CommandResult::success(format!("Payload written to {}", path))
+            .with_data(serde_json::json!({
+                "path": path,
+                "services": info.services.len(),
+                "packages": info.packages.len(),
+                "sent": send,
+            })),
+        None => CommandResult::printed(),
+    })
+}
+
+/// Run the diagnostics and print a pass/fail report
+fn handle_doctor(cli: &Cli) -> Result<CommandResult, String> {
+    let checks = doctor::run(&cli.config_path(), load_config(cli));
+    let count = |status| checks.iter().filter(|c| c.status == status).count();
+    let failed = count(doctor::CheckStatus::Fail);
+
+    if cli.output == OutputFormat::Text {
+        print!("{}", doctor::render(&checks));
+        println!();
+        println!("{} passed, {} failed, {} skipped",
+            count(doctor::CheckStatus::Pass), failed, count(doctor::CheckStatus::Skip));
+        let mut result = CommandResult::printed();
+        result.ok = failed == 0;
+        return Ok(result);
+    }
+
+    let message = format!("{} passed, {} failed, {} skipped",
+        count(doctor::CheckStatus::Pass), failed, count(doctor::CheckStatus::Skip));
+    let result = if failed == 0 { CommandResult::success(message) } else { CommandResult::failure(message) };
+    Ok(result.with_data(serde_json::json!({ "checks": checks })))
+}
+
+/// Print or check the configuration
+fn handle_config(cli: &Cli, action: ConfigCommand) -> Result<CommandResult, String> {
+    let (mut config, sources) = Config::load_layered(&cli.config_path())?;
+    apply_overrides(cli, &mut config);
+
+    match action {
+        ConfigCommand::Show => {
+            let shown = config.redacted();
+            if cli.output == OutputFormat::Json {
+                let data = serde_json::json!({ "sources": sources, "config": shown });
+                return Ok(CommandResult::success(format!("Configuration from {}", cli.config_path())).with_data(data));
+            }
+            let text = toml::to_string_pretty(&shown)
+                .map_err(|e| format!("Failed to serialize config: {}", e))?;
+            for source in &sources {
+                println!("# Source: {}", source);
+            }
+            print!("\n{}", text);
+            Ok(CommandResult::printed())
+        }
+        ConfigCommand::Validate => Ok(CommandResult::success(format!("Configuration from {} is valid", sources.join(", ")))
+            .with_data(serde_json::json!({ "path": cli.config_path(), "sources": sources }))),
+    }
+}
+
+/// Query the local snapshot history
+fn handle_history(cli: &Cli, action: HistoryCommand) -> Result<CommandResult, String> {
+    let config = load_config(cli)?;
+    let history = history::open_existing(&config.history_path(), &config.history)?;
+    let text = cli.output == OutputFormat::Text;
+
+    match action {
+        HistoryCommand::List { limit } => {
+            let snapshots = history.list(limit)?;
+            if text {
+                history::print_list(&snapshots);
+                return Ok(CommandResult::printed());
+            }
+            Ok(CommandResult::success(format!("{} snapshot(s)", snapshots.len()))
+                .with_data(serde_json::json!({ "snapshots": snapshots })))
+        }
+        HistoryCommand::Show { id } => {
+            let info = history.load(id)?;
+            if text {
+                let json = serde_json::to_string_pretty(&info)
+                    .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
+                println!("{}", json);
+                return Ok(CommandResult::printed());
+            }
+            let data = serde_json::to_value(&info)
+                .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
+            Ok(CommandResult::success(format!("Snapshot #{}", id)).with_data(data))
+        }
+        HistoryCommand::Diff { from, to } => {
+            let diff = history.diff(from, to)?;
+            if text {
+                history::print_diff(from, to, &diff);
+                return Ok(CommandResult::printed());
             }
+            let data = serde_json::to_value(&diff)
+                .map_err(|e| format!("Failed to serialize diff: {}", e))?;
+            Ok(CommandResult::success(format!("Snapshot #{} → #{}", from, to)).with_data(data))
+        }
+    }
+}
+
+/// Print the installed software as a CycloneDX or SPDX document
+fn handle_sbom(cli: &Cli, format: &str, file: Option<&str>) -> Result<CommandResult, String> {
+    let config = load_config(cli)?;
+    let (document, packages) = sbom::generate(format, &config)?;
+
+    match file {
+        Some(path) => {
+            let json = serde_json::to_string_pretty(&document)
+                .map_err(|e| format!("Failed to serialize SBOM: {}", e))?;
+            std::fs::write(path, json)
+                .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            Ok(CommandResult::success(format!("SBOM with {} packages written to {}", packages, path))
+                .with_data(serde_json::json!({ "path": path, "format": format, "packages": packages })))
         }
+        None if cli.output == OutputFormat::Json => {
+            Ok(CommandResult::success(format!("SBOM with {} packages", packages)).with_data(document))
+        }
+        None => {
+            let json = serde_json::to_string_pretty(&document)
+                .map_err(|e| format!("Failed to serialize SBOM: {}", e))?;
+            println!("{}", json);
+            Ok(CommandResult::printed())
+        }
+    }
+}
+
+/// Write a diagnostic archive to attach to a support request
+fn handle_support_bundle(cli: &Cli, file: Option<String>, logs: usize) -> Result<CommandResult, String> {
+    let (config, source) = match load_config(cli) {
+        Ok(config) => (config, cli.config_path()),
         Err(e) => {
-            eprintln!("");
-            eprintln!("✗ Failed to send registration request:");
-            eprintln!("  {}", e);
-            eprintln!("");
-            eprintln!("Make sure:");
-            eprintln!("- Backend server is running");
-            eprintln!("- URL in config.toml is correct");
-            eprintln!("- Network connectivity is working");
-            std::process::exit(1);
+            warn!("{}; bundling the defaults", e);
+            (Config::default(), format!("defaults ({})", e))
         }
+    };
+    let file = file.unwrap_or_else(|| format!("device-agent-support-{}.tar.gz", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+
+    let bundle = support_bundle::create(&config, &source, Path::new(&file), logs)?;
+    let mut result = CommandResult::success(format!("Support bundle written to {}", bundle.path.display()))
+        .with_data(serde_json::json!({
+            "path": bundle.path,
+            "files": bundle.files,
+            "missing": bundle.missing,
+        }));
+    if !bundle.missing.is_empty() {
+        result = result.with_hint("Some items were not available; see manifest.json in the bundle");
     }
+    Ok(result.with_hint("Secrets are redacted from config.toml, but review the logs before sharing the bundle"))
 }
 
-// ✅ NEW: Handle registration
-fn handle_register(args: &[String]) {
-    if args.len() < 3 {
-        eprintln!("✗ Error: Missing token argument");
-        eprintln!("");
-        eprintln!("Usage:");
-        eprintln!("  device-agent --register <api_token>");
-        eprintln!("");
-        eprintln!("Example:");
-        eprintln!("  device-agent --register agt_xxxxxxxxxxxxxxxxxxx");
-        eprintln!("");
-        std::process::exit(1);
+// ✅ NEW: Combined collect and save with proper error handling
+///
+/// The primary backend is served first; the other sinks are written afterwards
+/// and their failures are only logged. Returns the latest backend response,
+/// if a heartbeat was delivered.
+fn collect_and_save(config: &Config, delivery: &mut Delivery, sinks: &mut Sinks) -> Result<Option<HeartbeatResponse>, String> {
+    // Collect data
+    let mut info = retry_with_backoff(
+        "collect_system_data",
+        &retry_config(config),
+        || collect_system_data(config),
+    )?;
+    if let Some(changes) = delivery.changes.as_mut() {
+        changes.track(&mut info);
+    }
+    if let Some(vulnerabilities) = delivery.vulnerabilities.as_mut() {
+        vulnerabilities.scan(&mut info);
+    }
+    if let Some(policies) = delivery.policies.as_mut() {
+        policies.evaluate(&mut info);
+    }
+    if let Some(software_lists) = delivery.software_lists.as_mut() {
+        software_lists.evaluate(&mut info);
     }
     
-    let token = &args[2];
-    
-    println!("");
-    println!("=== Registering Device Agent ===");
-    println!("");
+    // ✅ NEW: Send to backend if enabled
+    let response = if config.server.enabled {
+        deliver(&info, config, delivery)
+    } else {
+        Ok(None)
+    };
+
+    let failed_sinks = sinks.write_all(&info);
+    if failed_sinks > 0 {
+        warn!("{} output sink(s) failing or still busy", failed_sinks);
+    }
     
-    match crypto::save_token(token) {
-        Ok(_) => {
-            println!("");
-            println!("✓ Registration successful!");
-            println!("");
-            println!("Your API token has been encrypted and stored securely.");
-            println!("You can now start the agent:");
-            println!("  device-agent");
-            println!("");
-            std::process::exit(0);
+    response
+}
+
+/// Send a heartbeat, falling back to the offline spool when the backend is unreachable
+///
+/// Spooled heartbeats are replayed before the current one so the backend
+/// receives them in collection order.
+fn deliver(info: &models::SystemInfo, config: &Config, delivery: &mut Delivery) -> Result<Option<HeartbeatResponse>, String> {
+    let result = deliver_or_spool(info, config, delivery);
+    if let Some(metrics) = &delivery.metrics {
+        // Delivered only if nothing (including the current heartbeat) is left in the spool
+        let spool_empty = delivery.spool.as_ref().is_none_or(|s| s.is_empty());
+        metrics.record_send(matches!(result, Ok(Some(_))) && spool_empty);
+    }
+    result
+}
+
+fn deliver_or_spool(info: &models::SystemInfo, config: &Config, delivery: &mut Delivery) -> Result<Option<HeartbeatResponse>, String> {
+    let Delivery { spool, delta, websocket, transport, .. } = delivery;
+    let mut transports: Vec<&dyn Transport> = Vec::new();
+    if let Some(websocket) = websocket.as_ref() {
+        transports.push(websocket);
+    }
+    if let Some(transport) = transport.as_ref() {
+        transports.push(transport.as_ref());
+    }
+
+    let spool = match spool {
+        Some(spool) => spool,
+        None => return send_heartbeat(info, delta, &transports).map(Some),
+    };
+
+    let mut latest = None;
+    let replayed = spool.replay(|queued| {
+        latest = Some(send_heartbeat(queued, delta, &transports)?);
+        Ok(())
+    }, sender::is_permanent_error);
+    if let Err(e) = replayed {
+        warn!("Backend still unreachable ({}), spooling current heartbeat", e);
+        spool.push(info)?;
+        return Ok(latest);
+    }
+
+    let result = retry_with_backoff(
+        "send_to_backend",
+        &retry_config(config),
+        || send_heartbeat(info, delta, &transports),
+    );
+
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(e) if sender::is_permanent_error(&e) => {
+            spool.reject(info, &e)?;
+            Err(e)
         }
         Err(e) => {
-            eprintln!("");
-            eprintln!("✗ Registration failed: {}", e);
-            eprintln!("");
-            std::process::exit(1);
+            warn!("Failed to deliver heartbeat ({}), spooling for later", e);
+            spool.push(info)?;
+            info!("Spool now holds {} heartbeat(s)", spool.len());
+            Ok(latest)
         }
     }
 }
 
-// ✅ NEW: Handle unregistration
-fn handle_unregister() {
-    println!("");
-    println!("=== Unregistering Device Agent ===");
-    println!("");
-    
-    match crypto::delete_token() {
-        Ok(_) => {
-            println!("");
-            println!("✓ Unregistration successful!");
-            println!("");
-            println!("Your API token has been deleted.");
-            println!("To use the agent again, register with:");
-            println!("  device-agent --register <token>");
-            println!("");
-            std::process::exit(0);
+/// Send one heartbeat as a full snapshot or a delta against the last acknowledged one
+///
+/// Transports are tried in order (WebSocket first when enabled, then the
+/// primary transport); the first one that delivers wins. A delta the backend
+/// refuses for good (e.g. a stale `base_version`) is resent as a full snapshot.
+fn send_heartbeat(
+    info: &models::SystemInfo,
+    delta: &mut DeltaTracker,
+    transports: &[&dyn Transport],
+) -> Result<HeartbeatResponse, String> {
+    let payload = delta.build_payload(info)?;
+    match send_over(transports, info, &payload, delta) {
+        Err(e) if payload.is_delta && sender::is_permanent_error(&e) => {
+            info!("Backend refused the delta ({}), resending as a full snapshot", e);
+            let full = delta.build_payload(info)?;
+            send_over(transports, info, &full, delta)
+        }
+        result => result,
+    }
+}
+
+fn send_over(
+    transports: &[&dyn Transport],
+    info: &models::SystemInfo,
+    payload: &delta::Payload,
+    delta: &mut DeltaTracker,
+) -> Result<HeartbeatResponse, String> {
+    let mut result = Err("No transport configured".to_string());
+    for transport in transports {
+        result = transport.send(&payload.body);
+        match &result {
+            Ok(_) => break,
+            Err(e) => debug!("Transport '{}' failed: {}", transport.name(), e),
+        }
+    }
+
+    match result {
+        Ok(response) => {
+            delta.acknowledge(info, payload, &response);
+            Ok(response)
         }
         Err(e) => {
-            eprintln!("");
-            eprintln!("✗ Unregistration failed: {}", e);
-            eprintln!("");
-            std::process::exit(1);
+            // The backend may have rejected our base version; resync with a full snapshot
+            if payload.is_delta {
+                delta.request_full();
+            }
+            Err(e)
         }
     }
 }
 
-// ✅ NEW: Check token status
-fn handle_check_token() {
-    println!("");
-    println!("=== Token Status ===");
-    println!("");
-    
-    if crypto::has_token() {
-        println!("✓ API token is registered");
-        println!("  Location: {}", crypto::get_token_location());
-        println!("");
-        
-        // Try to load token to verify it's valid
-        match crypto::load_token() {
-            Ok(token) => {
-                println!("✓ Token is valid and can be decrypted");
-                println!("  Length: {} characters", token.len());
-                println!("  Starts with: {}...", &token[..7]);
-                println!("");
-            }
-            Err(e) => {
-                eprintln!("✗ Token file exists but cannot be decrypted: {}", e);
-                eprintln!("");
-                eprintln!("Try re-registering:");
-                eprintln!("  device-agent --register <token>");
-                eprintln!("");
-                std::process::exit(1);
+/// Run the CIS checks when due and send the pending report as a `cis` payload
+///
+/// A report that could not be sent is retried every collection cycle until
+/// delivered or replaced by the next run.
+fn run_cis_checks(cis: &mut CisScheduler, config: &Config, delivery: &Delivery) {
+    if cis.due() {
+        cis.run(&config.agent.agent_id);
+    }
+    if !config.server.enabled {
+        cis.clear_pending();
+        return;
+    }
+    let Some(report) = cis.pending() else {
+        return;
+    };
+
+    let mut payload = match serde_json::to_value(report) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("✗ Failed to serialize CIS report: {}", e);
+            return;
+        }
+    };
+    payload["payload_type"] = serde_json::json!("cis");
+
+    match send_payload(&payload, config, delivery) {
+        Ok(_) => {
+            info!("✓ CIS report sent to backend");
+            cis.clear_pending();
+        }
+        Err(e) if sender::is_permanent_error(&e) => {
+            error!("✗ Backend rejected the CIS report, dropping it: {}", e);
+            cis.clear_pending();
+        }
+        Err(e) => warn!("✗ Failed to send CIS report (retrying next cycle): {}", e),
+    }
+}
+
+/// Deliver an alert right away to the backend and/or the webhook
+fn send_alert(alerter: &Alerter, alert: &models::Alert, config: &Config, delivery: &Delivery) {
+    match alert.state {
+        models::AlertState::Firing => warn!("Alert '{}' firing: {}", alert.rule, alert.message),
+        models::AlertState::Resolved => info!("Alert '{}' resolved: {}", alert.rule, alert.message),
+    }
+    let payload = alerter.payload(alert);
+
+    if config.server.enabled && config.alerts.send_to_backend {
+        match send_payload(&payload, config, delivery) {
+            Ok(_) => info!("✓ Alert sent to backend"),
+            Err(e) => error!("✗ Failed to send alert to backend: {}", e),
+        }
+    }
+    if let Some(url) = &config.alerts.webhook_url {
+        if let Err(e) = alerter.post_webhook(url, &payload) {
+            error!("✗ Failed to send alert to webhook: {}", e);
+        }
+    }
+}
+
+/// Send a standalone payload (not a heartbeat) over the first transport that delivers it
+fn send_payload(payload: &serde_json::Value, config: &Config, delivery: &Delivery) -> Result<HeartbeatResponse, String> {
+    let mut transports: Vec<&dyn Transport> = Vec::new();
+    if let Some(websocket) = delivery.websocket.as_ref() {
+        transports.push(websocket);
+    }
+    if let Some(transport) = delivery.transport.as_ref() {
+        transports.push(transport.as_ref());
+    }
+
+    retry_with_backoff("send_payload", &retry_config(config), || {
+        let mut result = Err("No transport configured".to_string());
+        for transport in &transports {
+            result = transport.send(payload);
+            match &result {
+                Ok(_) => break,
+                Err(e) => debug!("Transport '{}' failed: {}", transport.name(), e),
             }
         }
-        
-        std::process::exit(0);
-    } else {
-        println!("✗ No API token found");
-        println!("");
-        println!("Register your device with:");
-        println!("  device-agent --register <token>");
-        println!("");
-        std::process::exit(1);
-    }
-}
-
-// ✅ NEW: Print help
-fn print_help() {
-    println!("");
-    println!("Device Agent - System Monitoring Agent");
-    println!("");
-    println!("USAGE:");
-    println!("  device-agent [OPTIONS]");
-    println!("");
-    println!("OPTIONS:");
-    println!("  --register <token>, -r <token>    Register agent with API token");
-    println!("  --unregister, -u                   Remove stored API token");
-    println!("  --check-token, -c                  Check if token is registered");
-    println!("  --help, -h                         Show this help message");
-    println!("");
-    println!("EXAMPLES:");
-    println!("  # Register agent with token from Django admin");
-    println!("  device-agent --register agt_xxxxxxxxxxxxxxxxxxx");
-    println!("");
-    println!("  # Start agent (runs continuously)");
-    println!("  device-agent");
-    println!("");
-    println!("  # Check if token is registered");
-    println!("  device-agent --check-token");
-    println!("");
-    println!("  # Unregister (delete token)");
-    println!("  device-agent --unregister");
-    println!("");
-    println!("Get your API token from:");
-    println!("  http://localhost:8000/admin/agents/agenttoken/");
-    println!("");
+        result
+    })
 }
 
-// ✅ NEW: Combined collect and save with proper error handling
-fn collect_and_save(config: &Config) -> Result<(), String> {
-    // Collect data
-    let info = collect_system_data(config)?;
-    
-    // Save to file if enabled
-    if config.output.save_to_file {
-        save_to_file(&info, config)?;
+/// Apply config updates and commands pushed over the WebSocket
+///
+/// Returns true if the backend asked for an immediate collection.
+fn handle_pushes(
+    config: &mut Config,
+    websocket: Option<&WebSocketLink>,
+    mut command_runner: Option<&mut CommandRunner>,
+) -> bool {
+    let websocket = match websocket {
+        Some(websocket) => websocket,
+        None => return false,
+    };
+
+    let mut collect_now = false;
+    while let Some(push) = websocket.try_recv() {
+        control::apply_response(config, &push);
+
+        if push.commands.is_empty() {
+            continue;
+        }
+        match command_runner.as_deref_mut() {
+            Some(runner) => collect_now |= runner.run(push.commands, config),
+            None => warn!("Ignoring {} pushed command(s): remote commands are disabled", push.commands.len()),
+        }
     }
-    // ✅ NEW: Send to backend if enabled
-    if config.server.enabled {
-        send_to_backend(&info, config)?;
+
+    collect_now
+}
+
+/// Start the persistent WebSocket connection if enabled
+fn open_websocket(config: &Config) -> Option<WebSocketLink> {
+    if !config.server.enabled || !config.websocket.enabled {
+        return None;
     }
-    
-    Ok(())
+
+    match fingerprint::generate_fingerprint() {
+        Ok(device_fingerprint) => Some(WebSocketLink::start(config, device_fingerprint)),
+        Err(e) => {
+            error!("✗ WebSocket disabled, failed to generate fingerprint: {}", e);
+            None
+        }
+    }
+}
+
+/// Open the offline spool if backend delivery and spooling are enabled
+fn open_spool(config: &Config) -> Option<Spool> {
+    if !config.server.enabled || !config.spool.enabled {
+        return None;
+    }
+
+    match Spool::open(&config.spool_directory(), &config.spool) {
+        Ok(spool) => Some(spool),
+        Err(e) => {
+            error!("✗ Failed to open spool, undelivered heartbeats will be dropped: {}", e);
+            None
+        }
+    }
+}
+
+/// Long-lived delivery state shared across collection cycles
+struct Delivery {
+    spool: Option<Spool>,
+    delta: DeltaTracker,
+    websocket: Option<WebSocketLink>,
+    transport: Option<Box<dyn Transport>>,
+    metrics: Option<Metrics>,
+    changes: Option<ChangeTracker>,
+    vulnerabilities: Option<VulnerabilityDb>,
+    policies: Option<PolicyEngine>,
+    software_lists: Option<SoftwareLists>,
+}
+
+fn retry_config(config: &Config) -> retry::RetryConfig {
+    (&config.retry).into()
 }
 
 fn collect_system_data(config: &Config) -> Result<models::SystemInfo, String> {
     debug!("Starting system information collection");
     
-    let start_time = std::time::Instant::now();
+    let start_time = Instant::now();
     let info = collect_all_info(config);
     let elapsed = start_time.elapsed();
     
@@ -549,45 +987,53 @@ fn collect_system_data(config: &Config) -> Result<models::SystemInfo, String> {
     Ok(info)
 }
 
-fn save_to_file(info: &models::SystemInfo, config: &Config) -> Result<String, String> {
-    debug!("Preparing to save data to file");
-    
-    let timestamp = info.collected_at.format(&config.output.timestamp_format).to_string();
-    let filename = format!("{}/system_info_{}.json", config.output.output_directory, timestamp);
-    
-    let json = serde_json::to_string_pretty(&info)
-        .map_err(|e| format!("Failed to serialize data: {}", e))?;
-    
-    fs::write(&filename, json)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
-    
-    info!("✓ Data saved to: {}", filename);
-    Ok(filename)
-}
-
-fn sleep_with_interrupt(running: &Arc<AtomicBool>, seconds: u64) {
+/// Sleep in one-second steps, stopping early on shutdown or when `wake` returns true
+fn sleep_with_interrupt<F>(running: &Arc<AtomicBool>, seconds: u64, mut wake: F)
+where
+    F: FnMut() -> bool,
+{
     for _ in 0..seconds {
         if !running.load(Ordering::SeqCst) {
             debug!("Sleep interrupted by shutdown signal");
             break;
         }
+        if wake() {
+            info!("Collecting immediately as requested by backend");
+            break;
+        }
         thread::sleep(Duration::from_secs(1));
     }
 }
 
-/// Initialize logging with console and file support
-fn init_logger(config: &Config) -> Result<(), String> {
-    // Parse log level
-    let log_level = match config.logging.level.to_lowercase().as_str() {
+/// Parse a log level name, defaulting to info
+fn parse_level(level: &str) -> log::LevelFilter {
+    match level.to_lowercase().as_str() {
         "trace" => log::LevelFilter::Trace,
         "debug" => log::LevelFilter::Debug,
         "info" => log::LevelFilter::Info,
         "warn" => log::LevelFilter::Warn,
         "error" => log::LevelFilter::Error,
         _ => log::LevelFilter::Info,
-    };
+    }
+}
 
-    // Base configuration
+/// Log to stderr only, so command results on stdout stay parseable
+fn init_cli_logger(level: &str) -> Result<(), String> {
+    fern::Dispatch::new()
+        .format(|out, message, record| out.finish(format_args!("[{}] {}", record.level(), message)))
+        .level(parse_level(level))
+        .level_for("hyper", log::LevelFilter::Warn)
+        .level_for("reqwest", log::LevelFilter::Warn)
+        .chain(std::io::stderr())
+        .apply()
+        .map_err(|e| format!("Failed to initialize logger: {}", e))
+}
+
+/// Initialize logging with console and file support
+fn init_logger(config: &Config, otlp_logs: Option<&LogBuffer>) -> Result<(), String> {
+    let log_level = parse_level(&config.logging.level);
+
+    // Console/file output; formatted here so the OTLP copy keeps the raw message
     let mut dispatch = fern::Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
@@ -598,10 +1044,7 @@ fn init_logger(config: &Config) -> Result<(), String> {
                 message
             ))
         })
-        .level(log_level)
-        // Filter out noisy dependencies
-        .level_for("hyper", log::LevelFilter::Warn)
-        .level_for("reqwest", log::LevelFilter::Warn);
+        .level(log_level);
 
     // Add console output if enabled
     if config.logging.console {
@@ -628,8 +1071,23 @@ fn init_logger(config: &Config) -> Result<(), String> {
         }
     }
 
+    let mut root = fern::Dispatch::new()
+        // Filter out noisy dependencies
+        .level_for("hyper", log::LevelFilter::Warn)
+        .level_for("reqwest", log::LevelFilter::Warn)
+        .chain(dispatch);
+
+    // Copy records to the OTLP log buffer
+    if let Some(logs) = otlp_logs {
+        root = root.chain(
+            fern::Dispatch::new()
+                .level(parse_level(&config.otlp.log_level))
+                .chain(logs.output()),
+        );
+    }
+
     // Apply the logging configuration
-    dispatch.apply()
+    root.apply()
         .map_err(|e| format!("Failed to initialize logger: {}", e))?;
 
     Ok(())
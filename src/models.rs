@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SystemInfo {
     pub collected_at: DateTime<Utc>,
     pub agent_id: String,
@@ -14,10 +14,288 @@ pub struct SystemInfo {
     pub cpu_info: String,
     pub memory_total: u64,
     pub memory_available: u64,
+    // CPU and disk usage (defaulted so older spooled snapshots still load)
+    #[serde(default)]
+    pub cpu_count: usize,
+    #[serde(default)]
+    pub cpu_usage_percent: f32,
+    #[serde(default)]
+    pub load_average: LoadAverage,
+    #[serde(default)]
+    pub disks: Vec<DiskInfo>,
+    /// Breaches of the software allow/deny lists, ahead of the inventory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub software_violations: Vec<SoftwareViolation>,
     // Network Info - IP → [IPv6 addresses]
     pub ip_addresses: HashMap<String, Vec<String>>,
     // Services - list of service names
     pub services: Vec<String>,
     // Installed Software - list of app names
     pub installed_software: Vec<String>,
-}
\ No newline at end of file
+    /// Installed packages with versions (names also listed in `installed_software`)
+    #[serde(default)]
+    pub packages: Vec<Package>,
+    /// Inventory fields left empty because their collector is disabled, so an
+    /// empty list is not read as "everything was removed"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub not_collected: Vec<String>,
+    /// Changes since the previous collection
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<ChangeEvent>,
+    /// Installed packages matching the local advisory feeds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vulnerabilities: Vec<Vulnerability>,
+    /// Results of the local compliance policies, one report per policy file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compliance: Vec<PolicyReport>,
+}
+
+/// Installed package with the version reported by its package manager
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// `deb`, `rpm`, `macos` or `windows`
+    pub manager: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Source package, when it differs from `name` (deb); advisories are filed against it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl Package {
+    /// Package without architecture or source package
+    pub fn new(name: &str, version: &str, manager: &str) -> Self {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            manager: manager.to_string(),
+            arch: None,
+            source: None,
+        }
+    }
+}
+
+/// Known vulnerability affecting an installed package
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vulnerability {
+    /// Advisory ID (`CVE-...`, `DSA-...`, `RHSA-...`)
+    pub id: String,
+    /// CVE IDs of the advisory, including aliases
+    #[serde(default)]
+    pub cves: Vec<String>,
+    pub package: String,
+    pub installed_version: String,
+    /// None while no fix is available
+    pub fixed_version: Option<String>,
+    pub severity: Option<String>,
+    /// Feed the advisory came from (`osv` or `debian`)
+    pub source: String,
+}
+
+/// Forbidden software present, or required software missing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoftwareViolation {
+    /// Name of the list file
+    pub list: String,
+    pub rule: String,
+    pub kind: ViolationKind,
+    pub severity: String,
+    /// Matching package or service, or the pattern that matched nothing
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// A forbidden package is installed or a forbidden service is running
+    Forbidden,
+    /// A required package is not installed or a required service is not running
+    Missing,
+    /// A required package is installed outside the allowed version range
+    VersionNotAllowed,
+}
+
+/// Outcome of one policy file for one snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyReport {
+    pub policy: String,
+    /// Version declared in the policy file
+    pub version: String,
+    /// `sha256:<hex>` of the policy file, identifies the exact rule set
+    pub digest: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<RuleResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleResult {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub severity: String,
+    pub passed: bool,
+    /// What was observed, e.g. `memory_available_percent = 23.4 (expected > 10)`
+    pub evidence: String,
+}
+
+/// Result of the built-in CIS Level 1 checks, sent as its own `cis` payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CisReport {
+    pub collected_at: DateTime<Utc>,
+    pub agent_id: String,
+    pub device_fingerprint: String,
+    pub hostname: String,
+    /// e.g. `CIS Linux Level 1`
+    pub benchmark: String,
+    /// os-release ID (`debian`, `ubuntu`, `rhel`, ...)
+    pub distribution: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub not_applicable: usize,
+    pub results: Vec<CisResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CisResult {
+    pub id: String,
+    /// `filesystem`, `sysctl`, `ssh`, `password`, `auditing` or `permissions`
+    pub section: String,
+    pub title: String,
+    pub rationale: String,
+    pub remediation: String,
+    pub status: CisStatus,
+    /// What was observed on the host
+    pub evidence: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CisStatus {
+    Pass,
+    Fail,
+    /// The check doesn't apply (e.g. sshd is not installed)
+    NotApplicable,
+}
+
+/// Threshold alert sent out of band as an `alert` payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule: String,
+    pub state: AlertState,
+    pub severity: String,
+    /// Metric or service the rule watches, e.g. `disk_free_percent:/`
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    pub message: String,
+    pub detected_at: DateTime<Utc>,
+    /// Notifications of this rule dropped by rate limiting since the last one sent
+    pub suppressed: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// Something that changed between two consecutive collections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Collection time of the snapshot the change was first seen in
+    pub detected_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+    PackageInstalled { name: String, version: String },
+    /// Version changed (upgrade or downgrade)
+    PackageUpgraded { name: String, from_version: String, to_version: String },
+    PackageRemoved { name: String, version: String },
+    ServiceStarted { name: String },
+    ServiceStopped { name: String },
+    IpChanged { interface: String, added: Vec<String>, removed: Vec<String> },
+    MemoryChanged { from_bytes: u64, to_bytes: u64 },
+}
+
+/// 1, 5 and 15 minute load averages (zero on Windows)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+/// Mounted filesystem with its capacity in bytes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiskInfo {
+    pub mount_point: String,
+    pub file_system: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Backend response to a heartbeat
+///
+/// Everything beyond `status` is optional so older backends (and empty
+/// bodies) still parse; directives the agent acts on are applied by `control`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub device_id: Option<u64>,
+    pub device: Option<DeviceSummary>,
+    // Delta heartbeat acknowledgement
+    pub snapshot_version: Option<u64>,
+    pub full_snapshot_required: bool,
+    // Directives
+    pub collection_interval_seconds: Option<u64>,
+    /// Collector name → enabled (e.g. `{"software": false}`)
+    pub collectors: HashMap<String, bool>,
+    pub token_rotation: Option<TokenRotation>,
+    /// Remote commands queued for this agent
+    pub commands: Vec<RemoteCommand>,
+}
+
+/// Device summary echoed back by the backend
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DeviceSummary {
+    pub hostname: String,
+    pub os: String,
+    pub memory_usage_percent: Option<f64>,
+    pub services_count: Option<u64>,
+    pub software_count: Option<u64>,
+}
+
+/// Notice that the agent's API token is being replaced
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TokenRotation {
+    /// Replacement token; when absent the agent must re-check its registration
+    pub new_token: Option<String>,
+    pub message: Option<String>,
+}
+
+
+/// Command sent by the backend, via the heartbeat response or the commands endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RemoteCommand {
+    pub id: String,
+    pub action: String,
+    #[serde(default)]
+    pub args: HashMap<String, String>,
+}
@@ -0,0 +1,397 @@
+use crate::config::OtlpConfig;
+use crate::models::SystemInfo;
+use crate::sinks::Sink;
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const SCOPE_NAME: &str = "device-agent";
+
+/// Agent log records waiting to be exported
+#[derive(Clone)]
+pub struct LogBuffer {
+    records: Arc<Mutex<VecDeque<Value>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        LogBuffer {
+            records: Arc::new(Mutex::new(VecDeque::new())),
+            capacity,
+        }
+    }
+
+    /// fern output that copies every record into the buffer, dropping the oldest when full
+    pub fn output(&self) -> fern::Output {
+        let buffer = self.clone();
+        fern::Output::call(move |record| {
+            // Our own export errors would otherwise feed back into the next export
+            if record.target().starts_with(module_path!()) {
+                return;
+            }
+            buffer.push(log_record(record));
+        })
+    }
+
+    fn push(&self, record: Value) {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    fn drain(&self) -> Vec<Value> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner()).drain(..).collect()
+    }
+
+    /// Put records back after a failed export, keeping them ahead of newer ones
+    fn restore(&self, failed: Vec<Value>) {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        for record in failed.into_iter().rev() {
+            if records.len() >= self.capacity {
+                break;
+            }
+            records.push_front(record);
+        }
+    }
+}
+
+/// OTLP/HTTP (JSON encoding) exporter for metrics and agent logs
+pub struct OtlpExporter {
+    client: reqwest::blocking::Client,
+    config: OtlpConfig,
+    logs: Option<LogBuffer>,
+    /// Resource of the last exported snapshot, reused for log-only flushes
+    resource: Option<Value>,
+}
+
+impl OtlpExporter {
+    pub fn new(config: &OtlpConfig, logs: Option<LogBuffer>) -> Result<Self, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(|e| format!("Failed to create OTLP client: {}", e))?;
+
+        Ok(OtlpExporter {
+            client,
+            config: config.clone(),
+            logs,
+            resource: None,
+        })
+    }
+
+    /// Export a snapshot as metrics, then any buffered log records
+    fn export(&mut self, info: &SystemInfo) -> Result<(), String> {
+        let resource = resource(info);
+        self.resource = Some(resource.clone());
+
+        let body = json!({
+            "resourceMetrics": [{
+                "resource": resource,
+                "scopeMetrics": [{ "scope": scope(), "metrics": metrics(info) }],
+            }],
+        });
+        self.post("v1/metrics", &body)?;
+
+        self.flush_logs()
+    }
+
+    /// Export buffered log records; a no-op before the first snapshot
+    fn flush_logs(&self) -> Result<(), String> {
+        let (logs, resource) = match (&self.logs, &self.resource) {
+            (Some(logs), Some(resource)) => (logs, resource),
+            _ => return Ok(()),
+        };
+
+        let records = logs.drain();
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let count = records.len();
+        let body = json!({
+            "resourceLogs": [{
+                "resource": resource,
+                "scopeLogs": [{ "scope": scope(), "logRecords": &records }],
+            }],
+        });
+
+        if let Err(e) = self.post("v1/logs", &body) {
+            logs.restore(records);
+            return Err(e);
+        }
+
+        debug!("Exported {} log record(s) over OTLP", count);
+        Ok(())
+    }
+
+    fn post(&self, signal: &str, body: &Value) -> Result<(), String> {
+        let url = format!("{}/{}", self.config.endpoint.trim_end_matches('/'), signal);
+
+        let mut request = self.client.post(&url).json(body);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .send()
+            .map_err(|e| format!("Failed to send OTLP {}: {}", signal, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().unwrap_or_default();
+            return Err(format!("OTLP collector rejected {} ({}): {}", signal, status, text));
+        }
+
+        // Partial success responses still return 200; surface them in the log
+        if let Ok(reply) = response.json::<Value>() {
+            if let Some(partial) = reply.get("partialSuccess").filter(|p| p.as_object().is_some_and(|o| !o.is_empty())) {
+                warn!("OTLP collector partially rejected {}: {}", signal, partial);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for OtlpExporter {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        self.export(info)
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.flush_logs()
+    }
+}
+
+/// Host resource attributes following the OpenTelemetry semantic conventions
+fn resource(info: &SystemInfo) -> Value {
+    // OTel names macOS "darwin"; the other values already match
+    let os_type = match info.os_type.as_str() {
+        "macos" => "darwin",
+        other => other,
+    };
+
+    json!({
+        "attributes": [
+            attribute("service.name", SCOPE_NAME),
+            attribute("service.version", env!("CARGO_PKG_VERSION")),
+            attribute("service.instance.id", &info.agent_id),
+            attribute("host.name", &info.hostname),
+            attribute("host.id", &info.device_fingerprint),
+            attribute("os.type", os_type),
+            attribute("os.version", &info.os_version),
+        ],
+    })
+}
+
+fn scope() -> Value {
+    json!({ "name": SCOPE_NAME, "version": env!("CARGO_PKG_VERSION") })
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// Map the numeric `SystemInfo` fields to OTel gauges
+fn metrics(info: &SystemInfo) -> Vec<Value> {
+    let time = unix_nano(info.collected_at);
+    let int = |value: u64, attributes: Vec<Value>| {
+        json!({ "timeUnixNano": time, "asInt": value.to_string(), "attributes": attributes })
+    };
+    let double = |value: f64, attributes: Vec<Value>| {
+        json!({ "timeUnixNano": time, "asDouble": value, "attributes": attributes })
+    };
+
+    let memory_used = info.memory_total.saturating_sub(info.memory_available);
+    let mut filesystem_limit = Vec::new();
+    let mut filesystem_usage = Vec::new();
+    for disk in &info.disks {
+        let labels = || vec![
+            attribute("system.filesystem.mountpoint", &disk.mount_point),
+            attribute("system.filesystem.type", &disk.file_system),
+        ];
+        filesystem_limit.push(int(disk.total_bytes, labels()));
+
+        let mut used = labels();
+        used.push(attribute("system.filesystem.state", "used"));
+        filesystem_usage.push(int(disk.total_bytes.saturating_sub(disk.available_bytes), used));
+
+        let mut free = labels();
+        free.push(attribute("system.filesystem.state", "free"));
+        filesystem_usage.push(int(disk.available_bytes, free));
+    }
+
+    let load = &info.load_average;
+    vec![
+        gauge("system.memory.limit", "By", vec![int(info.memory_total, vec![])]),
+        gauge("system.memory.usage", "By", vec![
+            int(memory_used, vec![attribute("system.memory.state", "used")]),
+            int(info.memory_available, vec![attribute("system.memory.state", "free")]),
+        ]),
+        gauge("system.cpu.logical.count", "{cpu}", vec![int(info.cpu_count as u64, vec![])]),
+        gauge("system.cpu.utilization", "1", vec![double(info.cpu_usage_percent as f64 / 100.0, vec![])]),
+        gauge("system.cpu.load_average.1m", "{thread}", vec![double(load.one, vec![])]),
+        gauge("system.cpu.load_average.5m", "{thread}", vec![double(load.five, vec![])]),
+        gauge("system.cpu.load_average.15m", "{thread}", vec![double(load.fifteen, vec![])]),
+        gauge("system.filesystem.limit", "By", filesystem_limit),
+        gauge("system.filesystem.usage", "By", filesystem_usage),
+        gauge("device_agent.services.running", "{service}", vec![int(info.services.len() as u64, vec![])]),
+        gauge("device_agent.software.installed", "{package}", vec![int(info.installed_software.len() as u64, vec![])]),
+    ]
+}
+
+fn gauge(name: &str, unit: &str, data_points: Vec<Value>) -> Value {
+    json!({ "name": name, "unit": unit, "gauge": { "dataPoints": data_points } })
+}
+
+/// OTLP JSON encodes 64-bit integers as strings
+fn unix_nano(time: DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+/// Convert a `log` record to an OTLP LogRecord
+fn log_record(record: &log::Record) -> Value {
+    // Severity numbers from the OTel log data model
+    let severity_number = match record.level() {
+        log::Level::Trace => 1,
+        log::Level::Debug => 5,
+        log::Level::Info => 9,
+        log::Level::Warn => 13,
+        log::Level::Error => 17,
+    };
+    let now = unix_nano(Utc::now());
+
+    json!({
+        "timeUnixNano": now,
+        "observedTimeUnixNano": now,
+        "severityNumber": severity_number,
+        "severityText": record.level().to_string(),
+        "body": { "stringValue": record.args().to_string() },
+        "attributes": [attribute("log.target", record.target())],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DiskInfo;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Captured request: path, Authorization header and JSON body
+    type Captured = (String, Option<String>, Value);
+
+    /// Collector stand-in: accept `count` requests and capture each one
+    fn mock_collector(count: usize) -> (String, thread::JoinHandle<Vec<Captured>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..count {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+
+                let mut content_length = 0;
+                let mut auth = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        match name.to_lowercase().as_str() {
+                            "content-length" => content_length = value.trim().parse().unwrap(),
+                            "authorization" => auth = Some(value.trim().to_string()),
+                            _ => {}
+                        }
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut stream = stream;
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}").unwrap();
+                requests.push((path, auth, serde_json::from_slice(&body).unwrap()));
+            }
+            requests
+        });
+
+        (endpoint, handle)
+    }
+
+    fn attribute_value<'a>(attributes: &'a Value, key: &str) -> Option<&'a str> {
+        attributes.as_array()?.iter()
+            .find(|a| a["key"] == key)
+            .and_then(|a| a["value"]["stringValue"].as_str())
+    }
+
+    #[test]
+    fn test_exports_metrics_and_logs_to_collector() {
+        let (endpoint, collector) = mock_collector(2);
+        let config = OtlpConfig {
+            enabled: true,
+            endpoint,
+            headers: HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]),
+            ..OtlpConfig::default()
+        };
+
+        let logs = LogBuffer::new(10);
+        logs.push(json!({ "severityText": "INFO", "body": { "stringValue": "hello" } }));
+        let mut exporter = OtlpExporter::new(&config, Some(logs.clone())).unwrap();
+
+        exporter.export(&SystemInfo {
+            collected_at: Utc::now(),
+            agent_id: "agent-test".to_string(),
+            device_fingerprint: "f".repeat(64),
+            hostname: "host-1".to_string(),
+            os_type: "macos".to_string(),
+            memory_total: 2048,
+            memory_available: 512,
+            disks: vec![DiskInfo {
+                mount_point: "/".to_string(),
+                file_system: "apfs".to_string(),
+                total_bytes: 100,
+                available_bytes: 40,
+            }],
+            ..Default::default()
+        }).unwrap();
+
+        let requests = collector.join().unwrap();
+        let (path, auth, metrics) = &requests[0];
+        assert_eq!(path, "/v1/metrics");
+        assert_eq!(auth.as_deref(), Some("Bearer secret"), "Configured headers should be sent");
+
+        let attributes = &metrics["resourceMetrics"][0]["resource"]["attributes"];
+        assert_eq!(attribute_value(attributes, "host.name"), Some("host-1"));
+        assert_eq!(attribute_value(attributes, "os.type"), Some("darwin"));
+        assert_eq!(attribute_value(attributes, "host.id"), Some("f".repeat(64).as_str()));
+
+        let points = metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"].as_array().unwrap();
+        let limit = points.iter().find(|m| m["name"] == "system.memory.limit").unwrap();
+        assert_eq!(limit["gauge"]["dataPoints"][0]["asInt"], "2048");
+        let filesystem = points.iter().find(|m| m["name"] == "system.filesystem.usage").unwrap();
+        assert_eq!(filesystem["gauge"]["dataPoints"].as_array().unwrap().len(), 2);
+
+        let (path, _, exported_logs) = &requests[1];
+        assert_eq!(path, "/v1/logs");
+        let records = &exported_logs["resourceLogs"][0]["scopeLogs"][0]["logRecords"];
+        assert_eq!(records[0]["body"]["stringValue"], "hello");
+        assert!(logs.drain().is_empty(), "Exported log records should leave the buffer");
+    }
+}
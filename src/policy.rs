@@ -0,0 +1,497 @@
+use crate::config::PolicyConfig;
+use crate::models::{PolicyReport, RuleResult, SystemInfo};
+use crate::version;
+use crate::watch::RuleFiles;
+use log::{info, debug, warn};
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+
+/// A policy file (`.toml`, `.yaml` or `.yml`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct Policy {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Rule {
+    pub id: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "default_severity")]
+    pub severity: String,
+    #[serde(flatten)]
+    pub check: Check,
+}
+
+fn default_severity() -> String {
+    "medium".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Check {
+    ServiceRunning { name: String },
+    ServiceNotRunning { name: String },
+    /// Optionally at least `min_version` (package manager version rules)
+    PackageInstalled {
+        name: String,
+        #[serde(default)]
+        min_version: Option<String>,
+    },
+    PackageAbsent { name: String },
+    /// Compare a snapshot metric with `value`
+    Metric {
+        metric: Metric,
+        op: Comparison,
+        value: f64,
+        /// Disk metrics only; the fullest disk is used when not set
+        #[serde(default)]
+        mount: Option<String>,
+    },
+    /// The file contains `text` verbatim
+    FileContains { path: String, text: String },
+    /// A line of the file matches the regular expression
+    FileMatches { path: String, pattern: String },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    MemoryAvailablePercent,
+    MemoryAvailableBytes,
+    MemoryTotalBytes,
+    CpuUsagePercent,
+    CpuCount,
+    #[serde(rename = "load_1m")]
+    Load1m,
+    #[serde(rename = "load_5m")]
+    Load5m,
+    #[serde(rename = "load_15m")]
+    Load15m,
+    DiskAvailablePercent,
+    DiskAvailableBytes,
+    Vulnerabilities,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = "==")]
+    Equal,
+    #[serde(rename = "!=")]
+    NotEqual,
+}
+
+impl Comparison {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        }
+    }
+}
+
+impl Metric {
+    fn name(self) -> &'static str {
+        match self {
+            Metric::MemoryAvailablePercent => "memory_available_percent",
+            Metric::MemoryAvailableBytes => "memory_available_bytes",
+            Metric::MemoryTotalBytes => "memory_total_bytes",
+            Metric::CpuUsagePercent => "cpu_usage_percent",
+            Metric::CpuCount => "cpu_count",
+            Metric::Load1m => "load_1m",
+            Metric::Load5m => "load_5m",
+            Metric::Load15m => "load_15m",
+            Metric::DiskAvailablePercent => "disk_available_percent",
+            Metric::DiskAvailableBytes => "disk_available_bytes",
+            Metric::Vulnerabilities => "vulnerabilities",
+        }
+    }
+
+    /// Current value, or an error when the snapshot doesn't have it
+    fn value(self, info: &SystemInfo, mount: Option<&str>) -> Result<f64, String> {
+        Ok(match self {
+            Metric::MemoryAvailablePercent => {
+                if info.memory_total == 0 {
+                    return Err("memory_total is unknown".to_string());
+                }
+                info.memory_available as f64 * 100.0 / info.memory_total as f64
+            }
+            Metric::MemoryAvailableBytes => info.memory_available as f64,
+            Metric::MemoryTotalBytes => info.memory_total as f64,
+            Metric::CpuUsagePercent => info.cpu_usage_percent as f64,
+            Metric::CpuCount => info.cpu_count as f64,
+            Metric::Load1m => info.load_average.one,
+            Metric::Load5m => info.load_average.five,
+            Metric::Load15m => info.load_average.fifteen,
+            Metric::DiskAvailablePercent | Metric::DiskAvailableBytes => {
+                let disks = info.disks.iter()
+                    .filter(|d| mount.is_none_or(|m| d.mount_point == m) && d.total_bytes > 0);
+                let values = disks.map(|d| match self {
+                    Metric::DiskAvailablePercent => d.available_bytes as f64 * 100.0 / d.total_bytes as f64,
+                    _ => d.available_bytes as f64,
+                });
+                values.min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                    .ok_or_else(|| match mount {
+                        Some(mount) => format!("no disk mounted at {}", mount),
+                        None => "no disks collected".to_string(),
+                    })?
+            }
+            Metric::Vulnerabilities => info.vulnerabilities.len() as f64,
+        })
+    }
+}
+
+impl Check {
+    /// Whether the check passed, with the observation backing it
+    ///
+    /// Checks on a section listed in `info.not_collected` fail rather than
+    /// pass or fail on an empty list.
+    fn evaluate(&self, info: &SystemInfo) -> (bool, String) {
+        let section = match self {
+            Check::ServiceRunning { .. } | Check::ServiceNotRunning { .. } => Some("services"),
+            Check::PackageInstalled { .. } | Check::PackageAbsent { .. } => Some("packages"),
+            _ => None,
+        };
+        if let Some(section) = section.filter(|s| info.not_collected.iter().any(|f| f == s)) {
+            return (false, format!("Not evaluated: {} not collected", section));
+        }
+
+        match self {
+            Check::ServiceRunning { name } | Check::ServiceNotRunning { name } => {
+                let name = name.trim_end_matches(".service");
+                let running = info.services.iter().any(|s| s == name);
+                let evidence = format!("{} is {}", name, if running { "running" } else { "not running" });
+                (running == matches!(self, Check::ServiceRunning { .. }), evidence)
+            }
+            Check::PackageInstalled { name, min_version } => {
+                let Some(package) = info.packages.iter().find(|p| &p.name == name) else {
+                    return (false, format!("{} is not installed", name));
+                };
+                match min_version {
+                    Some(min) if version::compare(&package.manager, &package.version, min) == Ordering::Less => {
+                        (false, format!("{} {} is installed (expected >= {})", name, package.version, min))
+                    }
+                    _ => (true, format!("{} {} is installed", name, package.version)),
+                }
+            }
+            Check::PackageAbsent { name } => match info.packages.iter().find(|p| &p.name == name) {
+                Some(package) => (false, format!("{} {} is installed", name, package.version)),
+                None => (true, format!("{} is not installed", name)),
+            },
+            Check::Metric { metric, op, value, mount } => match metric.value(info, mount.as_deref()) {
+                Ok(actual) => (
+                    op.holds(actual, *value),
+                    format!("{} = {:.2} (expected {} {})", metric.name(), actual, op.symbol(), value),
+                ),
+                Err(e) => (false, format!("{}: {}", metric.name(), e)),
+            },
+            Check::FileContains { path, text } => match fs::read_to_string(path) {
+                Ok(contents) if contents.contains(text.as_str()) => (true, format!("{} contains \"{}\"", path, text)),
+                Ok(_) => (false, format!("{} does not contain \"{}\"", path, text)),
+                Err(e) => (false, format!("Failed to read {}: {}", path, e)),
+            },
+            Check::FileMatches { path, pattern } => {
+                let regex = match Regex::new(pattern) {
+                    Ok(regex) => regex,
+                    Err(e) => return (false, format!("Invalid pattern: {}", e)),
+                };
+                match fs::read_to_string(path) {
+                    Ok(contents) => match contents.lines().find(|line| regex.is_match(line)) {
+                        Some(line) => (true, format!("{} matches: {}", path, line.trim())),
+                        None => (false, format!("No line of {} matches /{}/", path, pattern)),
+                    },
+                    Err(e) => (false, format!("Failed to read {}: {}", path, e)),
+                }
+            }
+        }
+    }
+}
+
+impl Policy {
+    pub fn evaluate(&self, digest: &str, info: &SystemInfo) -> PolicyReport {
+        let results: Vec<RuleResult> = self.rules.iter()
+            .map(|rule| {
+                let (passed, evidence) = rule.check.evaluate(info);
+                RuleResult {
+                    id: rule.id.clone(),
+                    description: rule.description.clone(),
+                    severity: rule.severity.clone(),
+                    passed,
+                    evidence,
+                }
+            })
+            .collect();
+
+        let passed = results.iter().filter(|r| r.passed).count();
+        PolicyReport {
+            policy: self.name.clone(),
+            version: self.version.clone(),
+            digest: digest.to_string(),
+            passed,
+            failed: results.len() - passed,
+            results,
+        }
+    }
+}
+
+/// Parse a policy by file extension and reject rules that could never pass
+pub fn parse_policy(path: &Path, contents: &str) -> Result<Policy, String> {
+    let policy: Policy = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(contents)
+            .map_err(|e| format!("Failed to parse YAML: {}", e))?,
+        _ => toml::from_str(contents)
+            .map_err(|e| format!("Failed to parse TOML: {}", e))?,
+    };
+
+    let mut ids = std::collections::HashSet::new();
+    for rule in &policy.rules {
+        if !ids.insert(rule.id.as_str()) {
+            return Err(format!("Duplicate rule ID: {}", rule.id));
+        }
+        if let Check::FileMatches { pattern, .. } = &rule.check {
+            Regex::new(pattern).map_err(|e| format!("Rule {}: invalid pattern: {}", rule.id, e))?;
+        }
+    }
+    Ok(policy)
+}
+
+struct LoadedPolicy {
+    digest: String,
+    policy: Policy,
+}
+
+/// Policies from `[policy] paths`, reloaded when a file changes
+pub struct PolicyEngine {
+    policies: RuleFiles<LoadedPolicy>,
+}
+
+impl PolicyEngine {
+    pub fn load(config: &PolicyConfig) -> Self {
+        let mut engine = PolicyEngine { policies: RuleFiles::new(&config.paths) };
+        engine.reload_if_changed();
+        engine
+    }
+
+    /// Evaluate every policy against `info` and store the reports in `info.compliance`
+    pub fn evaluate(&mut self, info: &mut SystemInfo) {
+        self.reload_if_changed();
+
+        info.compliance = self.policies.iter()
+            .map(|loaded| loaded.policy.evaluate(&loaded.digest, info))
+            .collect();
+
+        for report in &info.compliance {
+            if report.failed > 0 {
+                warn!("Policy {} {}: {} of {} rules failed",
+                    report.policy, report.version, report.failed, report.results.len());
+            } else {
+                debug!("Policy {} {}: all {} rules passed", report.policy, report.version, report.passed);
+            }
+        }
+    }
+
+    fn reload_if_changed(&mut self) {
+        self.policies.reload_if_changed("policy", |file, previous| {
+            let loaded = load_file(file)?;
+            if previous.is_none_or(|p| p.digest != loaded.digest) {
+                info!("✓ Loaded policy {} {} ({} rules) from {}",
+                    loaded.policy.name, loaded.policy.version, loaded.policy.rules.len(), file.display());
+            }
+            Ok(loaded)
+        });
+    }
+}
+
+fn load_file(path: &Path) -> Result<LoadedPolicy, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let policy = parse_policy(path, &contents)?;
+    let digest = format!("sha256:{:x}", Sha256::digest(contents.as_bytes()));
+
+    Ok(LoadedPolicy { digest, policy })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DiskInfo, Package};
+
+    fn sample_info() -> SystemInfo {
+        SystemInfo {
+            memory_total: 1000,
+            memory_available: 80,
+            services: vec!["sshd".to_string()],
+            packages: vec![Package::new("openssl", "3.0.2-0ubuntu1.10", "deb")],
+            disks: vec![DiskInfo {
+                mount_point: "/".to_string(),
+                file_system: "ext4".to_string(),
+                total_bytes: 100,
+                available_bytes: 40,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_toml_and_yaml_rules_with_evidence() {
+        let dir = tempfile::tempdir().unwrap();
+        let sshd_config = dir.path().join("sshd_config");
+        fs::write(&sshd_config, "Port 22\nPermitRootLogin yes\n").unwrap();
+
+        let toml_policy = format!(r#"
+name = "baseline"
+version = "1.2.0"
+
+[[rules]]
+id = "sshd-running"
+type = "service_running"
+name = "sshd.service"
+
+[[rules]]
+id = "no-telnet"
+severity = "high"
+type = "package_absent"
+name = "telnet"
+
+[[rules]]
+id = "memory-headroom"
+type = "metric"
+metric = "memory_available_percent"
+op = ">"
+value = 10
+
+[[rules]]
+id = "no-root-login"
+type = "file_contains"
+path = "{}"
+text = "PermitRootLogin no"
+"#, sshd_config.display());
+        let policy = parse_policy(Path::new("baseline.toml"), &toml_policy).unwrap();
+        let report = policy.evaluate("sha256:test", &sample_info());
+
+        assert_eq!((report.policy.as_str(), report.version.as_str()), ("baseline", "1.2.0"));
+        assert_eq!((report.passed, report.failed), (2, 2));
+        let by_id = |id: &str| report.results.iter().find(|r| r.id == id).unwrap();
+        assert!(by_id("sshd-running").passed);
+        assert!(by_id("no-telnet").passed);
+        assert_eq!(by_id("no-telnet").severity, "high");
+        assert_eq!(by_id("memory-headroom").evidence, "memory_available_percent = 8.00 (expected > 10)");
+        assert!(!by_id("no-root-login").passed);
+
+        let yaml_policy = r#"
+name: packages
+version: "2"
+rules:
+  - id: openssl-patched
+    type: package_installed
+    name: openssl
+    min_version: 3.0.2-0ubuntu1.12
+  - id: root-disk
+    type: metric
+    metric: disk_available_percent
+    mount: /
+    op: ">="
+    value: 20
+  - id: port
+    type: file_matches
+    path: /nonexistent/sshd_config
+    pattern: "^Port\\s+22$"
+"#;
+        let policy = parse_policy(Path::new("packages.yaml"), yaml_policy).unwrap();
+        let report = policy.evaluate("sha256:test", &sample_info());
+        assert!(!report.results[0].passed, "1.10 is older than 1.12: {}", report.results[0].evidence);
+        assert!(report.results[1].passed);
+        assert!(report.results[2].evidence.starts_with("Failed to read"));
+
+        assert!(parse_policy(Path::new("bad.yaml"), "name: x\nversion: '1'\nrules:\n  - {id: a, type: file_matches, path: /x, pattern: '('}\n").is_err(),
+            "Invalid regular expressions are rejected at load time");
+
+        let example = parse_policy(Path::new("policy.example.toml"), include_str!("../policy.example.toml")).unwrap();
+        assert!(!example.rules.is_empty());
+    }
+
+    #[test]
+    fn test_uncollected_sections_are_not_evaluated() {
+        let policy = parse_policy(Path::new("p.toml"), r#"
+name = "p"
+version = "1"
+
+[[rules]]
+id = "no-telnet"
+type = "package_absent"
+name = "telnet"
+
+[[rules]]
+id = "sshd-running"
+type = "service_running"
+name = "sshd"
+"#).unwrap();
+        let info = SystemInfo {
+            packages: Vec::new(),
+            not_collected: vec!["installed_software".to_string(), "packages".to_string()],
+            ..sample_info()
+        };
+
+        let report = policy.evaluate("sha256:test", &info);
+        let no_telnet = report.results.iter().find(|r| r.id == "no-telnet").unwrap();
+        assert!(!no_telnet.passed, "An absent package list proves nothing");
+        assert_eq!(no_telnet.evidence, "Not evaluated: packages not collected");
+        assert!(report.results.iter().find(|r| r.id == "sshd-running").unwrap().passed);
+    }
+
+    #[test]
+    fn test_engine_reloads_and_keeps_last_good_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        fs::write(&path, "name = \"p\"\nversion = \"1\"\n[[rules]]\nid = \"a\"\ntype = \"service_running\"\nname = \"sshd\"\n").unwrap();
+
+        let config = PolicyConfig { enabled: true, paths: vec![dir.path().to_str().unwrap().to_string()] };
+        let mut engine = PolicyEngine::load(&config);
+        let mut info = sample_info();
+        engine.evaluate(&mut info);
+        assert_eq!(info.compliance[0].version, "1");
+        let first_digest = info.compliance[0].digest.clone();
+
+        // Force a reload regardless of the file system's timestamp resolution
+        fs::write(&path, "name = \"p\"\nversion = \"2\"\n").unwrap();
+        engine.policies.force_reload();
+        engine.evaluate(&mut info);
+        assert_eq!(info.compliance[0].version, "2");
+        assert_ne!(info.compliance[0].digest, first_digest);
+
+        fs::write(&path, "name = ").unwrap();
+        engine.policies.force_reload();
+        engine.evaluate(&mut info);
+        assert_eq!(info.compliance[0].version, "2", "A broken edit keeps the previous policy");
+    }
+}
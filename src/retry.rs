@@ -19,6 +19,16 @@ impl Default for RetryConfig {
     }
 }
 
+impl From<&crate::config::RetryConfig> for RetryConfig {
+    fn from(config: &crate::config::RetryConfig) -> Self {
+        RetryConfig {
+            max_retries: config.max_retries,
+            initial_delay_ms: config.initial_delay_ms,
+            max_delay_ms: config.max_delay_ms,
+        }
+    }
+}
+
 /// Retry a fallible operation with exponential backoff
 pub fn retry_with_backoff<F, T, E>(
     operation_name: &str,
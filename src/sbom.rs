@@ -0,0 +1,307 @@
+use crate::collector::collect_all_info;
+use crate::collector::common::Distro;
+use crate::config::{Config, SbomFormat};
+use crate::models::{Package, SystemInfo};
+use crate::sinks::Sink;
+use chrono::SecondsFormat;
+use log::debug;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+
+const TOOL_NAME: &str = "device-agent";
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Package URL for an installed package, e.g. `pkg:deb/ubuntu/openssl@3.0.2-0ubuntu1?arch=amd64&distro=ubuntu-22.04`
+///
+/// Only deb and rpm have a purl type; macOS and Windows applications use `pkg:generic`.
+pub fn purl(package: &Package, distro: Option<&Distro>) -> String {
+    let mut qualifiers: Vec<(&str, String)> = Vec::new();
+    if let Some(arch) = &package.arch {
+        qualifiers.push(("arch", arch.clone()));
+    }
+
+    let (kind, namespace, version) = match package.manager.as_str() {
+        "deb" | "rpm" => {
+            let namespace = distro
+                .map(|d| d.id.clone())
+                .unwrap_or_else(|| if package.manager == "deb" { "debian" } else { "redhat" }.to_string());
+            if let Some(d) = distro {
+                let name = match &d.version {
+                    Some(version) => format!("{}-{}", d.id, version),
+                    None => d.id.clone(),
+                };
+                qualifiers.push(("distro", name));
+            }
+
+            // rpm carries the epoch as a qualifier, deb keeps it in the version
+            let mut version = package.version.clone();
+            if package.manager == "rpm" {
+                if let Some((epoch, rest)) = package.version.split_once(':') {
+                    qualifiers.push(("epoch", epoch.to_string()));
+                    version = rest.to_string();
+                }
+            }
+            (package.manager.as_str(), Some(namespace), version)
+        }
+        _ => ("generic", None, package.version.clone()),
+    };
+
+    let mut purl = format!("pkg:{}/", kind);
+    if let Some(namespace) = namespace {
+        purl.push_str(&encode(&namespace.to_lowercase()));
+        purl.push('/');
+    }
+    purl.push_str(&encode(&package.name));
+    if !version.is_empty() {
+        purl.push('@');
+        purl.push_str(&encode(&version));
+    }
+
+    qualifiers.sort_by(|a, b| a.0.cmp(b.0));
+    if !qualifiers.is_empty() {
+        let qualifiers: Vec<String> = qualifiers.iter()
+            .map(|(key, value)| format!("{}={}", key, encode(value)))
+            .collect();
+        purl.push('?');
+        purl.push_str(&qualifiers.join("&"));
+    }
+    purl
+}
+
+/// Percent-encode everything but unreserved characters
+/// Percent-encode everything but RFC 3986 unreserved characters
+pub fn encode(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+/// Random (version 4) UUID for document serial numbers
+fn uuid_v4() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// CycloneDX 1.5 JSON document for the installed software
+pub fn cyclonedx(info: &SystemInfo, distro: Option<&Distro>) -> Value {
+    let components: Vec<Value> = info.packages.iter()
+        .map(|package| {
+            let purl = purl(package, distro);
+            json!({
+                "type": "library",
+                "bom-ref": purl,
+                "name": package.name,
+                "version": package.version,
+                "purl": purl,
+                "properties": [{ "name": "device-agent:package-manager", "value": package.manager }],
+            })
+        })
+        .collect();
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid_v4()),
+        "version": 1,
+        "metadata": {
+            "timestamp": info.collected_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "tools": {
+                "components": [{ "type": "application", "name": TOOL_NAME, "version": TOOL_VERSION }],
+            },
+            "component": {
+                "type": "device",
+                "bom-ref": info.device_fingerprint,
+                "name": info.hostname,
+                "description": format!("{} {}", info.os_type, info.os_version),
+            },
+        },
+        "components": components,
+    })
+}
+
+/// SPDX 2.3 JSON document for the installed software
+pub fn spdx(info: &SystemInfo, distro: Option<&Distro>) -> Value {
+    let mut packages = Vec::new();
+    let mut relationships = Vec::new();
+
+    for (index, package) in info.packages.iter().enumerate() {
+        // SPDX IDs only allow letters, digits, '.' and '-'
+        let safe_name: String = package.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '-' })
+            .collect();
+        let id = format!("SPDXRef-Package-{}-{}", index + 1, safe_name);
+
+        packages.push(json!({
+            "name": package.name,
+            "SPDXID": id,
+            "versionInfo": package.version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": purl(package, distro),
+            }],
+        }));
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": id,
+        }));
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-software", info.hostname),
+        "documentNamespace": format!("https://spdx.org/spdxdocs/{}-{}", TOOL_NAME, uuid_v4()),
+        "creationInfo": {
+            "created": info.collected_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "creators": [format!("Tool: {}-{}", TOOL_NAME, TOOL_VERSION)],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+pub fn document(format: SbomFormat, info: &SystemInfo, distro: Option<&Distro>) -> Value {
+    match format {
+        SbomFormat::CycloneDx => cyclonedx(info, distro),
+        SbomFormat::Spdx => spdx(info, distro),
+    }
+}
+
+pub fn render(format: SbomFormat, info: &SystemInfo, distro: Option<&Distro>) -> Result<String, String> {
+    serde_json::to_string_pretty(&document(format, info, distro))
+        .map_err(|e| format!("Failed to serialize SBOM: {}", e))
+}
+
+fn file_name(format: SbomFormat) -> &'static str {
+    match format {
+        SbomFormat::CycloneDx => "sbom.cdx.json",
+        SbomFormat::Spdx => "sbom.spdx.json",
+    }
+}
+
+/// Rewrites the SBOM documents in `[sbom] directory` after every collection
+pub struct SbomSink {
+    directory: PathBuf,
+    formats: Vec<SbomFormat>,
+    distro: Option<Distro>,
+}
+
+impl SbomSink {
+    pub fn new(directory: &str, formats: &[SbomFormat]) -> Result<Self, String> {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create SBOM directory: {}", e))?;
+
+        Ok(SbomSink {
+            directory: PathBuf::from(directory),
+            formats: formats.to_vec(),
+            distro: Distro::local(),
+        })
+    }
+}
+
+impl Sink for SbomSink {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        if info.packages.is_empty() {
+            debug!("No software collected, keeping the previous SBOM");
+            return Ok(());
+        }
+
+        for format in &self.formats {
+            let path = self.directory.join(file_name(*format));
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, render(*format, info, self.distro.as_ref())?)
+                .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+            fs::rename(&tmp_path, &path)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            debug!("SBOM written to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Collect the installed software for `device-agent sbom`, returning the document and its package count
+pub fn generate(format: &str, config: &Config) -> Result<(Value, usize), String> {
+    let format = match format {
+        "cyclonedx" => SbomFormat::CycloneDx,
+        "spdx" => SbomFormat::Spdx,
+        other => return Err(format!("Unknown SBOM format: {} (use cyclonedx or spdx)", other)),
+    };
+
+    let mut config = config.clone();
+    config.collection.include_software = true;
+    config.collection.include_services = false;
+    let info = collect_all_info(&config);
+    Ok((document(format, &info, Distro::local().as_ref()), info.packages.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_purls_per_package_manager() {
+        let ubuntu = Distro { id: "ubuntu".to_string(), version: Some("22.04".to_string()) };
+        assert_eq!(
+            purl(&Package { arch: Some("amd64".to_string()), ..Package::new("libstdc++6", "1:12.3.0-1ubuntu1", "deb") }, Some(&ubuntu)),
+            "pkg:deb/ubuntu/libstdc%2B%2B6@1%3A12.3.0-1ubuntu1?arch=amd64&distro=ubuntu-22.04"
+        );
+
+        let fedora = Distro { id: "fedora".to_string(), version: Some("40".to_string()) };
+        assert_eq!(
+            purl(&Package { arch: Some("x86_64".to_string()), ..Package::new("openssl", "1:3.2.1-2.fc40", "rpm") }, Some(&fedora)),
+            "pkg:rpm/fedora/openssl@3.2.1-2.fc40?arch=x86_64&distro=fedora-40&epoch=1",
+            "rpm epochs become a qualifier"
+        );
+
+        assert_eq!(purl(&Package::new("curl", "8.5", "deb"), None), "pkg:deb/debian/curl@8.5");
+        assert_eq!(purl(&Package::new("Google Chrome", "120.0", "macos"), None), "pkg:generic/Google%20Chrome@120.0");
+    }
+
+    #[test]
+    fn test_cyclonedx_and_spdx_documents() {
+        let info = SystemInfo {
+            collected_at: Utc::now(),
+            hostname: "host1".to_string(),
+            packages: vec![
+                Package { arch: Some("amd64".to_string()), ..Package::new("openssl", "3.0.2", "deb") },
+                Package { arch: Some("amd64".to_string()), ..Package::new("python3.10", "3.10.12", "deb") },
+            ],
+            ..Default::default()
+        };
+
+        let bom = cyclonedx(&info, None);
+        assert_eq!(bom["bomFormat"], "CycloneDX");
+        assert_eq!(bom["specVersion"], "1.5");
+        assert!(bom["serialNumber"].as_str().unwrap().starts_with("urn:uuid:"));
+        assert_eq!(bom["components"].as_array().unwrap().len(), 2);
+        assert_eq!(bom["components"][0]["purl"], "pkg:deb/debian/openssl@3.0.2?arch=amd64");
+
+        let doc = spdx(&info, None);
+        assert_eq!(doc["spdxVersion"], "SPDX-2.3");
+        assert_eq!(doc["packages"][1]["SPDXID"], "SPDXRef-Package-2-python3.10");
+        assert_eq!(doc["packages"][1]["externalRefs"][0]["referenceType"], "purl");
+        assert_eq!(doc["relationships"][1]["relatedSpdxElement"], "SPDXRef-Package-2-python3.10");
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = SbomSink::new(dir.path().to_str().unwrap(), &[SbomFormat::CycloneDx, SbomFormat::Spdx]).unwrap();
+        sink.write(&info).unwrap();
+        assert!(dir.path().join("sbom.cdx.json").exists());
+        assert!(dir.path().join("sbom.spdx.json").exists());
+    }
+}
@@ -0,0 +1,313 @@
+use super::Transport;
+use crate::config::{Compression, ServerConfig};
+use crate::crypto;
+use crate::models::HeartbeatResponse;
+use flate2::write::GzEncoder;
+use reqwest::blocking::Client;
+use std::io::Write;
+use std::time::Duration;
+use serde_json::Value;
+use log::{info, error, debug, warn};
+
+/// Heartbeats as HTTP POSTs to the Django backend
+pub struct HttpTransport {
+    server: ServerConfig,
+}
+
+impl HttpTransport {
+    pub fn new(server: &ServerConfig) -> Self {
+        HttpTransport { server: server.clone() }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    /// POST a heartbeat body and return the backend's parsed response
+    fn send(&self, payload: &Value) -> Result<HeartbeatResponse, String> {
+        debug!("Loading API token from encrypted storage...");
+        let api_token = crypto::load_token()
+            .map_err(|e| {
+                error!("Failed to load API token: {}", e);
+                e
+            })?;
+        
+        post_heartbeat(payload, &api_token, &self.server)
+    }
+}
+
+/// POST a heartbeat body with the given token, compressing it if configured
+fn post_heartbeat(payload: &Value, api_token: &str, server: &ServerConfig) -> Result<HeartbeatResponse, String> {
+    info!("Sending data to backend: {}", server.url);
+    debug!("Agent ID: {}", payload.get("agent_id").and_then(|v| v.as_str()).unwrap_or("unknown"));
+    debug!("Payload type: {}", payload.get("payload_type").and_then(|v| v.as_str()).unwrap_or("full"));
+    
+    // Create HTTP client with timeout
+    let client = Client::builder()
+        .timeout(Duration::from_secs(server.timeout_seconds))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let auth_header = format!("Bearer {}", api_token);
+
+    let json = serde_json::to_vec(payload)
+        .map_err(|e| format!("Failed to serialize data: {}", e))?;
+    let (body, encoding) = encode_body(json, server)?;
+    
+    // Send POST request
+    let mut request = client
+        .post(&server.url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", auth_header);
+    if let Some(encoding) = encoding {
+        request = request.header("Content-Encoding", encoding);
+    }
+    let response = request
+        .body(body)
+        .send()
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    
+    // Check response status
+    let status = response.status();
+    
+    if status.is_success() {
+        info!("✓ Data sent successfully (status: {})", status);
+        
+        // Log response body for debugging
+        match response.text() {
+            Ok(body) => {
+                debug!("Response body: {}", loggable_body(&body));
+                Ok(parse_response(&body))
+            }
+            Err(e) => {
+                warn!("Could not read response body: {}", e);
+                Ok(HeartbeatResponse::default())
+            }
+        }
+    } else {
+        let error_body = response
+            .text()
+            .unwrap_or_else(|_| "No error details available".to_string());
+        
+        match status.as_u16() {
+            401 => {
+                error!("✗ Authentication failed (401 Unauthorized)");
+                error!("   Check your API token in config.toml");
+                error!("   Try re-registering: device-agent register <new_token>");
+            }
+            403 => {
+                error!("✗ Access forbidden (403 Forbidden)");
+                error!("   Your token may have been revoked");
+            }
+            400 => {
+                error!("✗ Bad request (400 Bad Request)");
+                error!("   Error: {}", error_body);
+            }
+            500..=599 => {
+                error!("✗ Server error ({} Server Error)", status);
+                error!("   The backend server encountered an error");
+            }
+            _ => {
+                error!("✗ Unexpected error (status {})", status);
+            }
+        }
+        
+        Err(format!("Backend error ({}): {}", status, error_body))
+    }
+}
+
+/// Parse a successful heartbeat response, tolerating empty or unexpected bodies
+fn parse_response(body: &str) -> HeartbeatResponse {
+    if body.trim().is_empty() {
+        return HeartbeatResponse::default();
+    }
+
+    match serde_json::from_str(body) {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Could not parse heartbeat response: {}", e);
+            HeartbeatResponse::default()
+        }
+    }
+}
+
+/// Response body with any rotated token masked, safe for log files
+fn loggable_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut json) => {
+            if let Some(token) = json.pointer_mut("/token_rotation/new_token").filter(|t| !t.is_null()) {
+                *token = Value::from("********");
+            }
+            json.to_string()
+        }
+        Err(_) => format!("<{} bytes, not JSON>", body.len()),
+    }
+}
+
+/// Compress the request body if enabled and above the size threshold
+///
+/// Returns the body and the `Content-Encoding` to send with it, if any.
+fn encode_body(json: Vec<u8>, server: &ServerConfig) -> Result<(Vec<u8>, Option<&'static str>), String> {
+    if server.compression == Compression::None || json.len() < server.compression_threshold_bytes {
+        return Ok((json, None));
+    }
+
+    let original_len = json.len();
+    let (body, encoding) = match server.compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&json)
+                .and_then(|_| encoder.finish())
+                .map(|body| (body, "gzip"))
+                .map_err(|e| format!("Failed to gzip request body: {}", e))?
+        }
+        Compression::Zstd => {
+            zstd::encode_all(json.as_slice(), 0)
+                .map(|body| (body, "zstd"))
+                .map_err(|e| format!("Failed to zstd-compress request body: {}", e))?
+        }
+        Compression::None => unreachable!(),
+    };
+
+    debug!("Compressed request body with {}: {} -> {} bytes", encoding, original_len, body.len());
+    Ok((body, Some(encoding)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TransportKind;
+    use flate2::read::GzDecoder;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Captured request: Content-Encoding header and decompressed body
+    type Captured = (Option<String>, Value);
+
+    /// Accept one request, decompress its body and answer with a JSON ack
+    fn mock_backend() -> (String, thread::JoinHandle<Captured>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/heartbeat/", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut content_length = 0;
+            let mut encoding = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    match name.to_lowercase().as_str() {
+                        "content-length" => content_length = value.trim().parse().unwrap(),
+                        "content-encoding" => encoding = Some(value.trim().to_string()),
+                        _ => {}
+                    }
+                }
+            }
+
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let json = match encoding.as_deref() {
+                Some("gzip") => {
+                    let mut decoded = Vec::new();
+                    GzDecoder::new(body.as_slice()).read_to_end(&mut decoded).unwrap();
+                    decoded
+                }
+                Some("zstd") => zstd::decode_all(body.as_slice()).unwrap(),
+                _ => body,
+            };
+
+            let ack = r#"{"status":"success"}"#;
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                ack.len(),
+                ack
+            ).unwrap();
+
+            (encoding, serde_json::from_slice(&json).unwrap())
+        });
+
+        (url, handle)
+    }
+
+    fn server_config(url: String, compression: Compression, threshold: usize) -> ServerConfig {
+        ServerConfig {
+            enabled: true,
+            url,
+            timeout_seconds: 5,
+            transport: TransportKind::Http,
+            compression,
+            compression_threshold_bytes: threshold,
+        }
+    }
+
+    fn sample_payload() -> Value {
+        let software: Vec<String> = (0..500).map(|i| format!("package-{}", i)).collect();
+        serde_json::json!({ "agent_id": "agent-test", "installed_software": software })
+    }
+
+    #[test]
+    fn test_compressed_bodies_round_trip() {
+        for (compression, expected) in [(Compression::Gzip, "gzip"), (Compression::Zstd, "zstd")] {
+            let (url, handle) = mock_backend();
+            let payload = sample_payload();
+
+            let response = post_heartbeat(&payload, "agt_test", &server_config(url, compression, 1024)).unwrap();
+            assert_eq!(response.status, "success");
+
+            let (encoding, received) = handle.join().unwrap();
+            assert_eq!(encoding.as_deref(), Some(expected));
+            assert_eq!(received, payload, "Backend should see the original JSON after decompression");
+        }
+    }
+
+    #[test]
+    fn test_parse_response_directives() {
+        let response = parse_response(r#"{
+            "status": "success",
+            "device_id": 7,
+            "device": {"hostname": "box", "os": "linux 6.1", "memory_usage_percent": 42.5},
+            "collection_interval_seconds": 60,
+            "collectors": {"software": false},
+            "token_rotation": {"new_token": "agt_new"}
+        }"#);
+
+        assert_eq!(response.device_id, Some(7));
+        assert_eq!(response.device.unwrap().hostname, "box");
+        assert_eq!(response.collection_interval_seconds, Some(60));
+        assert_eq!(response.collectors.get("software"), Some(&false));
+        assert_eq!(response.token_rotation.unwrap().new_token.as_deref(), Some("agt_new"));
+        assert!(!response.full_snapshot_required);
+
+        let logged = loggable_body(r#"{"status": "success", "token_rotation": {"new_token": "agt_new"}}"#);
+        assert!(!logged.contains("agt_new") && logged.contains("********"), "{}", logged);
+        assert!(!loggable_body("token=agt_new").contains("agt_new"));
+
+        // Older backends and empty bodies still yield a usable response
+        assert_eq!(parse_response("").status, "");
+        assert_eq!(parse_response("not json").collection_interval_seconds, None);
+    }
+
+    #[test]
+    fn test_small_bodies_are_sent_uncompressed() {
+        let (url, handle) = mock_backend();
+        let payload = serde_json::json!({ "agent_id": "agent-test" });
+
+        post_heartbeat(&payload, "agt_test", &server_config(url, Compression::Gzip, 1024)).unwrap();
+
+        let (encoding, received) = handle.join().unwrap();
+        assert_eq!(encoding, None, "Payloads below the threshold should not be compressed");
+        assert_eq!(received, payload);
+    }
+}
@@ -0,0 +1,68 @@
+// src/sender/mod.rs
+
+pub mod http;
+pub mod mqtt;
+
+use crate::config::{Config, TransportKind};
+use crate::crypto;
+use crate::models::HeartbeatResponse;
+use serde_json::Value;
+
+/// A way of delivering heartbeat payloads to the backend
+pub trait Transport {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Deliver one heartbeat body and return the backend's response
+    ///
+    /// Transports without a response channel return a default response
+    /// once delivery is confirmed.
+    fn send(&self, payload: &Value) -> Result<HeartbeatResponse, String>;
+}
+
+/// Create the primary transport selected by `server.transport`
+pub fn create_transport(config: &Config) -> Result<Box<dyn Transport>, String> {
+    match config.server.transport {
+        TransportKind::Http => Ok(Box::new(http::HttpTransport::new(&config.server))),
+        TransportKind::Mqtt => {
+            let password = if config.mqtt.password_from_token {
+                Some(crypto::load_token()?)
+            } else {
+                None
+            };
+            Ok(Box::new(mqtt::MqttTransport::connect(config, password)?))
+        }
+    }
+}
+
+/// Whether a send error means the backend refused the payload for good
+///
+/// HTTP 4xx responses will fail the same way on every retry, except 408
+/// (timeout), 429 (rate limited) and 401/403: those reject the token, which
+/// is fixed by re-registration or rotation, not by the payload. Connection
+/// errors and 5xx are transient too.
+pub fn is_permanent_error(error: &str) -> bool {
+    let Some(rest) = error.strip_prefix("Backend error (") else {
+        return false;
+    };
+    let status: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    matches!(status.parse::<u16>(), Ok(400..=499) if !matches!(status.as_str(), "401" | "403" | "408" | "429"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permanent_errors() {
+        assert!(is_permanent_error("Backend error (400 Bad Request): invalid payload"));
+        assert!(is_permanent_error("Backend error (409 Conflict): stale base_version"));
+        assert!(!is_permanent_error("Backend error (401 Unauthorized): invalid token"));
+        assert!(!is_permanent_error("Backend error (403 Forbidden): token revoked"));
+        assert!(is_permanent_error("Backend error (413 Payload Too Large): "));
+        assert!(!is_permanent_error("Backend error (429 Too Many Requests): slow down"));
+        assert!(!is_permanent_error("Backend error (503 Service Unavailable): "));
+        assert!(!is_permanent_error("Failed to send request: connection refused"));
+        assert!(!is_permanent_error("MQTT broker not connected"));
+    }
+}
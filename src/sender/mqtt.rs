@@ -0,0 +1,356 @@
+use super::Transport;
+use crate::config::{Config, MqttConfig};
+use crate::models::HeartbeatResponse;
+use log::{info, debug, warn};
+use rumqttc::{Client, Connection, ConnectionError, Event, LastWill, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration};
+use serde_json::Value;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Pause between reconnect attempts after a connection error
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Heartbeats published to an MQTT broker
+///
+/// The broker's acknowledgement (PUBACK/PUBCOMP, or the write itself at
+/// QoS 0) counts as delivery; MQTT has no response channel, so the
+/// returned `HeartbeatResponse` is always empty. A retained "online"
+/// message is published on connect and the broker publishes the
+/// "offline" last will if the agent disappears.
+pub struct MqttTransport {
+    client: Client,
+    heartbeat_topic: String,
+    status_topic: String,
+    qos: QoS,
+    timeout: Duration,
+    confirmations: Receiver<()>,
+    connected: Arc<AtomicBool>,
+    /// Set on every (re)connect until the "online" status has been published
+    announce: Arc<AtomicBool>,
+}
+
+impl MqttTransport {
+    /// Configure the client and start its network thread
+    pub fn connect(config: &Config, password: Option<String>) -> Result<Self, String> {
+        let settings = &config.mqtt;
+        let agent_id = &config.agent.agent_id;
+        let qos = qos_from_level(settings.qos)?;
+        let status_topic = render_topic(&settings.status_topic, agent_id);
+
+        let client_id = settings.client_id
+            .clone()
+            .unwrap_or_else(|| format!("device-agent-{}", agent_id));
+        let mut options = MqttOptions::new(client_id, settings.broker_host.clone(), settings.broker_port);
+        options.set_keep_alive(Duration::from_secs(settings.keep_alive_seconds));
+        options.set_last_will(LastWill::new(status_topic.clone(), "offline", qos, true));
+        // Snapshots with large software lists exceed rumqttc's 10 KB default
+        options.set_max_packet_size(16 * 1024 * 1024, 16 * 1024 * 1024);
+
+        if let Some(password) = password {
+            let username = settings.username.clone().unwrap_or_else(|| agent_id.clone());
+            options.set_credentials(username, password);
+        }
+
+        if settings.tls {
+            options.set_transport(rumqttc::Transport::tls_with_config(tls_configuration(settings)?));
+        }
+
+        let (client, connection) = Client::new(options, 10);
+        let (confirm_tx, confirm_rx) = mpsc::channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let announce = Arc::new(AtomicBool::new(false));
+
+        let event_loop = EventLoop {
+            qos,
+            confirmations: confirm_tx,
+            connected: connected.clone(),
+            announce: announce.clone(),
+        };
+        thread::Builder::new()
+            .name("mqtt".to_string())
+            .spawn(move || event_loop.run(connection))
+            .map_err(|e| format!("Failed to spawn MQTT thread: {}", e))?;
+
+        info!("MQTT transport: {}:{} (QoS {})", settings.broker_host, settings.broker_port, settings.qos);
+
+        Ok(MqttTransport {
+            client,
+            heartbeat_topic: render_topic(&settings.heartbeat_topic, agent_id),
+            status_topic,
+            qos,
+            timeout: Duration::from_secs(config.server.timeout_seconds),
+            confirmations: confirm_rx,
+            connected,
+            announce,
+        })
+    }
+
+    /// Publish one message and wait for the broker to confirm it
+    ///
+    /// Publishes are serialized through `send`, so the next confirmation
+    /// always belongs to this message.
+    fn publish(&self, topic: &str, retain: bool, body: Vec<u8>) -> Result<(), String> {
+        // Discard confirmations of publishes that timed out earlier
+        while self.confirmations.try_recv().is_ok() {}
+
+        self.client
+            .publish(topic, self.qos, retain, body)
+            .map_err(|e| format!("Failed to publish to {}: {}", topic, e))?;
+
+        match self.confirmations.recv_timeout(self.timeout) {
+            Ok(()) => Ok(()),
+            Err(RecvTimeoutError::Timeout) => Err("Timed out waiting for broker acknowledgement".to_string()),
+            Err(RecvTimeoutError::Disconnected) => Err("MQTT connection thread has stopped".to_string()),
+        }
+    }
+
+    /// Wait for the broker connection, up to the send timeout
+    fn wait_connected(&self) -> bool {
+        let deadline = Instant::now() + self.timeout;
+        while !self.connected.load(Ordering::SeqCst) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        true
+    }
+}
+
+impl Transport for MqttTransport {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn send(&self, payload: &Value) -> Result<HeartbeatResponse, String> {
+        if !self.wait_connected() {
+            return Err("MQTT broker not connected".to_string());
+        }
+
+        if self.announce.swap(false, Ordering::SeqCst) {
+            if let Err(e) = self.publish(&self.status_topic, true, b"online".to_vec()) {
+                self.announce.store(true, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+
+        let body = serde_json::to_vec(payload)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+
+        info!("Publishing heartbeat to MQTT topic: {}", self.heartbeat_topic);
+        self.publish(&self.heartbeat_topic, false, body)?;
+
+        info!("✓ Heartbeat published (QoS {})", self.qos as u8);
+        Ok(HeartbeatResponse::default())
+    }
+}
+
+impl Drop for MqttTransport {
+    /// A clean DISCONNECT suppresses the last will, so publish "offline" ourselves
+    fn drop(&mut self) {
+        debug!("Disconnecting from MQTT broker");
+        let _ = self.client.try_publish(self.status_topic.clone(), self.qos, true, "offline");
+        let _ = self.client.try_disconnect();
+    }
+}
+
+/// Drives the rumqttc connection and reports publish confirmations
+struct EventLoop {
+    qos: QoS,
+    confirmations: Sender<()>,
+    connected: Arc<AtomicBool>,
+    announce: Arc<AtomicBool>,
+}
+
+impl EventLoop {
+    fn run(self, mut connection: Connection) {
+        for notification in connection.iter() {
+            match notification {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("✓ Connected to MQTT broker");
+                    self.announce.store(true, Ordering::SeqCst);
+                    self.connected.store(true, Ordering::SeqCst);
+                }
+                Ok(Event::Incoming(Packet::PubAck(_))) | Ok(Event::Incoming(Packet::PubComp(_))) => {
+                    let _ = self.confirmations.send(());
+                }
+                Ok(Event::Outgoing(Outgoing::Publish(_))) if self.qos == QoS::AtMostOnce => {
+                    let _ = self.confirmations.send(());
+                }
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                    self.connected.store(false, Ordering::SeqCst);
+                    debug!("MQTT connection closed");
+                    return;
+                }
+                Ok(_) => {}
+                // Every client handle has been dropped
+                Err(ConnectionError::RequestsDone) => return,
+                Err(e) => {
+                    if self.connected.swap(false, Ordering::SeqCst) {
+                        warn!("MQTT connection lost: {}", e);
+                    } else {
+                        debug!("MQTT connection failed: {}", e);
+                    }
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// Substitute `{agent_id}` in a topic template
+fn render_topic(template: &str, agent_id: &str) -> String {
+    template.replace("{agent_id}", agent_id)
+}
+
+fn qos_from_level(level: u8) -> Result<QoS, String> {
+    match level {
+        0 => Ok(QoS::AtMostOnce),
+        1 => Ok(QoS::AtLeastOnce),
+        2 => Ok(QoS::ExactlyOnce),
+        _ => Err(format!("Invalid MQTT QoS level: {} (expected 0, 1 or 2)", level)),
+    }
+}
+
+fn tls_configuration(settings: &MqttConfig) -> Result<TlsConfiguration, String> {
+    let ca_file = match &settings.ca_file {
+        Some(ca_file) => ca_file,
+        // System trust store
+        None => return Ok(TlsConfiguration::Native),
+    };
+
+    let ca = fs::read(ca_file)
+        .map_err(|e| format!("Failed to read MQTT CA file: {}", e))?;
+
+    let client_auth = match &settings.client_pkcs12_file {
+        Some(path) => {
+            let der = fs::read(path)
+                .map_err(|e| format!("Failed to read MQTT client certificate: {}", e))?;
+            Some((der, settings.client_pkcs12_password.clone().unwrap_or_default()))
+        }
+        None => None,
+    };
+
+    Ok(TlsConfiguration::SimpleNative { ca, client_auth })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    /// Packets the stub broker saw: client id, will topic, and (topic, payload) publishes
+    struct Received {
+        client_id: String,
+        will_topic: Option<String>,
+        publishes: Vec<(String, Vec<u8>)>,
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0u8; 1];
+        stream.read_exact(&mut header).ok()?;
+
+        let (mut length, mut shift) = (0usize, 0);
+        loop {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).ok()?;
+            length |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).ok()?;
+        Some((header[0], body))
+    }
+
+    fn read_string(body: &[u8], offset: &mut usize) -> String {
+        let len = u16::from_be_bytes([body[*offset], body[*offset + 1]]) as usize;
+        let value = String::from_utf8(body[*offset + 2..*offset + 2 + len].to_vec()).unwrap();
+        *offset += 2 + len;
+        value
+    }
+
+    /// Minimal MQTT 3.1.1 broker: CONNACK, PUBACK, PINGRESP; stops after `publishes` PUBLISH packets
+    fn stub_broker(publishes: usize) -> (u16, thread::JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Received { client_id: String::new(), will_topic: None, publishes: Vec::new() };
+
+            while received.publishes.len() < publishes {
+                let (header, body) = read_packet(&mut stream).expect("client disconnected early");
+                match header >> 4 {
+                    1 => {
+                        // Variable header: protocol name, level, flags, keep alive
+                        let mut offset = 0;
+                        read_string(&body, &mut offset);
+                        let flags = body[offset + 1];
+                        offset += 4;
+                        received.client_id = read_string(&body, &mut offset);
+                        if flags & 0x04 != 0 {
+                            received.will_topic = Some(read_string(&body, &mut offset));
+                        }
+                        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+                    }
+                    3 => {
+                        let qos = (header >> 1) & 0x03;
+                        let mut offset = 0;
+                        let topic = read_string(&body, &mut offset);
+                        if qos > 0 {
+                            let packet_id = [body[offset], body[offset + 1]];
+                            offset += 2;
+                            stream.write_all(&[0x40, 0x02, packet_id[0], packet_id[1]]).unwrap();
+                        }
+                        received.publishes.push((topic, body[offset..].to_vec()));
+                    }
+                    12 => stream.write_all(&[0xd0, 0x00]).unwrap(),
+                    _ => {}
+                }
+            }
+
+            received
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn test_render_topic() {
+        assert_eq!(render_topic("devices/{agent_id}/heartbeat", "laptop-001"), "devices/laptop-001/heartbeat");
+    }
+
+    #[test]
+    fn test_publish_heartbeat_to_stub_broker() {
+        let (port, broker) = stub_broker(2);
+
+        let mut config = Config::default();
+        config.agent.agent_id = "agent-test".to_string();
+        config.server.timeout_seconds = 5;
+        config.mqtt.broker_host = "127.0.0.1".to_string();
+        config.mqtt.broker_port = port;
+
+        let transport = MqttTransport::connect(&config, None).unwrap();
+        let payload = serde_json::json!({ "agent_id": "agent-test", "hostname": "box" });
+        transport.send(&payload).unwrap();
+
+        let received = broker.join().unwrap();
+        assert_eq!(received.client_id, "device-agent-agent-test");
+        assert_eq!(received.will_topic.as_deref(), Some("devices/agent-test/status"));
+
+        let heartbeat = received.publishes.iter()
+            .find(|(topic, _)| topic == "devices/agent-test/heartbeat")
+            .expect("heartbeat should be published");
+        assert_eq!(serde_json::from_slice::<Value>(&heartbeat.1).unwrap(), payload);
+        assert!(received.publishes.iter().any(|(topic, body)| topic == "devices/agent-test/status" && body == b"online"));
+    }
+}
@@ -0,0 +1,380 @@
+use super::Sink;
+use crate::config::{OutputConfig, OutputFormat, Rotation};
+use crate::models::SystemInfo;
+use chrono::{DateTime, Local};
+use flate2::write::GzEncoder;
+use log::{info, debug, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const FILE_PREFIX: &str = "system_info";
+
+const CSV_HEADER: &str = "collected_at,agent_id,hostname,os_type,os_version,cpu_count,cpu_usage_percent,\
+load_1m,load_5m,load_15m,memory_total,memory_available,disk_total_bytes,disk_available_bytes,\
+services_count,software_count,events_count";
+
+/// Snapshots written to `output_directory` in the configured format
+///
+/// `json` writes one file per collection. The other formats append to
+/// `system_info.<ext>`, which is rotated to `system_info-<timestamp>.<ext>`
+/// by day or size. Retention applies to all files but the active one.
+pub struct FileSink {
+    directory: PathBuf,
+    config: OutputConfig,
+}
+
+impl FileSink {
+    pub fn new(directory: &str, config: &OutputConfig) -> Result<Self, String> {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Failed to create output directory: {}", e))?;
+
+        Ok(FileSink {
+            directory: PathBuf::from(directory),
+            config: config.clone(),
+        })
+    }
+
+    fn extension(&self) -> &'static str {
+        match self.config.format {
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Msgpack => "msgpack",
+        }
+    }
+
+    fn active_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.{}", FILE_PREFIX, self.extension()))
+    }
+
+    fn write_json(&self, info: &SystemInfo) -> Result<PathBuf, String> {
+        let timestamp = info.collected_at.format(&self.config.timestamp_format).to_string();
+        let path = self.directory.join(format!("{}_{}.json", FILE_PREFIX, timestamp));
+
+        let json = serde_json::to_string_pretty(&info)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+
+        fs::write(&path, json)
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        Ok(path)
+    }
+
+    /// Append one record to the active file, rotating it first if due
+    fn append(&self, info: &SystemInfo) -> Result<PathBuf, String> {
+        let record = match self.config.format {
+            OutputFormat::Ndjson => {
+                let mut line = serde_json::to_vec(info)
+                    .map_err(|e| format!("Failed to serialize data: {}", e))?;
+                line.push(b'\n');
+                line
+            }
+            OutputFormat::Csv => csv_row(info).into_bytes(),
+            OutputFormat::Msgpack => rmp_serde::to_vec_named(info)
+                .map_err(|e| format!("Failed to serialize data: {}", e))?,
+            OutputFormat::Json => unreachable!("json is written one file per collection"),
+        };
+
+        let path = self.active_path();
+        if self.rotation_due(&path, record.len() as u64) {
+            self.rotate(&path)?;
+        } else if self.config.format == OutputFormat::Csv && stale_csv_header(&path) {
+            info!("CSV columns changed, starting a new {}", path.display());
+            self.rotate(&path)?;
+        }
+
+        let is_new = !path.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+        if is_new && self.config.format == OutputFormat::Csv {
+            writeln!(file, "{}", CSV_HEADER)
+                .map_err(|e| format!("Failed to write file: {}", e))?;
+        }
+        file.write_all(&record)
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+
+        Ok(path)
+    }
+
+    fn rotation_due(&self, path: &Path, incoming: u64) -> bool {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+
+        match self.config.rotation {
+            Rotation::Size => metadata.len() > 0 && metadata.len() + incoming > self.config.max_file_bytes,
+            Rotation::Daily => metadata
+                .modified()
+                .map(|modified| DateTime::<Local>::from(modified).date_naive() != Local::now().date_naive())
+                .unwrap_or(false),
+        }
+    }
+
+    /// Move the active file aside, gzipping it if configured
+    fn rotate(&self, path: &Path) -> Result<(), String> {
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let mut rotated = self.directory.join(format!("{}-{}.{}", FILE_PREFIX, timestamp, self.extension()));
+        let mut counter = 1;
+        while rotated.exists() || gz_path(&rotated).exists() {
+            rotated = self.directory.join(format!("{}-{}-{}.{}", FILE_PREFIX, timestamp, counter, self.extension()));
+            counter += 1;
+        }
+
+        fs::rename(path, &rotated)
+            .map_err(|e| format!("Failed to rotate {}: {}", path.display(), e))?;
+
+        if self.config.compress_rotated {
+            if let Err(e) = gzip(&rotated) {
+                warn!("Failed to compress rotated file {}: {}", rotated.display(), e);
+            }
+        }
+
+        info!("Rotated output file to {}", rotated.display());
+        Ok(())
+    }
+
+    /// Delete old output files beyond `max_files` or older than `max_age_days`
+    fn apply_retention(&self) {
+        if self.config.max_files == 0 && self.config.max_age_days == 0 {
+            return;
+        }
+
+        let active = self.active_path();
+        let mut files: Vec<(PathBuf, SystemTime)> = match fs::read_dir(&self.directory) {
+            Ok(dir) => dir
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| *path != active && is_output_file(path))
+                .filter_map(|path| {
+                    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                    Some((path, modified))
+                })
+                .collect(),
+            Err(e) => {
+                warn!("Failed to read output directory: {}", e);
+                return;
+            }
+        };
+
+        // Newest first
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+
+        let max_age = Duration::from_secs(self.config.max_age_days.saturating_mul(86_400));
+        let now = SystemTime::now();
+        for (index, (path, modified)) in files.iter().enumerate() {
+            let too_many = self.config.max_files > 0 && index >= self.config.max_files;
+            let too_old = self.config.max_age_days > 0
+                && now.duration_since(*modified).unwrap_or_default() > max_age;
+
+            if too_many || too_old {
+                match fs::remove_file(path) {
+                    Ok(()) => debug!("Removed old output file: {}", path.display()),
+                    Err(e) => warn!("Failed to remove old output file {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        debug!("Preparing to save data to file");
+
+        let path = match self.config.format {
+            OutputFormat::Json => self.write_json(info)?,
+            _ => self.append(info)?,
+        };
+
+        info!("✓ Data saved to: {}", path.display());
+        self.apply_retention();
+        Ok(())
+    }
+}
+
+/// An existing CSV file whose header differs from the columns written now
+fn stale_csv_header(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut header = String::new();
+    match BufReader::new(file).read_line(&mut header) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => header.trim_end() != CSV_HEADER,
+    }
+}
+
+/// Snapshot files and rotated files written by this sink
+fn is_output_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(FILE_PREFIX) && !name.ends_with(".tmp"))
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Replace `path` with `path.gz`
+fn gzip(path: &Path) -> io::Result<()> {
+    let target = gz_path(path);
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&target)?, flate2::Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Scalar metrics of a snapshot as one CSV line
+fn csv_row(info: &SystemInfo) -> String {
+    let disk_total: u64 = info.disks.iter().map(|d| d.total_bytes).sum();
+    let disk_available: u64 = info.disks.iter().map(|d| d.available_bytes).sum();
+
+    let fields = [
+        info.collected_at.to_rfc3339(),
+        info.agent_id.clone(),
+        info.hostname.clone(),
+        info.os_type.clone(),
+        info.os_version.clone(),
+        info.cpu_count.to_string(),
+        format!("{:.2}", info.cpu_usage_percent),
+        format!("{:.2}", info.load_average.one),
+        format!("{:.2}", info.load_average.five),
+        format!("{:.2}", info.load_average.fifteen),
+        info.memory_total.to_string(),
+        info.memory_available.to_string(),
+        disk_total.to_string(),
+        disk_available.to_string(),
+        info.services.len().to_string(),
+        info.installed_software.len().to_string(),
+        info.events.len().to_string(),
+    ];
+
+    let escaped: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", escaped.join(","))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use chrono::Utc;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn sample_info(hostname: &str) -> SystemInfo {
+        SystemInfo {
+            collected_at: Utc::now(),
+            agent_id: "agent-test".to_string(),
+            hostname: hostname.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn output_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_ndjson_rotates_by_size_with_retention_and_gzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = OutputConfig {
+            format: OutputFormat::Ndjson,
+            rotation: Rotation::Size,
+            max_file_bytes: 1,
+            max_files: 2,
+            compress_rotated: true,
+            ..Config::default().output
+        };
+        let mut sink = FileSink::new(dir.path().to_str().unwrap(), &config).unwrap();
+
+        for name in ["a", "b", "c", "d"] {
+            sink.write(&sample_info(name)).unwrap();
+        }
+
+        let files = output_files(dir.path());
+        assert_eq!(files.len(), 3, "Active file plus max_files rotated files: {:?}", files);
+        assert!(files.contains(&"system_info.ndjson".to_string()));
+        assert!(files.iter().filter(|f| f.ends_with(".ndjson.gz")).count() == 2);
+
+        let active = fs::read_to_string(dir.path().join("system_info.ndjson")).unwrap();
+        let latest: SystemInfo = serde_json::from_str(active.trim()).unwrap();
+        assert_eq!(latest.hostname, "d");
+
+        // Retention dropped the oldest record ("a")
+        let mut rotated: Vec<String> = files.iter()
+            .filter(|f| f.ends_with(".gz"))
+            .map(|f| {
+                let mut text = String::new();
+                GzDecoder::new(File::open(dir.path().join(f)).unwrap()).read_to_string(&mut text).unwrap();
+                serde_json::from_str::<SystemInfo>(text.trim()).unwrap().hostname
+            })
+            .collect();
+        rotated.sort();
+        assert_eq!(rotated, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_csv_writes_header_once_and_escapes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = OutputConfig {
+            format: OutputFormat::Csv,
+            ..Config::default().output
+        };
+        let mut sink = FileSink::new(dir.path().to_str().unwrap(), &config).unwrap();
+
+        sink.write(&sample_info("plain")).unwrap();
+        sink.write(&sample_info("with,comma")).unwrap();
+
+        let text = fs::read_to_string(dir.path().join("system_info.csv")).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[2].contains(",\"with,comma\","), "Fields with commas should be quoted: {}", lines[2]);
+    }
+
+    #[test]
+    fn test_csv_rotates_when_header_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = OutputConfig {
+            format: OutputFormat::Csv,
+            ..Config::default().output
+        };
+        let old = "collected_at,agent_id,hostname\n2026-01-01T00:00:00Z,agent-test,old\n";
+        fs::write(dir.path().join("system_info.csv"), old).unwrap();
+
+        let mut sink = FileSink::new(dir.path().to_str().unwrap(), &config).unwrap();
+        sink.write(&sample_info("new")).unwrap();
+
+        let text = fs::read_to_string(dir.path().join("system_info.csv")).unwrap();
+        assert_eq!(text.lines().next(), Some(CSV_HEADER));
+        assert_eq!(text.lines().count(), 2);
+        let rotated: Vec<String> = output_files(dir.path()).into_iter()
+            .filter(|f| f.starts_with("system_info-"))
+            .collect();
+        assert_eq!(rotated.len(), 1, "The old file is kept under a rotated name");
+        assert_eq!(fs::read_to_string(dir.path().join(&rotated[0])).unwrap(), old);
+    }
+}
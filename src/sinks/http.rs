@@ -0,0 +1,45 @@
+use super::Sink;
+use crate::models::SystemInfo;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// POSTs every full snapshot as JSON to an additional endpoint
+pub struct HttpSink {
+    client: reqwest::blocking::Client,
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+impl HttpSink {
+    pub fn new(url: &str, headers: &HashMap<String, String>, timeout_seconds: u64) -> Result<Self, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(timeout_seconds))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        Ok(HttpSink {
+            client,
+            url: url.to_string(),
+            headers: headers.clone(),
+        })
+    }
+}
+
+impl Sink for HttpSink {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        let mut request = self.client.post(&self.url).json(info);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .send()
+            .map_err(|e| format!("Failed to send to {}: {}", self.url, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("{} returned {}", self.url, status));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+mod file;
+mod http;
+mod syslog;
+
+use crate::config::{Config, SinkConfig, SinkKind};
+use crate::exporter::{self, Metrics};
+use crate::history::History;
+use crate::models::SystemInfo;
+use crate::otlp::{LogBuffer, OtlpExporter};
+use crate::sbom::SbomSink;
+use crate::retry::{self, retry_with_backoff};
+use log::{info, debug, warn, error};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub use file::FileSink;
+pub use http::HttpSink;
+pub use syslog::SyslogSink;
+
+/// How long shutdown waits for the sinks to finish and flush
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Destination for collected snapshots
+pub trait Sink: Send {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String>;
+
+    /// Push out anything still buffered, called on shutdown
+    fn flush(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A sink running on its own worker thread
+///
+/// The worker takes one snapshot at a time and retries it with the sink's
+/// backoff; at most one more snapshot waits, newer ones are dropped while the
+/// sink is busy. The collection loop never waits for a sink.
+struct SinkHandle {
+    name: String,
+    jobs: Option<SyncSender<Arc<SystemInfo>>>,
+    /// Whether the last write failed after all retries
+    failed: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SinkHandle {
+    fn spawn(name: String, mut sink: Box<dyn Sink>, retry: retry::RetryConfig) -> Result<Self, String> {
+        let (jobs, queue) = mpsc::sync_channel::<Arc<SystemInfo>>(1);
+        let failed = Arc::new(AtomicBool::new(false));
+        let worker_failed = failed.clone();
+        let operation = format!("sink '{}'", name);
+
+        let worker = thread::Builder::new()
+            .name(format!("sink-{}", name))
+            .spawn(move || {
+                for info in queue {
+                    match retry_with_backoff(&operation, &retry, || sink.write(&info)) {
+                        Ok(()) => {
+                            debug!("✓ Wrote snapshot to {}", operation);
+                            worker_failed.store(false, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            warn!("✗ Sink failed: {}: {}", operation, e);
+                            worker_failed.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                if let Err(e) = sink.flush() {
+                    warn!("✗ Failed to flush {}: {}", operation, e);
+                }
+            })
+            .map_err(|e| format!("Failed to start worker thread: {}", e))?;
+
+        Ok(SinkHandle { name, jobs: Some(jobs), failed, worker: Some(worker) })
+    }
+}
+
+/// All configured sinks; each one retries and fails on its own thread
+pub struct Sinks {
+    sinks: Vec<SinkHandle>,
+    metrics: Option<Metrics>,
+}
+
+impl Sinks {
+    /// Build the sinks from `config.effective_sinks()`, skipping (and logging) any that fail to start
+    pub fn from_config(config: &Config, otlp_logs: Option<LogBuffer>) -> Self {
+        let mut sinks = Sinks { sinks: Vec::new(), metrics: None };
+
+        for sink_config in config.effective_sinks() {
+            let name = sink_config.name.clone().unwrap_or_else(|| kind_name(&sink_config.kind).to_string());
+            match sinks.build(&sink_config, config, otlp_logs.clone()) {
+                Ok(sink) => {
+                    let retry = sink_config.retry.as_ref().unwrap_or(&config.retry).into();
+                    match SinkHandle::spawn(name.clone(), sink, retry) {
+                        Ok(handle) => {
+                            info!("Output sink enabled: {}", name);
+                            sinks.sinks.push(handle);
+                        }
+                        Err(e) => error!("✗ Failed to set up sink '{}': {}", name, e),
+                    }
+                }
+                Err(e) => error!("✗ Failed to set up sink '{}': {}", name, e),
+            }
+        }
+
+        sinks
+    }
+
+    fn build(&mut self, sink_config: &SinkConfig, config: &Config, otlp_logs: Option<LogBuffer>) -> Result<Box<dyn Sink>, String> {
+        Ok(match &sink_config.kind {
+            SinkKind::File { directory } => Box::new(FileSink::new(
+                directory.as_deref().unwrap_or(&config.output.output_directory),
+                &config.output,
+            )?),
+            SinkKind::Http { url, headers, timeout_seconds } => {
+                Box::new(HttpSink::new(url, headers, *timeout_seconds)?)
+            }
+            SinkKind::History => {
+                let path = config.history_path();
+                if let Some(parent) = std::path::Path::new(&path).parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create history directory: {}", e))?;
+                }
+                Box::new(History::open(&path, &config.history)?)
+            }
+            SinkKind::Syslog(syslog) => Box::new(SyslogSink::new(syslog, config.collection.track_changes)?),
+            SinkKind::Sbom => Box::new(SbomSink::new(&config.sbom_directory(), &config.sbom.formats)?),
+            SinkKind::Otlp => Box::new(OtlpExporter::new(&config.otlp, otlp_logs)?),
+            SinkKind::Prometheus => {
+                if self.metrics.is_some() {
+                    return Err("Only one Prometheus exporter can be configured".to_string());
+                }
+                let metrics = Metrics::default();
+                exporter::start(&config.exporter, metrics.clone())?;
+                self.metrics = Some(metrics.clone());
+                Box::new(metrics)
+            }
+        })
+    }
+
+    /// Metrics shared with the Prometheus exporter, if one is configured
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Hand a snapshot to every sink without waiting for the writes
+    ///
+    /// Returns the number of sinks that are unhealthy: the last write failed,
+    /// or the sink is still busy and this snapshot was dropped for it.
+    pub fn write_all(&mut self, info: &SystemInfo) -> usize {
+        let info = Arc::new(info.clone());
+        let mut unhealthy = 0;
+        for handle in &mut self.sinks {
+            let Some(jobs) = &handle.jobs else {
+                continue;
+            };
+            match jobs.try_send(info.clone()) {
+                Ok(()) => {
+                    if handle.failed.load(Ordering::Relaxed) {
+                        unhealthy += 1;
+                    }
+                }
+                Err(TrySendError::Full(_)) => {
+                    warn!("✗ Sink '{}' is still busy with earlier snapshots, skipping this one", handle.name);
+                    unhealthy += 1;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    error!("✗ Sink '{}' worker stopped", handle.name);
+                    handle.jobs = None;
+                    unhealthy += 1;
+                }
+            }
+        }
+        unhealthy
+    }
+
+    /// Let the workers finish queued snapshots and flush, waiting up to `FLUSH_TIMEOUT`
+    pub fn flush_all(&mut self) {
+        for handle in &mut self.sinks {
+            handle.jobs = None;
+        }
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        for handle in &mut self.sinks {
+            let Some(worker) = handle.worker.take() else {
+                continue;
+            };
+            while !worker.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(50));
+            }
+            if worker.is_finished() {
+                let _ = worker.join();
+            } else {
+                warn!("✗ Sink '{}' did not finish within {:?}; abandoning it", handle.name, FLUSH_TIMEOUT);
+            }
+        }
+    }
+}
+
+/// Whether an OTLP sink is configured (its log buffer must exist before the logger starts)
+pub fn wants_otlp_logs(config: &Config) -> bool {
+    config.otlp.export_logs
+        && config.effective_sinks().iter().any(|s| s.kind == SinkKind::Otlp)
+}
+
+fn kind_name(kind: &SinkKind) -> &'static str {
+    match kind {
+        SinkKind::File { .. } => "file",
+        SinkKind::Http { .. } => "http",
+        SinkKind::Otlp => "otlp",
+        SinkKind::Prometheus => "prometheus",
+        SinkKind::History => "history",
+        SinkKind::Syslog(_) => "syslog",
+        SinkKind::Sbom => "sbom",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::sync::Mutex;
+
+    /// Records the hostnames it is asked to write, failing every write when `fail` is set
+    struct TestSink {
+        fail: bool,
+        written: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Sink for TestSink {
+        fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+            self.written.lock().unwrap().push(info.hostname.clone());
+            if self.fail {
+                return Err("sink down".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    /// Blocks every write until the test lets it go
+    struct StuckSink {
+        started: Sender<()>,
+        release: Receiver<()>,
+    }
+
+    impl Sink for StuckSink {
+        fn write(&mut self, _info: &SystemInfo) -> Result<(), String> {
+            let _ = self.started.send(());
+            self.release.recv().map_err(|e| e.to_string())
+        }
+    }
+
+    fn handle(name: &str, sink: Box<dyn Sink>) -> SinkHandle {
+        let retry = retry::RetryConfig { max_retries: 1, initial_delay_ms: 1, max_delay_ms: 1 };
+        SinkHandle::spawn(name.to_string(), sink, retry).unwrap()
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        condition()
+    }
+
+    #[test]
+    fn test_failing_sink_does_not_block_others() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let attempted = Arc::new(Mutex::new(Vec::new()));
+        let (started, writing) = mpsc::channel();
+        let (release, stuck) = mpsc::channel();
+        let mut sinks = Sinks {
+            sinks: vec![
+                handle("stuck", Box::new(StuckSink { started, release: stuck })),
+                handle("dead", Box::new(TestSink { fail: true, written: attempted.clone() })),
+                handle("alive", Box::new(TestSink { fail: false, written: written.clone() })),
+            ],
+            metrics: None,
+        };
+
+        let info = SystemInfo { hostname: "host-1".to_string(), ..Default::default() };
+        let started = Instant::now();
+        sinks.write_all(&info);
+        assert!(started.elapsed() < Duration::from_secs(1), "Writes must not wait for the sinks");
+        assert!(wait_for(|| written.lock().unwrap().len() == 1), "Sinks after a stuck or failing one should still be written");
+        assert!(wait_for(|| sinks.sinks[1].failed.load(Ordering::Relaxed)));
+
+        // The stuck sink holds one snapshot and queues one more, then drops
+        writing.recv_timeout(Duration::from_secs(5)).unwrap();
+        sinks.write_all(&info);
+        assert!(wait_for(|| written.lock().unwrap().len() == 2 && attempted.lock().unwrap().len() == 2));
+        assert_eq!(sinks.write_all(&info), 2, "The stuck sink is busy and the dead one failed");
+        assert!(wait_for(|| written.lock().unwrap().len() == 3));
+
+        for _ in 0..2 {
+            release.send(()).unwrap();
+        }
+        sinks.flush_all();
+        assert!(sinks.sinks.iter().all(|h| h.worker.is_none()), "Workers finish on shutdown");
+    }
+
+    #[test]
+    fn test_effective_sinks_from_config() {
+        let mut config = Config::default();
+        config.exporter.enabled = true;
+        let kinds: Vec<_> = config.effective_sinks().into_iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, vec![SinkKind::File { directory: None }, SinkKind::Prometheus]);
+
+        let listed: Config = toml::from_str(r#"
+            [collection]
+            interval_seconds = 60
+            [output]
+            output_directory = "./data"
+            save_to_file = true
+            timestamp_format = "%Y"
+            [logging]
+            level = "info"
+            console = true
+            file = false
+            log_directory = "./logs"
+            [agent]
+            agent_id = "a"
+            agent_name = "A"
+            [retry]
+            max_retries = 3
+            initial_delay_ms = 100
+            max_delay_ms = 1000
+            [server]
+            enabled = false
+            url = "http://localhost/api/heartbeat/"
+            timeout_seconds = 5
+
+            [[sinks]]
+            type = "http"
+            name = "archive"
+            url = "http://archive.local/ingest"
+            retry = { max_retries = 1, initial_delay_ms = 10, max_delay_ms = 10 }
+        "#).unwrap();
+        let sinks = listed.effective_sinks();
+        assert_eq!(sinks.len(), 1, "Listed sinks should replace the implicit ones");
+        assert_eq!(sinks[0].name.as_deref(), Some("archive"));
+        assert!(matches!(&sinks[0].kind, SinkKind::Http { url, timeout_seconds: 30, .. } if url == "http://archive.local/ingest"));
+        assert_eq!(sinks[0].retry.as_ref().map(|r| r.max_retries), Some(1));
+    }
+}
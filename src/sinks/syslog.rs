@@ -0,0 +1,425 @@
+use super::Sink;
+use crate::changes;
+use crate::config::{SyslogConfig, SyslogProtocol};
+use crate::models::{Change, ChangeEvent, SystemInfo};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info};
+use std::fs;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const APP_NAME: &str = "device-agent";
+
+// Syslog severities (RFC 5424 section 6.2.1)
+const SEVERITY_NOTICE: u8 = 5;
+const SEVERITY_INFO: u8 = 6;
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<native_tls::TlsStream<TcpStream>>),
+}
+
+/// RFC 5424 messages over UDP, TCP or TLS (RFC 5425 octet-counting framing)
+///
+/// Each snapshot produces a summary message plus one message per change event
+/// (service started/stopped, package installed/upgraded/removed, ...). Without
+/// `collection.track_changes` the sink diffs consecutive snapshots itself; that
+/// baseline lives in memory only, so the first snapshot after a start has no events.
+pub struct SyslogSink {
+    config: SyslogConfig,
+    facility: u8,
+    connection: Option<Connection>,
+    /// Snapshot whose messages were partly sent, and how many, so a retry sends only the rest
+    sent: Option<(DateTime<Utc>, usize)>,
+    /// `Some` when the agent does not track changes; holds the last delivered snapshot
+    baseline: Option<Option<SystemInfo>>,
+}
+
+impl SyslogSink {
+    pub fn new(config: &SyslogConfig, track_changes: bool) -> Result<Self, String> {
+        let facility = facility_code(&config.facility)
+            .ok_or_else(|| format!("Unknown syslog facility: {}", config.facility))?;
+
+        if !track_changes {
+            info!("collection.track_changes is off: the syslog sink derives change events itself");
+        }
+
+        Ok(SyslogSink {
+            config: config.clone(),
+            facility,
+            connection: None,
+            sent: None,
+            baseline: (!track_changes).then_some(None),
+        })
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        let address = format!("{}:{}", self.config.host, self.config.port());
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+
+        match self.config.protocol {
+            SyslogProtocol::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .map_err(|e| format!("Failed to open UDP socket: {}", e))?;
+                socket.connect(&address)
+                    .map_err(|e| format!("Failed to resolve syslog server {}: {}", address, e))?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogProtocol::Tcp => Ok(Connection::Tcp(tcp_connect(&address, timeout)?)),
+            SyslogProtocol::Tls => {
+                let mut builder = native_tls::TlsConnector::builder();
+                if let Some(ca_file) = &self.config.ca_file {
+                    let pem = fs::read(ca_file)
+                        .map_err(|e| format!("Failed to read syslog CA file: {}", e))?;
+                    let certificate = native_tls::Certificate::from_pem(&pem)
+                        .map_err(|e| format!("Invalid syslog CA file: {}", e))?;
+                    builder.add_root_certificate(certificate);
+                }
+                let connector = builder.build()
+                    .map_err(|e| format!("Failed to set up TLS: {}", e))?;
+
+                let stream = tcp_connect(&address, timeout)?;
+                stream.set_read_timeout(Some(timeout))
+                    .map_err(|e| format!("Failed to set read timeout: {}", e))?;
+                let stream = connector.connect(&self.config.host, stream)
+                    .map_err(|e| format!("TLS handshake with {} failed: {}", address, e))?;
+                Ok(Connection::Tls(Box::new(stream)))
+            }
+        }
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), String> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect()?);
+        }
+
+        let result = match self.connection.as_mut() {
+            Some(Connection::Udp(socket)) => socket.send(message.as_bytes()).map(|_| ()),
+            Some(Connection::Tcp(stream)) => write_framed(stream, message),
+            Some(Connection::Tls(stream)) => write_framed(stream.as_mut(), message),
+            None => unreachable!(),
+        };
+
+        result.map_err(|e| {
+            // Reconnect on the next attempt
+            self.connection = None;
+            format!("Failed to send syslog message: {}", e)
+        })
+    }
+
+    /// Summary plus change events for one snapshot
+    fn messages(&self, info: &SystemInfo) -> Vec<String> {
+        let id = &self.config.enterprise_id;
+        let mut messages = vec![self.format(
+            SEVERITY_INFO,
+            info.collected_at,
+            &info.hostname,
+            "snapshot",
+            &element(&format!("snapshot@{}", id), &[
+                ("agentId", info.agent_id.clone()),
+                ("osType", info.os_type.clone()),
+                ("osVersion", info.os_version.clone()),
+                ("memoryTotal", info.memory_total.to_string()),
+                ("memoryAvailable", info.memory_available.to_string()),
+                ("cpuUsage", format!("{:.1}", info.cpu_usage_percent)),
+                ("services", info.services.len().to_string()),
+                ("software", info.installed_software.len().to_string()),
+            ]),
+            "Inventory snapshot",
+        )];
+
+        let derived = match &self.baseline {
+            Some(Some(previous)) => changes::diff(previous, info),
+            _ => Vec::new(),
+        };
+        let events = if self.baseline.is_some() { &derived } else { &info.events };
+        for event in events {
+            messages.push(self.event_message(info, event));
+        }
+
+        messages
+    }
+
+    /// One change event as `[change@<id> type="..." ...]`
+    fn event_message(&self, info: &SystemInfo, event: &ChangeEvent) -> String {
+        let msg_id = match &event.change {
+            Change::PackageInstalled { .. } | Change::PackageUpgraded { .. } | Change::PackageRemoved { .. } => "package",
+            Change::ServiceStarted { .. } | Change::ServiceStopped { .. } => "service",
+            Change::IpChanged { .. } => "network",
+            Change::MemoryChanged { .. } => "memory",
+        };
+
+        // Every field of the change becomes an SD param; lists are comma-joined
+        let fields = match serde_json::to_value(&event.change) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => Default::default(),
+        };
+        let params: Vec<(&str, String)> = fields
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Array(items) => items
+                        .iter()
+                        .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    other => other.to_string(),
+                };
+                (key.as_str(), value)
+            })
+            .collect();
+
+        let kind = fields.get("type").and_then(|t| t.as_str()).unwrap_or("change");
+        self.format(
+            SEVERITY_NOTICE,
+            event.detected_at,
+            &info.hostname,
+            msg_id,
+            &element(&format!("change@{}", self.config.enterprise_id), &params),
+            &kind.replace('_', " "),
+        )
+    }
+
+    /// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD MSG`
+    fn format(&self, severity: u8, time: DateTime<Utc>, hostname: &str, msg_id: &str, data: &str, text: &str) -> String {
+        format!(
+            "<{}>1 {} {} {} {} {} {} {}",
+            self.facility as u16 * 8 + severity as u16,
+            time.to_rfc3339_opts(SecondsFormat::Micros, true),
+            header_field(hostname, 255),
+            APP_NAME,
+            std::process::id(),
+            msg_id,
+            data,
+            text,
+        )
+    }
+}
+
+impl Sink for SyslogSink {
+    fn write(&mut self, info: &SystemInfo) -> Result<(), String> {
+        let messages = self.messages(info);
+        let already_sent = match self.sent {
+            Some((collected_at, sent)) if collected_at == info.collected_at => sent,
+            _ => 0,
+        };
+
+        for (index, message) in messages.iter().enumerate().skip(already_sent) {
+            if let Err(e) = self.send(message) {
+                self.sent = Some((info.collected_at, index));
+                return Err(e);
+            }
+        }
+
+        self.sent = None;
+        if let Some(baseline) = self.baseline.as_mut() {
+            *baseline = Some(info.clone());
+        }
+        debug!("Sent {} syslog message(s)", messages.len() - already_sent);
+        Ok(())
+    }
+}
+
+/// Try each resolved address in turn, each bounded by `timeout`
+fn tcp_connect(address: &str, timeout: Duration) -> Result<TcpStream, String> {
+    let addresses = address.to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve syslog server {}: {}", address, e))?;
+
+    let mut last_error = format!("Syslog server {} resolved to no addresses", address);
+    for resolved in addresses {
+        match TcpStream::connect_timeout(&resolved, timeout) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(timeout))
+                    .map_err(|e| format!("Failed to set write timeout: {}", e))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = format!("Failed to connect to syslog server {} ({}): {}", address, resolved, e),
+        }
+    }
+    Err(last_error)
+}
+
+/// Octet-counting framing: `<length> <message>`
+fn write_framed<W: Write>(stream: &mut W, message: &str) -> std::io::Result<()> {
+    stream.write_all(format!("{} {}", message.len(), message).as_bytes())?;
+    stream.flush()
+}
+
+/// One structured-data element; param values escape `"`, `\` and `]`
+fn element(id: &str, params: &[(&str, String)]) -> String {
+    let mut out = format!("[{}", id);
+    for (name, value) in params {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace(']', "\\]");
+        out.push_str(&format!(" {}=\"{}\"", name, escaped));
+    }
+    out.push(']');
+    out
+}
+
+/// Header fields are printable ASCII without spaces; `-` stands for empty
+fn header_field(value: &str, max_len: usize) -> String {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if cleaned.is_empty() { "-".to_string() } else { cleaned }
+}
+
+fn facility_code(name: &str) -> Option<u8> {
+    Some(match name.to_lowercase().as_str() {
+        "kern" => 0,
+        "user" => 1,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "authpriv" => 10,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    fn snapshot(services: &[&str], software: &[&str]) -> SystemInfo {
+        SystemInfo {
+            collected_at: Utc::now(),
+            agent_id: "agent-test".to_string(),
+            hostname: "host 1".to_string(),
+            services: services.iter().map(|s| s.to_string()).collect(),
+            installed_software: software.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn config(protocol: SyslogProtocol, port: u16) -> SyslogConfig {
+        SyslogConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            protocol,
+            facility: "local0".to_string(),
+            ..SyslogConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_udp_snapshot_and_change_events() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut sink = SyslogSink::new(&config(SyslogProtocol::Udp, port), true).unwrap();
+
+        let receive = || {
+            let mut buffer = [0u8; 2048];
+            let len = listener.recv(&mut buffer).unwrap();
+            String::from_utf8(buffer[..len].to_vec()).unwrap()
+        };
+
+        sink.write(&snapshot(&["sshd", "cron"], &["vim \"9\""])).unwrap();
+        let summary = receive();
+        // local0 (16) * 8 + info (6) = 134
+        assert!(summary.starts_with("<134>1 "), "Unexpected header: {}", summary);
+        assert!(summary.contains(" host1 device-agent "), "Hostname should be stripped of spaces: {}", summary);
+        assert!(summary.contains(" snapshot [snapshot@32473 agentId=\"agent-test\""));
+        assert!(summary.contains("services=\"2\""));
+
+        let mut changed = snapshot(&["sshd", "nginx"], &[]);
+        changed.events = vec![
+            ChangeEvent { detected_at: changed.collected_at, change: Change::ServiceStarted { name: "nginx".to_string() } },
+            ChangeEvent { detected_at: changed.collected_at, change: Change::ServiceStopped { name: "cron".to_string() } },
+            ChangeEvent {
+                detected_at: changed.collected_at,
+                change: Change::PackageRemoved { name: "vim \"9\"".to_string(), version: "9.0".to_string() },
+            },
+        ];
+        sink.write(&changed).unwrap();
+        let mut messages: Vec<String> = (0..4).map(|_| receive()).collect();
+        messages.remove(0); // summary
+        assert!(messages.iter().any(|m| m.starts_with("<133>1 ")
+            && m.contains(" service [change@32473 name=\"nginx\" type=\"service_started\"] service started")),
+            "Unexpected service event: {:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("type=\"service_stopped\"")));
+        assert!(messages.iter().any(|m| m.contains("[change@32473 name=\"vim \\\"9\\\"\" type=\"package_removed\" version=\"9.0\"]")),
+            "Quotes in param values should be escaped: {:?}", messages);
+    }
+
+    #[test]
+    fn test_retry_sends_only_the_unsent_messages() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut sink = SyslogSink::new(&config(SyslogProtocol::Udp, port), true).unwrap();
+
+        let mut info = snapshot(&[], &[]);
+        info.events = ["a", "b", "c"].iter()
+            .map(|name| ChangeEvent { detected_at: info.collected_at, change: Change::ServiceStarted { name: name.to_string() } })
+            .collect();
+
+        // As if the summary and the first event went out before the connection failed
+        sink.sent = Some((info.collected_at, 2));
+        sink.write(&info).unwrap();
+        assert!(sink.sent.is_none(), "A complete batch clears the resume point");
+
+        let mut buffer = [0u8; 2048];
+        let len = listener.recv(&mut buffer).unwrap();
+        let first = String::from_utf8(buffer[..len].to_vec()).unwrap();
+        assert!(first.contains("name=\"b\""), "Resumes at the first unsent message: {}", first);
+        let len = listener.recv(&mut buffer).unwrap();
+        assert!(String::from_utf8(buffer[..len].to_vec()).unwrap().contains("name=\"c\""));
+    }
+
+    #[test]
+    fn test_derives_events_without_change_tracking() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut sink = SyslogSink::new(&config(SyslogProtocol::Udp, port), false).unwrap();
+
+        let mut buffer = [0u8; 2048];
+        sink.write(&snapshot(&["sshd", "cron"], &[])).unwrap();
+        listener.recv(&mut buffer).unwrap();
+
+        sink.write(&snapshot(&["sshd"], &[])).unwrap();
+        let summary_len = listener.recv(&mut buffer).unwrap();
+        assert!(String::from_utf8_lossy(&buffer[..summary_len]).contains("Inventory snapshot"));
+        let len = listener.recv(&mut buffer).unwrap();
+        let event = String::from_utf8(buffer[..len].to_vec()).unwrap();
+        assert!(event.contains("name=\"cron\" type=\"service_stopped\""), "Diffed against the previous snapshot: {}", event);
+    }
+
+    #[test]
+    fn test_tcp_uses_octet_counting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut sink = SyslogSink::new(&config(SyslogProtocol::Tcp, port), true).unwrap();
+
+        sink.write(&snapshot(&[], &[])).unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = Vec::new();
+        reader.read_until(b' ', &mut length).unwrap();
+        let length: usize = String::from_utf8(length).unwrap().trim().parse().unwrap();
+        let mut message = vec![0; length];
+        reader.read_exact(&mut message).unwrap();
+        let message = String::from_utf8(message).unwrap();
+        assert!(message.starts_with("<134>1 ") && message.ends_with("Inventory snapshot"), "{}", message);
+    }
+}
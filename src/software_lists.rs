@@ -0,0 +1,461 @@
+use crate::collector;
+use crate::config::{CollectionConfig, Config};
+use crate::models::{Package, SoftwareViolation, SystemInfo, ViolationKind};
+use crate::version;
+use crate::watch::{latest_modification, RuleFiles};
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A list file (`.toml`, `.yaml` or `.yml`) of forbidden and required software
+#[derive(Debug, Deserialize)]
+struct ListFile {
+    name: String,
+    #[serde(default)]
+    forbidden: Vec<Entry>,
+    #[serde(default)]
+    required: Vec<Entry>,
+}
+
+/// `package` and `service` are globs (`*`, `?`, case-insensitive), or
+/// regexes when written as `/.../`
+#[derive(Debug, Deserialize)]
+struct Entry {
+    id: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default = "default_severity")]
+    severity: String,
+    #[serde(default)]
+    package: Option<String>,
+    /// Package versions the entry applies to, e.g. `>= 7.0, < 8`
+    #[serde(default)]
+    versions: Option<String>,
+    #[serde(default)]
+    service: Option<String>,
+}
+
+fn default_severity() -> String {
+    "high".to_string()
+}
+
+#[derive(Debug)]
+struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let regex = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(regex) => Regex::new(regex),
+            None => {
+                let mut glob = String::from("(?i)^");
+                for c in pattern.chars() {
+                    match c {
+                        '*' => glob.push_str(".*"),
+                        '?' => glob.push('.'),
+                        c => glob.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                glob.push('$');
+                Regex::new(&glob)
+            }
+        }
+        .map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+
+        Ok(Pattern { source: pattern.to_string(), regex })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+/// Comma-separated comparisons, all of which must hold
+#[derive(Debug)]
+struct VersionRange(Vec<(Ordering, bool, String)>);
+
+impl VersionRange {
+    fn parse(range: &str) -> Result<Self, String> {
+        let mut bounds = Vec::new();
+        for bound in range.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let split = bound.find(|c: char| !matches!(c, '<' | '>' | '=' | '!')).unwrap_or(bound.len());
+            let (op, version) = (&bound[..split], bound[split..].trim());
+            // (ordering, whether it is required or excluded)
+            let (ordering, required) = match op {
+                "=" | "==" => (Ordering::Equal, true),
+                "!=" => (Ordering::Equal, false),
+                "<" => (Ordering::Less, true),
+                ">=" => (Ordering::Less, false),
+                ">" => (Ordering::Greater, true),
+                "<=" => (Ordering::Greater, false),
+                _ => return Err(format!("invalid version bound '{}'", bound)),
+            };
+            if version.is_empty() {
+                return Err(format!("invalid version bound '{}'", bound));
+            }
+            bounds.push((ordering, required, version.to_string()));
+        }
+        Ok(VersionRange(bounds))
+    }
+
+    fn contains(&self, package: &Package) -> bool {
+        self.0.iter().all(|(ordering, required, version)| {
+            (version::compare(&package.manager, &package.version, version) == *ordering) == *required
+        })
+    }
+}
+
+struct Rule {
+    id: String,
+    description: Option<String>,
+    severity: String,
+    package: Option<Pattern>,
+    versions: Option<VersionRange>,
+    service: Option<Pattern>,
+}
+
+impl Rule {
+    fn compile(entry: Entry) -> Result<Self, String> {
+        let context = |e: String| format!("Entry {}: {}", entry.id, e);
+        if entry.package.is_none() && entry.service.is_none() {
+            return Err(context("needs 'package' and/or 'service'".to_string()));
+        }
+        if entry.versions.is_some() && entry.package.is_none() {
+            return Err(context("'versions' needs 'package'".to_string()));
+        }
+
+        Ok(Rule {
+            package: entry.package.as_deref().map(Pattern::parse).transpose().map_err(context)?,
+            versions: entry.versions.as_deref().map(VersionRange::parse).transpose().map_err(context)?,
+            service: entry.service.as_deref().map(Pattern::parse).transpose().map_err(context)?,
+            id: entry.id,
+            description: entry.description,
+            severity: entry.severity,
+        })
+    }
+
+    fn in_range(&self, package: &Package) -> bool {
+        self.versions.as_ref().is_none_or(|range| range.contains(package))
+    }
+
+    fn violation(&self, list: &str, kind: ViolationKind, subject: &str, version: Option<&str>, message: String) -> SoftwareViolation {
+        SoftwareViolation {
+            list: list.to_string(),
+            rule: self.id.clone(),
+            kind,
+            severity: self.severity.clone(),
+            subject: subject.to_string(),
+            version: version.map(str::to_string),
+            message: match &self.description {
+                Some(description) => format!("{} ({})", message, description),
+                None => message,
+            },
+        }
+    }
+}
+
+struct List {
+    name: String,
+    forbidden: Vec<Rule>,
+    required: Vec<Rule>,
+}
+
+/// Inventory the lists are checked against; `None` when it is not collected
+struct Inventory<'a> {
+    packages: Option<&'a [Package]>,
+    services: Option<&'a [String]>,
+}
+
+impl List {
+    fn check(&self, inventory: &Inventory) -> Vec<SoftwareViolation> {
+        let mut violations = Vec::new();
+
+        for rule in &self.forbidden {
+            if let (Some(pattern), Some(packages)) = (&rule.package, inventory.packages) {
+                for package in packages.iter().filter(|p| pattern.matches(&p.name) && rule.in_range(p)) {
+                    violations.push(rule.violation(&self.name, ViolationKind::Forbidden, &package.name,
+                        Some(&package.version), format!("Forbidden package {} {} is installed", package.name, package.version)));
+                }
+            }
+            if let (Some(pattern), Some(services)) = (&rule.service, inventory.services) {
+                for service in services.iter().filter(|s| pattern.matches(s)) {
+                    violations.push(rule.violation(&self.name, ViolationKind::Forbidden, service,
+                        None, format!("Forbidden service {} is running", service)));
+                }
+            }
+        }
+
+        for rule in &self.required {
+            if let (Some(pattern), Some(packages)) = (&rule.package, inventory.packages) {
+                let installed: Vec<&Package> = packages.iter().filter(|p| pattern.matches(&p.name)).collect();
+                if installed.is_empty() {
+                    violations.push(rule.violation(&self.name, ViolationKind::Missing, &pattern.source,
+                        None, format!("Required package {} is not installed", pattern.source)));
+                } else if !installed.iter().any(|p| rule.in_range(p)) {
+                    let package = installed[0];
+                    violations.push(rule.violation(&self.name, ViolationKind::VersionNotAllowed, &package.name,
+                        Some(&package.version), format!("Required package {} {} is outside the allowed versions",
+                            package.name, package.version)));
+                }
+            }
+            if let (Some(pattern), Some(services)) = (&rule.service, inventory.services) {
+                if !services.iter().any(|s| pattern.matches(s)) {
+                    violations.push(rule.violation(&self.name, ViolationKind::Missing, &pattern.source,
+                        None, format!("Required service {} is not running", pattern.source)));
+                }
+            }
+        }
+        violations
+    }
+}
+
+fn parse_list(path: &Path, contents: &str) -> Result<List, String> {
+    let file: ListFile = match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(contents)
+            .map_err(|e| format!("Failed to parse YAML: {}", e))?,
+        _ => toml::from_str(contents)
+            .map_err(|e| format!("Failed to parse TOML: {}", e))?,
+    };
+
+    Ok(List {
+        name: file.name,
+        forbidden: file.forbidden.into_iter().map(Rule::compile).collect::<Result<_, _>>()?,
+        required: file.required.into_iter().map(Rule::compile).collect::<Result<_, _>>()?,
+    })
+}
+
+/// Package manager databases whose modification time shows that packages changed
+#[cfg(target_os = "linux")]
+const PACKAGE_DATABASES: &[&str] = &["/var/lib/dpkg/status", "/var/lib/rpm", "/usr/lib/sysimage/rpm"];
+#[cfg(target_os = "macos")]
+const PACKAGE_DATABASES: &[&str] = &["/Applications", "/var/db/receipts"];
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const PACKAGE_DATABASES: &[&str] = &[];
+
+/// Lists from `[software_lists] paths`, reloaded when a file changes
+///
+/// Violations are added to every snapshot. Between collections the inventory
+/// is re-checked every `check_interval_seconds` so a new violation is sent
+/// right away instead of at the next cycle. Services are re-read on each
+/// check; packages only when the package database has been modified.
+pub struct SoftwareLists {
+    lists: RuleFiles<List>,
+    check_interval: Option<Duration>,
+    last_check: Instant,
+    /// Violations in the last snapshot, sorted
+    reported: Vec<SoftwareViolation>,
+    /// Packages last collected, reused while the package database is unchanged
+    packages: Vec<Package>,
+    packages_modified: Option<SystemTime>,
+}
+
+impl SoftwareLists {
+    pub fn load(config: &Config) -> Self {
+        let collection = &config.collection;
+        if !collection.include_software || !collection.include_services {
+            warn!("Software lists only check what is collected (include_software = {}, include_services = {})",
+                collection.include_software, collection.include_services);
+        }
+
+        let mut lists = SoftwareLists {
+            lists: RuleFiles::new(&config.software_lists.paths),
+            check_interval: (config.software_lists.check_interval_seconds > 0)
+                .then(|| Duration::from_secs(config.software_lists.check_interval_seconds)),
+            last_check: Instant::now(),
+            reported: Vec::new(),
+            packages: Vec::new(),
+            packages_modified: None,
+        };
+        lists.reload_if_changed();
+        lists
+    }
+
+    /// Check the lists against `info` and store the violations in `info.software_violations`
+    pub fn evaluate(&mut self, info: &mut SystemInfo) {
+        self.reload_if_changed();
+
+        // The backend may disable collectors at runtime; go by what this snapshot holds
+        let collected = |field: &str| !info.not_collected.iter().any(|f| f == field);
+        let inventory = Inventory {
+            packages: collected("packages").then_some(info.packages.as_slice()),
+            services: collected("services").then_some(info.services.as_slice()),
+        };
+        let mut violations = self.check(&inventory);
+        for violation in &violations {
+            warn!("✗ Software violation [{}] {}: {}", violation.list, violation.rule, violation.message);
+        }
+
+        violations.sort_by(|a, b| (&a.list, &a.rule, &a.subject).cmp(&(&b.list, &b.rule, &b.subject)));
+        info.software_violations = violations.clone();
+        self.reported = violations;
+        self.last_check = Instant::now();
+        if inventory.packages.is_some() {
+            self.packages = info.packages.clone();
+            self.packages_modified = package_database_modified();
+        }
+    }
+
+    /// Re-check the inventory if due; true when it has violations not yet reported
+    ///
+    /// Only the collectors enabled in the live `collection` config are consulted.
+    pub fn check_due(&mut self, collection: &CollectionConfig) -> bool {
+        let Some(interval) = self.check_interval else {
+            return false;
+        };
+        if self.last_check.elapsed() < interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+        self.reload_if_changed();
+
+        if collection.include_software {
+            let modified = package_database_modified();
+            if modified.is_some() && modified != self.packages_modified {
+                debug!("Package database changed, re-reading installed packages");
+                self.packages = collector::get_software();
+                self.packages_modified = modified;
+            }
+        } else {
+            self.packages.clear();
+            self.packages_modified = None;
+        }
+        let services = if collection.include_services { collector::get_services() } else { Vec::new() };
+        let inventory = Inventory {
+            packages: collection.include_software.then_some(self.packages.as_slice()),
+            services: collection.include_services.then_some(services.as_slice()),
+        };
+
+        let new: Vec<SoftwareViolation> = self.check(&inventory).into_iter()
+            .filter(|violation| !self.reported.iter().any(|r| r.rule == violation.rule
+                && r.list == violation.list && r.subject == violation.subject))
+            .collect();
+        for violation in &new {
+            warn!("✗ New software violation [{}] {}: {}", violation.list, violation.rule, violation.message);
+        }
+        !new.is_empty()
+    }
+
+    fn check(&self, inventory: &Inventory) -> Vec<SoftwareViolation> {
+        self.lists.iter().flat_map(|list| list.check(inventory)).collect()
+    }
+
+    fn reload_if_changed(&mut self) {
+        self.lists.reload_if_changed("software list", |file, _| {
+            let contents = fs::read_to_string(file)
+                .map_err(|e| format!("Failed to read file: {}", e))?;
+            let list = parse_list(file, &contents)?;
+            info!("✓ Loaded software list {} ({} forbidden, {} required) from {}",
+                list.name, list.forbidden.len(), list.required.len(), file.display());
+            Ok(list)
+        });
+    }
+}
+
+/// Newest modification time of the package databases present on this host
+fn package_database_modified() -> Option<SystemTime> {
+    PACKAGE_DATABASES.iter().filter_map(|path| latest_modification(Path::new(path))).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forbidden_and_required() {
+        let list = parse_list(Path::new("corp.toml"), r#"
+            name = "corp"
+
+            [[forbidden]]
+            id = "torrent"
+            package = "transmission*"
+
+            [[forbidden]]
+            id = "remote-access"
+            package = "/^(anydesk|teamviewer)$/"
+            service = "anydesk*"
+
+            [[forbidden]]
+            id = "old-openssl"
+            package = "openssl"
+            versions = "< 3.0"
+
+            [[required]]
+            id = "edr"
+            package = "falcon-sensor"
+            versions = ">= 7.0, < 8"
+            service = "falcon-sensor"
+
+            [[required]]
+            id = "backup"
+            package = "backup-agent"
+        "#).unwrap();
+
+        let packages = vec![
+            Package::new("Transmission-GTK", "4.0.5-1", "deb"),
+            Package::new("anydesk", "6.3.0", "deb"),
+            Package::new("openssl", "3.0.2-0ubuntu1.10", "deb"),
+            Package::new("falcon-sensor", "6.45.0", "deb"),
+        ];
+        let services = vec!["anydesk.service".to_string(), "sshd".to_string()];
+        let violations = list.check(&Inventory { packages: Some(&packages), services: Some(&services) });
+
+        let found: Vec<(&str, ViolationKind, &str)> = violations.iter()
+            .map(|v| (v.rule.as_str(), v.kind, v.subject.as_str()))
+            .collect();
+        assert_eq!(found, vec![
+            ("torrent", ViolationKind::Forbidden, "Transmission-GTK"),
+            ("remote-access", ViolationKind::Forbidden, "anydesk"),
+            ("remote-access", ViolationKind::Forbidden, "anydesk.service"),
+            ("edr", ViolationKind::VersionNotAllowed, "falcon-sensor"),
+            ("edr", ViolationKind::Missing, "falcon-sensor"),
+            ("backup", ViolationKind::Missing, "backup-agent"),
+        ], "openssl 3.0.2 is outside the forbidden range");
+
+        // Checks for what isn't collected are skipped
+        let violations = list.check(&Inventory { packages: None, services: Some(&services) });
+        assert_eq!(violations.len(), 2, "{:?}", violations);
+    }
+
+    #[test]
+    fn test_evaluate_skips_collectors_disabled_at_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("corp.toml"), "name = \"corp\"\n[[required]]\nid = \"backup\"\npackage = \"backup-agent\"\n").unwrap();
+        let mut config = Config::default();
+        config.software_lists.paths = vec![dir.path().to_string_lossy().to_string()];
+        let mut lists = SoftwareLists::load(&config);
+
+        let mut info = SystemInfo::default();
+        lists.evaluate(&mut info);
+        assert_eq!(info.software_violations.len(), 1, "Nothing installed: backup-agent is missing");
+
+        // The backend switched software collection off after startup
+        let mut info = SystemInfo {
+            not_collected: vec!["installed_software".to_string(), "packages".to_string()],
+            ..Default::default()
+        };
+        lists.evaluate(&mut info);
+        assert!(info.software_violations.is_empty(), "{:?}", info.software_violations);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        for (contents, error) in [
+            ("name = \"x\"\n[[forbidden]]\nid = \"a\"", "needs 'package'"),
+            ("name = \"x\"\n[[required]]\nid = \"a\"\nservice = \"s\"\nversions = \">= 1\"", "'versions' needs"),
+            ("name = \"x\"\n[[required]]\nid = \"a\"\npackage = \"p\"\nversions = \"~ 1\"", "invalid version bound"),
+            ("name = \"x\"\n[[forbidden]]\nid = \"a\"\npackage = \"/(/\"", "invalid pattern"),
+        ] {
+            let result = parse_list(Path::new("x.toml"), contents);
+            assert!(result.as_ref().err().is_some_and(|e| e.contains(error)), "{}: {:?}", contents, result.err());
+        }
+
+        let example = parse_list(Path::new("software-lists.example.toml"), include_str!("../software-lists.example.toml")).unwrap();
+        assert!(!example.forbidden.is_empty() && !example.required.is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+use crate::config::SpoolConfig;
+use crate::models::SystemInfo;
+use chrono::Utc;
+use log::{info, debug, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Per-process counter so entries spooled within the same millisecond keep their order
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Subdirectory for heartbeats the backend refused for good, kept for inspection
+const REJECTED_DIR: &str = "rejected";
+/// Rejected heartbeats kept; older ones are deleted
+const MAX_REJECTED: usize = 100;
+
+/// Durable on-disk queue of heartbeats that could not be delivered
+///
+/// Each entry is one JSON file named `<collected_at millis>-<sequence>.json`,
+/// so a plain sort by file name gives delivery order. Heartbeats the backend
+/// rejects permanently are moved to `rejected/` instead of blocking the queue.
+pub struct Spool {
+    dir: PathBuf,
+    config: SpoolConfig,
+}
+
+struct SpoolEntry {
+    path: PathBuf,
+    collected_at_ms: i64,
+    size: u64,
+}
+
+impl Spool {
+    /// Open (and create if needed) the spool directory
+    pub fn open(dir: &str, config: &SpoolConfig) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create spool directory: {}", e))?;
+
+        let spool = Spool {
+            dir: PathBuf::from(dir),
+            config: config.clone(),
+        };
+
+        if !spool.is_empty() {
+            info!("Spool contains {} undelivered heartbeat(s): {}", spool.len(), dir);
+        }
+
+        Ok(spool)
+    }
+
+    /// Number of entries waiting to be delivered
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Store a heartbeat for later delivery
+    pub fn push(&self, info: &SystemInfo) -> Result<(), String> {
+        let path = write_entry(&self.dir, info)?;
+        debug!("Spooled heartbeat: {}", path.display());
+        self.enforce_limits();
+        Ok(())
+    }
+
+    /// Keep a heartbeat the backend refused for good out of the queue
+    pub fn reject(&self, info: &SystemInfo, reason: &str) -> Result<(), String> {
+        let dir = self.dir.join(REJECTED_DIR);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create rejected directory: {}", e))?;
+        let path = write_entry(&dir, info)?;
+        warn!("Backend rejected heartbeat permanently ({}), moved to {}", reason, path.display());
+
+        let mut rejected = list_entries(&dir);
+        let excess = rejected.len().saturating_sub(MAX_REJECTED);
+        for entry in rejected.drain(..excess) {
+            remove_entry(&entry.path);
+        }
+        Ok(())
+    }
+
+    /// Deliver spooled heartbeats oldest first
+    ///
+    /// Stops at the first retryable failure so ordering is preserved; the
+    /// failed entry stays in the spool. Entries whose error `is_permanent`
+    /// are moved to `rejected/` and replay continues. Returns the number of
+    /// delivered entries.
+    pub fn replay<F, P>(&self, mut send: F, is_permanent: P) -> Result<usize, String>
+    where
+        F: FnMut(&SystemInfo) -> Result<(), String>,
+        P: Fn(&str) -> bool,
+    {
+        self.enforce_limits();
+
+        let mut delivered = 0;
+        for entry in self.entries() {
+            let info = match read_entry(&entry.path) {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!("Discarding unreadable spool entry {}: {}", entry.path.display(), e);
+                    remove_entry(&entry.path);
+                    continue;
+                }
+            };
+
+            if let Err(e) = send(&info) {
+                if is_permanent(&e) {
+                    self.reject(&info, &e)?;
+                    remove_entry(&entry.path);
+                    continue;
+                }
+                if delivered > 0 {
+                    info!("Replayed {} spooled heartbeat(s) before failure", delivered);
+                }
+                return Err(e);
+            }
+
+            remove_entry(&entry.path);
+            delivered += 1;
+        }
+
+        if delivered > 0 {
+            info!("✓ Replayed {} spooled heartbeat(s)", delivered);
+        }
+        Ok(delivered)
+    }
+
+    /// Drop entries that are too old, then the oldest ones until count and size fit
+    fn enforce_limits(&self) {
+        let mut entries = self.entries();
+
+        let max_age_ms = (self.config.max_age_hours as i64).saturating_mul(3_600_000);
+        let cutoff = Utc::now().timestamp_millis() - max_age_ms;
+        let expired = entries.iter().take_while(|e| e.collected_at_ms < cutoff).count();
+        for entry in entries.drain(..expired) {
+            warn!("Dropping expired spool entry: {}", entry.path.display());
+            remove_entry(&entry.path);
+        }
+
+        let mut total_bytes: u64 = entries.iter().map(|e| e.size).sum();
+        let mut dropped = 0;
+        while !entries.is_empty()
+            && (entries.len() > self.config.max_entries || total_bytes > self.config.max_bytes)
+        {
+            let entry = entries.remove(0);
+            total_bytes -= entry.size;
+            remove_entry(&entry.path);
+            dropped += 1;
+        }
+
+        if dropped > 0 {
+            warn!("Spool limits reached, dropped {} oldest heartbeat(s)", dropped);
+        }
+    }
+
+    /// Spool entries sorted oldest first
+    fn entries(&self) -> Vec<SpoolEntry> {
+        list_entries(&self.dir)
+    }
+}
+
+/// Write an entry atomically, named so that file name order is collection order
+fn write_entry(dir: &Path, info: &SystemInfo) -> Result<PathBuf, String> {
+    let json = serde_json::to_vec(info)
+        .map_err(|e| format!("Failed to serialize spool entry: {}", e))?;
+
+    let sequence = SEQUENCE.fetch_add(1, Ordering::SeqCst);
+    let name = format!("{:013}-{:010}.json", info.collected_at.timestamp_millis(), sequence);
+    let path = dir.join(&name);
+    let tmp_path = dir.join(format!("{}.tmp", name));
+
+    // Write then rename so a crash never leaves a half-written entry
+    fs::write(&tmp_path, json)
+        .map_err(|e| format!("Failed to write spool entry: {}", e))?;
+    fs::rename(&tmp_path, &path)
+        .map_err(|e| format!("Failed to commit spool entry: {}", e))?;
+    Ok(path)
+}
+
+/// Entries in `dir` sorted oldest first
+fn list_entries(dir: &Path) -> Vec<SpoolEntry> {
+    let mut entries = Vec::new();
+
+    let dir = match fs::read_dir(dir) {
+        Ok(dir) => dir,
+        Err(e) => {
+            warn!("Failed to read spool directory: {}", e);
+            return entries;
+        }
+    };
+
+    for entry in dir.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+
+        let collected_at_ms = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.split('-').next())
+            .and_then(|s| s.parse::<i64>().ok());
+
+        if let Some(collected_at_ms) = collected_at_ms {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            entries.push(SpoolEntry { path, collected_at_ms, size });
+        }
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+fn read_entry(path: &Path) -> Result<SystemInfo, String> {
+    let contents = fs::read(path)
+        .map_err(|e| format!("Failed to read spool entry: {}", e))?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| format!("Failed to parse spool entry: {}", e))
+}
+
+fn remove_entry(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove spool entry {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_info(hostname: &str) -> SystemInfo {
+        SystemInfo {
+            collected_at: Utc::now(),
+            agent_id: "agent-test".to_string(),
+            hostname: hostname.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_preserves_order_and_stops_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::open(dir.path().to_str().unwrap(), &SpoolConfig::default()).unwrap();
+
+        for name in ["first", "second", "third"] {
+            spool.push(&sample_info(name)).unwrap();
+        }
+        assert_eq!(spool.len(), 3);
+
+        // Fail on the second entry: only the first one is delivered
+        let mut seen = Vec::new();
+        let result = spool.replay(|info| {
+            if info.hostname == "second" {
+                return Err("backend down".to_string());
+            }
+            seen.push(info.hostname.clone());
+            Ok(())
+        }, |_| false);
+        assert!(result.is_err());
+        assert_eq!(seen, vec!["first"]);
+        assert_eq!(spool.len(), 2);
+
+        let mut seen = Vec::new();
+        let delivered = spool.replay(|info| {
+            seen.push(info.hostname.clone());
+            Ok(())
+        }, |_| false).unwrap();
+        assert_eq!(delivered, 2);
+        assert_eq!(seen, vec!["second", "third"]);
+        assert!(spool.is_empty());
+    }
+
+    #[test]
+    fn test_permanently_rejected_entries_do_not_block_the_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::open(dir.path().to_str().unwrap(), &SpoolConfig::default()).unwrap();
+        for name in ["first", "too-large", "third"] {
+            spool.push(&sample_info(name)).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let delivered = spool.replay(|info| {
+            if info.hostname == "too-large" {
+                return Err("Backend error (413 Payload Too Large): ".to_string());
+            }
+            seen.push(info.hostname.clone());
+            Ok(())
+        }, |e| e.contains("413")).unwrap();
+
+        assert_eq!(delivered, 2);
+        assert_eq!(seen, vec!["first", "third"]);
+        assert!(spool.is_empty());
+        let rejected = list_entries(&dir.path().join(REJECTED_DIR));
+        assert_eq!(rejected.len(), 1, "Rejected heartbeats are kept aside");
+        assert_eq!(read_entry(&rejected[0].path).unwrap().hostname, "too-large");
+    }
+
+    #[test]
+    fn test_unauthorized_backend_stops_replay_and_keeps_the_spool() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::open(dir.path().to_str().unwrap(), &SpoolConfig::default()).unwrap();
+        for name in ["first", "second", "third"] {
+            spool.push(&sample_info(name)).unwrap();
+        }
+
+        let mut attempts = 0;
+        let result = spool.replay(|_| {
+            attempts += 1;
+            Err("Backend error (401 Unauthorized): token revoked".to_string())
+        }, crate::sender::is_permanent_error);
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1, "Replay stops at the first unauthorized response");
+        assert_eq!(spool.len(), 3, "Nothing is lost while the token is being fixed");
+        assert!(list_entries(&dir.path().join(REJECTED_DIR)).is_empty());
+    }
+
+    #[test]
+    fn test_limits_drop_oldest_and_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SpoolConfig {
+            max_entries: 2,
+            ..SpoolConfig::default()
+        };
+        let spool = Spool::open(dir.path().to_str().unwrap(), &config).unwrap();
+
+        let mut old = sample_info("old");
+        old.collected_at = Utc::now() - chrono::Duration::hours(100);
+        spool.push(&old).unwrap();
+        assert!(spool.is_empty(), "Entries older than max_age_hours should be dropped");
+
+        for name in ["a", "b", "c"] {
+            spool.push(&sample_info(name)).unwrap();
+        }
+        assert_eq!(spool.len(), 2, "Spool should never exceed max_entries");
+
+        let mut seen = Vec::new();
+        spool.replay(|info| {
+            seen.push(info.hostname.clone());
+            Ok(())
+        }, |_| false).unwrap();
+        assert_eq!(seen, vec!["b", "c"]);
+    }
+}
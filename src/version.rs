@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+
+/// Compare two versions using the rules of the given package manager
+///
+/// `deb` follows dpkg, `rpm` follows rpmvercmp; anything else (macOS and
+/// Windows applications) is compared with the dpkg algorithm as well, which
+/// orders plain dotted versions sensibly.
+pub fn compare(manager: &str, a: &str, b: &str) -> Ordering {
+    match manager {
+        "rpm" => compare_rpm(a, b),
+        _ => compare_deb(a, b),
+    }
+}
+
+/// Split `[epoch:]version[-release]`; a missing epoch is 0
+fn split_evr(version: &str) -> (u64, &str, &str) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) if epoch.bytes().all(|b| b.is_ascii_digit()) => (epoch.parse().unwrap_or(0), rest),
+        _ => (0, version),
+    };
+    match rest.rsplit_once('-') {
+        Some((upstream, release)) => (epoch, upstream, release),
+        None => (epoch, rest, ""),
+    }
+}
+
+/// dpkg version comparison (`dpkg --compare-versions`)
+pub fn compare_deb(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_upstream, a_revision) = split_evr(a);
+    let (b_epoch, b_upstream, b_revision) = split_evr(b);
+
+    a_epoch.cmp(&b_epoch)
+        .then_with(|| verrevcmp(a_upstream.as_bytes(), b_upstream.as_bytes()))
+        .then_with(|| verrevcmp(a_revision.as_bytes(), b_revision.as_bytes()))
+}
+
+/// Sort weight of a non-digit character: `~` sorts before everything, even the end
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(c) => c as i32 + 256,
+    }
+}
+
+fn verrevcmp(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    let is_digit = |s: &[u8], k: usize| s.get(k).is_some_and(u8::is_ascii_digit);
+
+    while i < a.len() || j < b.len() {
+        // Non-digit prefix, character by character
+        while (i < a.len() && !is_digit(a, i)) || (j < b.len() && !is_digit(b, j)) {
+            let (ac, bc) = (order(a.get(i).copied()), order(b.get(j).copied()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        // Numeric part, ignoring leading zeros
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while is_digit(a, i) && is_digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if is_digit(a, i) {
+            return Ordering::Greater;
+        }
+        if is_digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+/// rpm version comparison (`rpmdev-vercmp`), including the epoch and release
+pub fn compare_rpm(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_version, a_release) = split_evr(a);
+    let (b_epoch, b_version, b_release) = split_evr(b);
+
+    a_epoch.cmp(&b_epoch)
+        .then_with(|| rpmvercmp(a_version, b_version))
+        .then_with(|| {
+            // A missing release matches any release
+            if a_release.is_empty() || b_release.is_empty() {
+                Ordering::Equal
+            } else {
+                rpmvercmp(a_release, b_release)
+            }
+        })
+}
+
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    let separator = |c: &u8| !c.is_ascii_alphanumeric() && *c != b'~' && *c != b'^';
+
+    loop {
+        while a.first().is_some_and(separator) {
+            a = &a[1..];
+        }
+        while b.first().is_some_and(separator) {
+            b = &b[1..];
+        }
+
+        // Tilde sorts before everything, caret after the end but before anything else
+        match (a.first() == Some(&b'~'), b.first() == Some(&b'~')) {
+            (true, true) => {
+                a = &a[1..];
+                b = &b[1..];
+                continue;
+            }
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            (false, false) => {}
+        }
+        if a.first() == Some(&b'^') || b.first() == Some(&b'^') {
+            if a.is_empty() {
+                return Ordering::Less;
+            }
+            if b.is_empty() {
+                return Ordering::Greater;
+            }
+            match (a[0] == b'^', b[0] == b'^') {
+                (true, true) => {
+                    a = &a[1..];
+                    b = &b[1..];
+                    continue;
+                }
+                (false, _) => return Ordering::Greater,
+                (_, false) => return Ordering::Less,
+            }
+        }
+
+        if a.is_empty() || b.is_empty() {
+            break;
+        }
+
+        let numeric = a[0].is_ascii_digit();
+        let segment = |s: &[u8]| -> usize {
+            s.iter()
+                .take_while(|c| if numeric { c.is_ascii_digit() } else { c.is_ascii_alphabetic() })
+                .count()
+        };
+        let (a_len, b_len) = (segment(a), segment(b));
+        if b_len == 0 {
+            // Numeric segments are newer than alphabetic ones
+            return if numeric { Ordering::Greater } else { Ordering::Less };
+        }
+
+        let (mut a_seg, mut b_seg) = (&a[..a_len], &b[..b_len]);
+        if numeric {
+            while a_seg.len() > 1 && a_seg[0] == b'0' {
+                a_seg = &a_seg[1..];
+            }
+            while b_seg.len() > 1 && b_seg[0] == b'0' {
+                b_seg = &b_seg[1..];
+            }
+            let ordering = a_seg.len().cmp(&b_seg.len()).then_with(|| a_seg.cmp(b_seg));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        } else if a_seg != b_seg {
+            return a_seg.cmp(b_seg);
+        }
+
+        a = &a[a_len..];
+        b = &b[b_len..];
+    }
+
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (false, _) => Ordering::Greater,
+        _ => Ordering::Less,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Ordering::{Equal, Greater, Less};
+
+    #[test]
+    fn test_compare_deb() {
+        for (a, b, expected) in [
+            ("1.0", "1.0", Equal),
+            ("1.0-1", "1.0-2", Less),
+            ("1.10", "1.9", Greater),
+            ("1:1.0", "2.0", Greater),
+            ("1.0~rc1", "1.0", Less),
+            ("1.0~rc1", "1.0~rc2", Less),
+            ("1.0a", "1.0", Greater),
+            ("1.0+dfsg", "1.0", Greater),
+            ("3.0.2-0ubuntu1.10", "3.0.2-0ubuntu1.9", Greater),
+            ("2.36-9+deb12u4", "2.36-9+deb12u10", Less),
+            ("1.001", "1.1", Equal),
+        ] {
+            assert_eq!(compare_deb(a, b), expected, "dpkg: {} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_compare_rpm() {
+        for (a, b, expected) in [
+            ("1.0-1", "1.0-1", Equal),
+            ("1.0-1.el9", "1.0-2.el9", Less),
+            ("1.10-1", "1.9-1", Greater),
+            ("1:1.0-1", "2.0-1", Greater),
+            ("1.0~rc1-1", "1.0-1", Less),
+            ("1.0^git1-1", "1.0-1", Greater),
+            ("1.0^git1-1", "1.0.1-1", Less),
+            ("1.0.1-1", "1.0^git1-1", Greater),
+            ("1.0^git1-1", "1.0^git2-1", Less),
+            ("1.0a-1", "1.0-1", Greater),
+            ("1.0.1-1", "1.0a-1", Greater),
+            ("3.0.7-27.el9", "3.0.7-25.el9", Greater),
+            ("1.0", "1.0-5", Equal),
+        ] {
+            assert_eq!(compare_rpm(a, b), expected, "rpm: {} vs {}", a, b);
+        }
+    }
+}
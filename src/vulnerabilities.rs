@@ -0,0 +1,492 @@
+use crate::collector::common::Distro;
+use crate::config::VulnerabilityConfig;
+use crate::models::{Package, SystemInfo, Vulnerability};
+use crate::version;
+use crate::watch::latest_modification;
+use log::{info, debug, warn};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Affected range of an advisory (OSV `ECOSYSTEM` range semantics)
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Introduced(String),
+    Fixed(String),
+    LastAffected(String),
+}
+
+/// (package manager, package name)
+type PackageKey = (String, String);
+
+/// One advisory for one package, normalized from either feed format
+#[derive(Debug, Clone)]
+struct Advisory {
+    id: String,
+    cves: Vec<String>,
+    severity: Option<String>,
+    ranges: Vec<Vec<Event>>,
+    versions: Vec<String>,
+    source: &'static str,
+    /// OSV ecosystem, e.g. `Debian:12`; None when the feed is release-specific already
+    ecosystem: Option<String>,
+}
+
+impl Advisory {
+    /// The fixed version (if any) when `installed` is affected, None otherwise
+    fn affects(&self, manager: &str, installed: &str) -> Option<Option<String>> {
+        if self.versions.iter().any(|v| version::compare(manager, installed, v) == Ordering::Equal) {
+            return Some(self.fixed_after(manager, installed));
+        }
+
+        for events in &self.ranges {
+            let mut affected = false;
+            let mut fixed = None;
+            for event in events {
+                match event {
+                    Event::Introduced(v) => {
+                        if v == "0" || version::compare(manager, installed, v) != Ordering::Less {
+                            affected = true;
+                            fixed = None;
+                        }
+                    }
+                    Event::Fixed(v) => {
+                        if version::compare(manager, installed, v) != Ordering::Less {
+                            affected = false;
+                        } else if affected && fixed.is_none() {
+                            fixed = Some(v.clone());
+                        }
+                    }
+                    Event::LastAffected(v) => {
+                        if version::compare(manager, installed, v) == Ordering::Greater {
+                            affected = false;
+                        }
+                    }
+                }
+            }
+            if affected {
+                return Some(fixed);
+            }
+        }
+        None
+    }
+
+    /// Lowest fixed version newer than `installed`
+    fn fixed_after(&self, manager: &str, installed: &str) -> Option<String> {
+        self.ranges.iter()
+            .flatten()
+            .filter_map(|event| match event {
+                Event::Fixed(v) if version::compare(manager, installed, v) == Ordering::Less => Some(v),
+                _ => None,
+            })
+            .min_by(|a, b| version::compare(manager, a, b))
+            .cloned()
+    }
+}
+
+/// Local advisory database, keyed by (package manager, package name)
+///
+/// Feeds are OSV JSON (single records, arrays or a directory of `*.json`
+/// files from an OSV export) or the Debian security tracker JSON. They are
+/// reloaded when a feed file changes on disk. OSV advisories only apply when
+/// their ecosystem is the local distribution and release.
+pub struct VulnerabilityDb {
+    feeds: Vec<PathBuf>,
+    debian_release: Option<String>,
+    distro: Option<Distro>,
+    loaded_at: Option<SystemTime>,
+    advisories: HashMap<PackageKey, Vec<Advisory>>,
+}
+
+impl VulnerabilityDb {
+    pub fn load(config: &VulnerabilityConfig) -> Self {
+        let mut db = VulnerabilityDb {
+            feeds: config.feeds.iter().map(PathBuf::from).collect(),
+            debian_release: config.debian_release.clone().or_else(debian_codename),
+            distro: Distro::local(),
+            loaded_at: None,
+            advisories: HashMap::new(),
+        };
+        if db.distro.is_none() && !db.feeds.is_empty() {
+            warn!("Unknown Linux distribution: OSV advisories are matched on package name only");
+        }
+        db.reload_if_changed();
+        db
+    }
+
+    /// Attach the vulnerabilities of `info.packages` to `info.vulnerabilities`
+    pub fn scan(&mut self, info: &mut SystemInfo) {
+        self.reload_if_changed();
+
+        info.vulnerabilities = info.packages.iter()
+            .flat_map(|package| self.matches(package))
+            .collect();
+
+        if !info.vulnerabilities.is_empty() {
+            warn!("Found {} known vulnerabilities in installed packages", info.vulnerabilities.len());
+        }
+    }
+
+    /// Advisories filed against the package or its source package
+    fn matches(&self, package: &Package) -> Vec<Vulnerability> {
+        let mut seen = HashSet::new();
+        [Some(&package.name), package.source.as_ref()].into_iter()
+            .flatten()
+            .filter_map(|name| self.advisories.get(&(package.manager.clone(), name.clone())))
+            .flatten()
+            .filter(|advisory| match (&advisory.ecosystem, &self.distro) {
+                (Some(ecosystem), Some(distro)) => ecosystem_applies(ecosystem, distro),
+                _ => true,
+            })
+            .filter(|advisory| seen.insert(advisory.id.as_str()))
+            .filter_map(|advisory| {
+                let fixed_version = advisory.affects(&package.manager, &package.version)?;
+                Some(Vulnerability {
+                    id: advisory.id.clone(),
+                    cves: advisory.cves.clone(),
+                    package: package.name.clone(),
+                    installed_version: package.version.clone(),
+                    fixed_version,
+                    severity: advisory.severity.clone(),
+                    source: advisory.source.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn reload_if_changed(&mut self) {
+        let modified = self.feeds.iter().filter_map(|feed| latest_modification(feed)).max();
+        if modified.is_some() && modified == self.loaded_at {
+            return;
+        }
+
+        self.advisories.clear();
+        for feed in self.feeds.clone() {
+            match self.load_feed(&feed) {
+                Ok(count) => info!("✓ Loaded {} advisories from {}", count, feed.display()),
+                Err(e) => warn!("✗ Failed to load advisory feed {}: {}", feed.display(), e),
+            }
+        }
+        self.loaded_at = modified;
+    }
+
+    fn load_feed(&mut self, path: &Path) -> Result<usize, String> {
+        if path.is_dir() {
+            let mut count = 0;
+            let entries = fs::read_dir(path)
+                .map_err(|e| format!("Failed to read directory: {}", e))?;
+            for entry in entries.flatten() {
+                let file = entry.path();
+                if file.extension().is_some_and(|ext| ext == "json") {
+                    match self.load_feed(&file) {
+                        Ok(n) => count += n,
+                        Err(e) => debug!("Skipping {}: {}", file.display(), e),
+                    }
+                }
+            }
+            return Ok(count);
+        }
+
+        let contents = fs::read(path)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        let document: Value = serde_json::from_slice(&contents)
+            .map_err(|e| format!("Failed to parse file: {}", e))?;
+
+        let advisories = match &document {
+            Value::Array(records) => records.iter().flat_map(parse_osv).collect(),
+            Value::Object(record) if record.contains_key("id") && record.contains_key("affected") => parse_osv(&document),
+            Value::Object(_) => parse_debian_tracker(&document, self.debian_release.as_deref())?,
+            _ => return Err("Unrecognized feed format".to_string()),
+        };
+
+        let count = advisories.len();
+        for (key, advisory) in advisories {
+            self.advisories.entry(key).or_default().push(advisory);
+        }
+        Ok(count)
+    }
+}
+
+/// `VERSION_CODENAME` from os-release, used to pick the Debian tracker release
+fn debian_codename() -> Option<String> {
+    let contents = fs::read_to_string("/etc/os-release").ok()?;
+    contents.lines()
+        .find_map(|line| line.strip_prefix("VERSION_CODENAME="))
+        .map(|value| value.trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Package manager an OSV ecosystem belongs to (`Debian:12` → deb)
+fn ecosystem_manager(ecosystem: &str) -> Option<&'static str> {
+    let base = ecosystem.split(':').next().unwrap_or("");
+    match base {
+        "Debian" | "Ubuntu" => Some("deb"),
+        "Red Hat" | "AlmaLinux" | "Rocky Linux" | "openSUSE" | "SUSE" | "Mageia" | "openEuler" => Some("rpm"),
+        _ => None,
+    }
+}
+
+/// OSV ecosystem name of an os-release ID
+fn osv_distribution(id: &str) -> Option<&'static str> {
+    match id {
+        "debian" => Some("Debian"),
+        "ubuntu" => Some("Ubuntu"),
+        "rhel" => Some("Red Hat"),
+        "almalinux" => Some("AlmaLinux"),
+        "rocky" => Some("Rocky Linux"),
+        "opensuse" | "opensuse-leap" | "opensuse-tumbleweed" => Some("openSUSE"),
+        "sles" | "sled" | "sles_sap" => Some("SUSE"),
+        "mageia" => Some("Mageia"),
+        "openEuler" | "openeuler" => Some("openEuler"),
+        _ => None,
+    }
+}
+
+/// Whether an OSV ecosystem (`Debian:12`, `Ubuntu:Pro:22.04:LTS`,
+/// `Red Hat:enterprise_linux:9::appstream`) covers the local distribution
+///
+/// An ecosystem without a release applies to every release; a release matches
+/// the local `VERSION_ID` exactly or as its major version (`9` for `9.3`).
+fn ecosystem_applies(ecosystem: &str, distro: &Distro) -> bool {
+    let mut parts = ecosystem.split(':');
+    if osv_distribution(&distro.id) != parts.next() {
+        return false;
+    }
+    let releases: Vec<&str> = parts
+        .flat_map(|part| part.split_whitespace())
+        .filter(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+    let Some(version) = distro.version.as_deref() else {
+        return true;
+    };
+    let major = version.split('.').next().unwrap_or(version);
+    releases.is_empty() || releases.iter().any(|release| *release == version || *release == major)
+}
+
+fn cve_ids<'a>(ids: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut cves: Vec<String> = ids.filter(|id| id.starts_with("CVE-")).map(str::to_string).collect();
+    cves.sort();
+    cves.dedup();
+    cves
+}
+
+/// One OSV record becomes an advisory per affected deb/rpm package
+fn parse_osv(record: &Value) -> Vec<(PackageKey, Advisory)> {
+    let Some(id) = record["id"].as_str() else {
+        return Vec::new();
+    };
+    let aliases = record["aliases"].as_array().into_iter().flatten().filter_map(Value::as_str);
+    let cves = cve_ids(std::iter::once(id).chain(aliases));
+
+    // Textual severity (GHSA/Ubuntu style) first, then the first scored entry
+    let severity = record["database_specific"]["severity"].as_str()
+        .or_else(|| record["severity"][0]["score"].as_str())
+        .map(str::to_string);
+
+    let mut advisories = Vec::new();
+    for affected in record["affected"].as_array().into_iter().flatten() {
+        let Some(manager) = affected["package"]["ecosystem"].as_str().and_then(ecosystem_manager) else {
+            continue;
+        };
+        let Some(name) = affected["package"]["name"].as_str() else {
+            continue;
+        };
+
+        let ranges = affected["ranges"].as_array().into_iter().flatten()
+            .filter(|range| range["type"] == "ECOSYSTEM")
+            .map(|range| {
+                range["events"].as_array().into_iter().flatten()
+                    .filter_map(|event| {
+                        let (kind, value) = event.as_object()?.iter().next()?;
+                        let value = value.as_str()?.to_string();
+                        match kind.as_str() {
+                            "introduced" => Some(Event::Introduced(value)),
+                            "fixed" => Some(Event::Fixed(value)),
+                            "last_affected" => Some(Event::LastAffected(value)),
+                            _ => None,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let versions = affected["versions"].as_array().into_iter().flatten()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect();
+
+        advisories.push(((manager.to_string(), name.to_string()), Advisory {
+            id: id.to_string(),
+            cves: cves.clone(),
+            severity: affected["ecosystem_specific"]["urgency"].as_str().map(str::to_string).or(severity.clone()),
+            ranges,
+            versions,
+            source: "osv",
+            ecosystem: affected["package"]["ecosystem"].as_str().map(str::to_string),
+        }));
+    }
+    advisories
+}
+
+/// Debian security tracker JSON: `{package: {CVE: {releases: {codename: {...}}}}}`
+fn parse_debian_tracker(document: &Value, release: Option<&str>) -> Result<Vec<(PackageKey, Advisory)>, String> {
+    let release = release.ok_or_else(|| {
+        "Debian tracker feeds need the release codename (set vulnerabilities.debian_release)".to_string()
+    })?;
+    let packages = document.as_object().ok_or_else(|| "Unrecognized feed format".to_string())?;
+
+    let mut advisories = Vec::new();
+    for (package, issues) in packages {
+        for (id, issue) in issues.as_object().into_iter().flatten() {
+            let status = &issue["releases"][release];
+            let range = match (status["status"].as_str(), status["fixed_version"].as_str()) {
+                (Some("open"), _) => vec![Event::Introduced("0".to_string())],
+                // "0" means the release was never affected
+                (Some("resolved"), Some(fixed)) if fixed != "0" => {
+                    vec![Event::Introduced("0".to_string()), Event::Fixed(fixed.to_string())]
+                }
+                _ => continue,
+            };
+            let severity = status["urgency"].as_str()
+                .filter(|urgency| *urgency != "not yet assigned")
+                .map(|urgency| urgency.trim_end_matches('*').to_string());
+
+            advisories.push((("deb".to_string(), package.clone()), Advisory {
+                id: id.clone(),
+                cves: cve_ids(std::iter::once(id.as_str())),
+                severity,
+                ranges: vec![range],
+                versions: Vec::new(),
+                source: "debian",
+                ecosystem: None,
+            }));
+        }
+    }
+    Ok(advisories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osv_ranges_with_package_manager_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("DSA-1.json"), r#"{
+            "id": "DSA-5000-1",
+            "aliases": ["CVE-2024-0001"],
+            "database_specific": {"severity": "HIGH"},
+            "affected": [{
+                "package": {"ecosystem": "Debian:11", "name": "openssl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "1.1.1w-0+deb11u1"}]}]
+            }, {
+                "package": {"ecosystem": "Debian:12", "name": "openssl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "3.0.11-1~deb12u2"}]}]
+            }, {
+                "package": {"ecosystem": "Ubuntu:22.04:LTS", "name": "vim"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "2:9.1"}]}]
+            }]
+        }"#).unwrap();
+        fs::write(dir.path().join("RHSA.json"), r#"[{
+            "id": "RHSA-2024:1",
+            "aliases": ["CVE-2024-0002"],
+            "affected": [{
+                "package": {"ecosystem": "Red Hat:enterprise_linux:9", "name": "curl"},
+                "ranges": [{"type": "ECOSYSTEM", "events": [{"introduced": "0"}, {"fixed": "0:7.76.1-26.el9_3.3"}]}]
+            }]
+        }]"#).unwrap();
+
+        let config = VulnerabilityConfig {
+            enabled: true,
+            feeds: vec![dir.path().to_str().unwrap().to_string()],
+            debian_release: Some("bookworm".to_string()),
+        };
+        let mut db = VulnerabilityDb::load(&config);
+        db.distro = Some(Distro { id: "debian".to_string(), version: Some("12".to_string()) });
+        let mut info = SystemInfo {
+            packages: vec![
+                Package::new("openssl", "3.0.11-1~deb12u1", "deb"),
+                Package::new("curl", "7.76.1-26.el9_3.2", "rpm"),
+                Package::new("vim", "9.0", "deb"),
+            ],
+            ..Default::default()
+        };
+        db.scan(&mut info);
+
+        assert_eq!(info.vulnerabilities.len(), 1, "Only Debian 12 advisories apply: {:?}", info.vulnerabilities);
+        let openssl = &info.vulnerabilities[0];
+        assert_eq!(openssl.cves, vec!["CVE-2024-0001"]);
+        assert_eq!(openssl.fixed_version.as_deref(), Some("3.0.11-1~deb12u2"));
+        assert_eq!(openssl.severity.as_deref(), Some("HIGH"));
+
+        db.distro = Some(Distro { id: "rhel".to_string(), version: Some("9.3".to_string()) });
+        db.scan(&mut info);
+        assert_eq!(info.vulnerabilities.len(), 1, "Unexpected matches: {:?}", info.vulnerabilities);
+        assert_eq!(info.vulnerabilities[0].package, "curl");
+        db.distro = Some(Distro { id: "debian".to_string(), version: Some("12".to_string()) });
+
+        // The fixed version itself is no longer affected
+        info.packages = vec![Package::new("openssl", "3.0.11-1~deb12u2", "deb")];
+        db.scan(&mut info);
+        assert!(info.vulnerabilities.is_empty());
+    }
+
+    #[test]
+    fn test_debian_tracker_feed() {
+        let document: Value = serde_json::from_str(r#"{
+            "glibc": {
+                "CVE-2023-4911": {"releases": {"bookworm": {"status": "resolved", "fixed_version": "2.36-9+deb12u3", "urgency": "high"}}},
+                "CVE-2010-4756": {"releases": {"bookworm": {"status": "open", "urgency": "unimportant"}}},
+                "CVE-2019-0000": {"releases": {"bookworm": {"status": "resolved", "fixed_version": "0", "urgency": "low"}}}
+            }
+        }"#).unwrap();
+        let advisories = parse_debian_tracker(&document, Some("bookworm")).unwrap();
+        assert_eq!(advisories.len(), 2, "Releases that were never affected are skipped");
+
+        let mut db = VulnerabilityDb {
+            feeds: Vec::new(),
+            debian_release: Some("bookworm".to_string()),
+            distro: None,
+            loaded_at: None,
+            advisories: HashMap::new(),
+        };
+        for (key, advisory) in advisories {
+            db.advisories.entry(key).or_default().push(advisory);
+        }
+
+        let mut libc = Package::new("libc6", "2.36-9+deb12u1", "deb");
+        libc.source = Some("glibc".to_string());
+        let mut found = db.matches(&libc);
+        found.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].id, "CVE-2010-4756");
+        assert_eq!(found[0].fixed_version, None);
+        assert_eq!(found[1].fixed_version.as_deref(), Some("2.36-9+deb12u3"));
+        assert_eq!(found[1].severity.as_deref(), Some("high"));
+
+        assert!(parse_debian_tracker(&document, None).is_err(), "The release codename is required");
+    }
+
+    #[test]
+    fn test_ecosystem_applies_to_local_release() {
+        let distro = |id: &str, version: Option<&str>| Distro { id: id.to_string(), version: version.map(str::to_string) };
+        let bookworm = distro("debian", Some("12"));
+        assert!(ecosystem_applies("Debian:12", &bookworm));
+        assert!(ecosystem_applies("Debian", &bookworm), "No release means every release");
+        assert!(!ecosystem_applies("Debian:11", &bookworm));
+        assert!(!ecosystem_applies("Ubuntu:22.04:LTS", &bookworm));
+
+        let jammy = distro("ubuntu", Some("22.04"));
+        assert!(ecosystem_applies("Ubuntu:22.04:LTS", &jammy));
+        assert!(ecosystem_applies("Ubuntu:Pro:22.04:LTS", &jammy));
+        assert!(!ecosystem_applies("Ubuntu:22.10", &jammy));
+
+        let rhel = distro("rhel", Some("9.3"));
+        assert!(ecosystem_applies("Red Hat:enterprise_linux:9::appstream", &rhel));
+        assert!(!ecosystem_applies("Red Hat:enterprise_linux:8::appstream", &rhel));
+        assert!(!ecosystem_applies("AlmaLinux:9", &rhel));
+        assert!(ecosystem_applies("openSUSE:Leap 15.5", &distro("opensuse-leap", Some("15.5"))));
+        assert!(ecosystem_applies("Debian:13", &distro("debian", None)), "Unknown release matches any");
+    }
+}
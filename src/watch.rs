@@ -0,0 +1,133 @@
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Rule files from configured paths, reloaded when one of them changes
+///
+/// Directories contribute their `*.toml` / `*.yaml` files. A file that fails
+/// to load after an edit keeps its previous version, so a typo doesn't
+/// silently drop a rule set.
+pub struct RuleFiles<T> {
+    paths: Vec<PathBuf>,
+    loaded_at: Option<SystemTime>,
+    loaded: Vec<(PathBuf, T)>,
+}
+
+impl<T> RuleFiles<T> {
+    pub fn new(paths: &[String]) -> Self {
+        RuleFiles {
+            paths: paths.iter().map(PathBuf::from).collect(),
+            loaded_at: None,
+            loaded: Vec::new(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.loaded.iter().map(|(_, item)| item)
+    }
+
+    /// Reload every file if any path changed since the last load
+    ///
+    /// `load` is given the file and the version loaded before, if any.
+    pub fn reload_if_changed<F>(&mut self, kind: &str, mut load: F)
+    where
+        F: FnMut(&Path, Option<&T>) -> Result<T, String>,
+    {
+        let modified = self.paths.iter().filter_map(|path| latest_modification(path)).max();
+        if modified.is_some() && modified == self.loaded_at {
+            return;
+        }
+
+        let mut loaded = Vec::new();
+        for file in rule_files(&self.paths) {
+            let previous = self.loaded.iter().position(|(path, _)| path == &file);
+            match load(&file, previous.map(|index| &self.loaded[index].1)) {
+                Ok(item) => loaded.push((file, item)),
+                Err(e) => {
+                    warn!("✗ Failed to load {} {}: {}", kind, file.display(), e);
+                    if let Some(index) = previous {
+                        warn!("Keeping the previous version of {}", file.display());
+                        loaded.push(self.loaded.swap_remove(index));
+                    }
+                }
+            }
+        }
+
+        self.loaded = loaded;
+        self.loaded_at = modified;
+    }
+
+    /// Reload on the next check regardless of the file system's timestamp resolution
+    #[cfg(test)]
+    pub fn force_reload(&mut self) {
+        self.loaded_at = None;
+    }
+}
+
+/// Newest modification time of `path`, or of a directory and its entries
+pub fn latest_modification(path: &Path) -> Option<SystemTime> {
+    let own = fs::metadata(path).and_then(|m| m.modified()).ok();
+    if !path.is_dir() {
+        return own;
+    }
+    fs::read_dir(path).ok()?
+        .flatten()
+        .filter_map(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+        .chain(own)
+        .max()
+}
+
+/// Expand `paths` into files, directories contributing their `*.toml` / `*.yaml` files
+fn rule_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    let mut entries: Vec<PathBuf> = entries.flatten()
+                        .map(|entry| entry.path())
+                        .filter(|file| {
+                            file.extension().and_then(|e| e.to_str())
+                                .is_some_and(|ext| matches!(ext, "toml" | "yaml" | "yml"))
+                        })
+                        .collect();
+                    entries.sort();
+                    files.extend(entries);
+                }
+                Err(e) => warn!("✗ Failed to read directory {}: {}", path.display(), e),
+            }
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_keeps_last_good_version() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.toml"), "1").unwrap();
+        fs::write(dir.path().join("b.yaml"), "2").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let parse = |path: &Path, _: Option<&u32>| -> Result<u32, String> {
+            fs::read_to_string(path).unwrap().parse().map_err(|e| format!("{}", e))
+        };
+        let mut files = RuleFiles::new(&[dir.path().to_string_lossy().to_string()]);
+        files.reload_if_changed("test file", parse);
+        assert_eq!(files.iter().copied().collect::<Vec<_>>(), vec![1, 2], "Sorted, only rule file extensions");
+
+        fs::write(dir.path().join("a.toml"), "oops").unwrap();
+        fs::write(dir.path().join("b.yaml"), "3").unwrap();
+        files.force_reload();
+        files.reload_if_changed("test file", parse);
+        let mut loaded: Vec<u32> = files.iter().copied().collect();
+        loaded.sort();
+        assert_eq!(loaded, vec![1, 3], "A broken edit keeps the previous version");
+    }
+}
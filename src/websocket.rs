@@ -0,0 +1,399 @@
+use crate::config::{Config, WebSocketConfig};
+use crate::crypto;
+use crate::models::HeartbeatResponse;
+use crate::sender::Transport;
+use log::{info, debug, warn, error};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::HeaderValue;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+/// How long a blocking read waits before checking for outgoing messages
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// Heartbeat waiting to be sent, with the channel its ack is delivered on
+struct Outgoing {
+    payload: Value,
+    reply: Sender<HeartbeatResponse>,
+}
+
+/// Message received from the backend
+///
+/// Every message carries a `type`; the remaining fields share the
+/// heartbeat response format so acks, config pushes and command pushes
+/// are all handled the same way.
+#[derive(Deserialize)]
+struct Incoming {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(flatten)]
+    response: HeartbeatResponse,
+}
+
+/// Persistent WebSocket connection to the backend, maintained by a background thread
+///
+/// Heartbeats are streamed over the socket while it is up; callers fall back
+/// to HTTP whenever `send_heartbeat` fails. Config updates and commands the
+/// backend pushes are queued for `try_recv`.
+pub struct WebSocketLink {
+    outgoing: Sender<Outgoing>,
+    pushes: Receiver<HeartbeatResponse>,
+    connected: Arc<AtomicBool>,
+    timeout: Duration,
+}
+
+impl WebSocketLink {
+    /// Start the connection thread
+    pub fn start(config: &Config, device_fingerprint: String) -> Self {
+        let (outgoing_tx, outgoing_rx) = mpsc::channel();
+        let (push_tx, push_rx) = mpsc::channel();
+        let connected = Arc::new(AtomicBool::new(false));
+
+        let connection = Connection {
+            url: websocket_url(config),
+            agent_id: config.agent.agent_id.clone(),
+            device_fingerprint,
+            settings: config.websocket.clone(),
+            outgoing: outgoing_rx,
+            pushes: push_tx,
+            connected: connected.clone(),
+        };
+
+        thread::Builder::new()
+            .name("websocket".to_string())
+            .spawn(move || connection.run())
+            .expect("Failed to spawn WebSocket thread");
+
+        WebSocketLink {
+            outgoing: outgoing_tx,
+            pushes: push_rx,
+            connected,
+            timeout: Duration::from_secs(config.server.timeout_seconds),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Next config update or command batch pushed by the backend, if any
+    pub fn try_recv(&self) -> Option<HeartbeatResponse> {
+        match self.pushes.try_recv() {
+            Ok(push) => Some(push),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
+
+impl Transport for WebSocketLink {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    /// Send a heartbeat over the socket and wait for the backend's ack
+    fn send(&self, payload: &Value) -> Result<HeartbeatResponse, String> {
+        if !self.is_connected() {
+            return Err("WebSocket not connected".to_string());
+        }
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.outgoing
+            .send(Outgoing { payload: payload.clone(), reply: reply_tx })
+            .map_err(|_| "WebSocket thread has stopped".to_string())?;
+
+        match reply_rx.recv_timeout(self.timeout) {
+            Ok(response) => {
+                info!("✓ Data sent successfully over WebSocket");
+                Ok(response)
+            }
+            Err(RecvTimeoutError::Timeout) => Err("Timed out waiting for WebSocket ack".to_string()),
+            Err(RecvTimeoutError::Disconnected) => Err("WebSocket closed before ack".to_string()),
+        }
+    }
+}
+
+/// WebSocket endpoint, configured or derived from the heartbeat URL
+fn websocket_url(config: &Config) -> String {
+    if let Some(url) = &config.websocket.url {
+        return url.clone();
+    }
+
+    let url = config.server.url.replace("/api/heartbeat/", "/ws/agents/");
+    if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        url
+    }
+}
+
+struct Connection {
+    url: String,
+    agent_id: String,
+    device_fingerprint: String,
+    settings: WebSocketConfig,
+    outgoing: Receiver<Outgoing>,
+    pushes: Sender<HeartbeatResponse>,
+    connected: Arc<AtomicBool>,
+}
+
+impl Connection {
+    /// Connect, serve, and reconnect with exponential backoff until the link is dropped
+    fn run(self) {
+        let mut delay_ms = self.settings.reconnect_initial_ms;
+
+        loop {
+            match crypto::load_token().and_then(|token| self.connect(&token)) {
+                Ok(mut socket) => {
+                    info!("✓ WebSocket connected: {}", self.url);
+                    delay_ms = self.settings.reconnect_initial_ms;
+                    self.connected.store(true, Ordering::SeqCst);
+
+                    let result = self.serve(&mut socket);
+                    self.connected.store(false, Ordering::SeqCst);
+
+                    match result {
+                        Ok(()) => {
+                            debug!("WebSocket link dropped, stopping connection thread");
+                            let _ = socket.close(None);
+                            return;
+                        }
+                        Err(e) => warn!("WebSocket disconnected: {}", e),
+                    }
+                }
+                Err(e) => warn!("WebSocket connection failed: {}", e),
+            }
+
+            debug!("Reconnecting WebSocket in {} ms", delay_ms);
+            if self.wait_for_retry(Duration::from_millis(delay_ms)) {
+                return;
+            }
+            delay_ms = (delay_ms * 2).min(self.settings.reconnect_max_ms);
+        }
+    }
+
+    fn connect(&self, api_token: &str) -> Result<Socket, String> {
+        let mut request = self.url.as_str()
+            .into_client_request()
+            .map_err(|e| format!("Invalid WebSocket URL: {}", e))?;
+        let headers = request.headers_mut();
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_str(&format!("Bearer {}", api_token))
+                .map_err(|e| format!("Invalid token header: {}", e))?,
+        );
+        headers.insert(
+            "X-Device-Fingerprint",
+            HeaderValue::from_str(&self.device_fingerprint)
+                .map_err(|e| format!("Invalid fingerprint header: {}", e))?,
+        );
+
+        let (mut socket, _) = tungstenite::connect(request)
+            .map_err(|e| format!("Handshake failed: {}", e))?;
+
+        set_read_timeout(&socket, READ_POLL_INTERVAL)?;
+
+        let hello = json!({
+            "type": "hello",
+            "agent_id": self.agent_id,
+            "device_fingerprint": self.device_fingerprint,
+            "agent_version": env!("CARGO_PKG_VERSION"),
+        });
+        socket.send(Message::text(hello.to_string()))
+            .map_err(|e| format!("Failed to send hello: {}", e))?;
+
+        Ok(socket)
+    }
+
+    /// Pump messages both ways; Ok(()) means the link owner went away
+    fn serve(&self, socket: &mut Socket) -> Result<(), String> {
+        let mut next_id: u64 = 1;
+        let mut awaiting: HashMap<u64, Sender<HeartbeatResponse>> = HashMap::new();
+
+        loop {
+            loop {
+                match self.outgoing.try_recv() {
+                    Ok(outgoing) => {
+                        let id = next_id;
+                        next_id += 1;
+                        let message = json!({ "type": "heartbeat", "id": id, "payload": outgoing.payload });
+                        socket.send(Message::text(message.to_string()))
+                            .map_err(|e| format!("Failed to send heartbeat: {}", e))?;
+                        awaiting.insert(id, outgoing.reply);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            match socket.read() {
+                Ok(Message::Text(text)) => self.handle_message(&text, &mut awaiting),
+                Ok(Message::Close(_)) => return Err("Closed by backend".to_string()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    fn handle_message(&self, text: &str, awaiting: &mut HashMap<u64, Sender<HeartbeatResponse>>) {
+        let message: Incoming = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Ignoring malformed WebSocket message: {}", e);
+                return;
+            }
+        };
+
+        match message.kind.as_str() {
+            "heartbeat_ack" => {
+                let reply = message.id.and_then(|id| awaiting.remove(&id));
+                match reply {
+                    // The sender may have timed out already; nothing to do then
+                    Some(reply) => { let _ = reply.send(message.response); }
+                    None => debug!("Ack for unknown heartbeat {:?}", message.id),
+                }
+            }
+            "config" | "command" | "commands" => {
+                debug!("Received {} push over WebSocket", message.kind);
+                if self.pushes.send(message.response).is_err() {
+                    error!("Dropping WebSocket push: agent loop has stopped");
+                }
+            }
+            other => debug!("Ignoring WebSocket message type: {}", other),
+        }
+    }
+
+    /// Sleep before reconnecting; returns true if the link was dropped meanwhile
+    fn wait_for_retry(&self, delay: Duration) -> bool {
+        // Heartbeats queued while disconnected are answered by the HTTP fallback
+        loop {
+            match self.outgoing.recv_timeout(delay) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => return false,
+                Err(RecvTimeoutError::Disconnected) => return true,
+            }
+        }
+    }
+}
+
+fn set_read_timeout(socket: &Socket, timeout: Duration) -> Result<(), String> {
+    let stream = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream,
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref(),
+        _ => return Err("Unsupported WebSocket stream".to_string()),
+    };
+    stream.set_read_timeout(Some(timeout))
+        .map_err(|e| format!("Failed to set read timeout: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use tungstenite::handshake::server::{Request, Response};
+
+    #[test]
+    fn test_websocket_url_derived_from_heartbeat_url() {
+        let mut config = Config::default();
+        config.server.url = "https://backend.example.com/api/heartbeat/".to_string();
+        assert_eq!(websocket_url(&config), "wss://backend.example.com/ws/agents/");
+
+        config.websocket.url = Some("ws://10.0.0.1:9000/agents".to_string());
+        assert_eq!(websocket_url(&config), "ws://10.0.0.1:9000/agents");
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)] // accept_hdr's callback signature
+    fn test_heartbeat_ack_and_push_against_stub_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/ws/agents/", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut headers = Vec::new();
+            let mut socket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+                for name in ["Authorization", "X-Device-Fingerprint"] {
+                    headers.push(request.headers().get(name).map(|v| v.to_str().unwrap().to_string()));
+                }
+                Ok(response)
+            }).unwrap();
+
+            let read_json = |socket: &mut WebSocket<TcpStream>| -> Value {
+                let text = socket.read().unwrap().into_text().unwrap();
+                serde_json::from_str(&text).unwrap()
+            };
+
+            let hello = read_json(&mut socket);
+            assert_eq!(hello["type"], "hello");
+            assert_eq!(hello["agent_id"], "agent-test");
+
+            let heartbeat = read_json(&mut socket);
+            assert_eq!(heartbeat["type"], "heartbeat");
+            assert_eq!(heartbeat["payload"]["hostname"], "box");
+
+            let ack = json!({ "type": "heartbeat_ack", "id": heartbeat["id"], "status": "success", "snapshot_version": 3 });
+            socket.send(Message::text(ack.to_string())).unwrap();
+            let push = json!({ "type": "config", "collection_interval_seconds": 60 });
+            socket.send(Message::text(push.to_string())).unwrap();
+
+            // Keep the socket open until the agent side has read everything
+            let _ = socket.read();
+            headers
+        });
+
+        let (outgoing_tx, outgoing_rx) = mpsc::channel();
+        let (push_tx, push_rx) = mpsc::channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let connection = Connection {
+            url,
+            agent_id: "agent-test".to_string(),
+            device_fingerprint: "f".repeat(64),
+            settings: WebSocketConfig::default(),
+            outgoing: outgoing_rx,
+            pushes: push_tx,
+            connected: connected.clone(),
+        };
+
+        let mut socket = connection.connect("agt_test").unwrap();
+        connected.store(true, Ordering::SeqCst);
+        let serve = thread::spawn(move || connection.serve(&mut socket));
+
+        let link = WebSocketLink { outgoing: outgoing_tx, pushes: push_rx, connected, timeout: Duration::from_secs(5) };
+        let response = link.send(&json!({ "hostname": "box" })).unwrap();
+        assert_eq!(response.snapshot_version, Some(3));
+
+        let mut push = None;
+        for _ in 0..50 {
+            push = link.try_recv();
+            if push.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(push.unwrap().collection_interval_seconds, Some(60));
+
+        // Dropping the link ends the serve loop cleanly
+        drop(link);
+        assert!(serve.join().unwrap().is_ok());
+        let headers = server.join().unwrap();
+        assert_eq!(headers[0].as_deref(), Some("Bearer agt_test"));
+        assert_eq!(headers[1], Some("f".repeat(64)));
+    }
+}